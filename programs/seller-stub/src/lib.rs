@@ -0,0 +1,180 @@
+//! Stand-in for a multisig/DAO program that owns a PDA seller or wraps
+//! settlement. Used by the integration tests to exercise `confirm_seller` and
+//! `release` via CPI. Also doubles as the whitelisted swap program for
+//! swap-on-release.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
+use solana_escrow::cpi::accounts::{ConfirmSeller, Release as EscrowRelease};
+use solana_escrow::program::SolanaEscrow;
+use solana_escrow::Escrow;
+
+declare_id!("G2rPssUQL5bcF2F73FWrSv4w1BBwAq24K1BMWWiNxfTR");
+
+#[program]
+pub mod seller_stub {
+    use super::*;
+
+    /// Confirm an escrow on behalf of this program's seller PDA.
+    pub fn confirm(ctx: Context<Confirm>) -> Result<()> {
+        let signer_seeds: &[&[&[u8]]] = &[&[b"seller", &[ctx.bumps.seller]]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.escrow_program.to_account_info(),
+            ConfirmSeller {
+                seller: ctx.accounts.seller.to_account_info(),
+                escrow: ctx.accounts.escrow.to_account_info(),
+                instructions: ctx.accounts.instructions.to_account_info(),
+            },
+            signer_seeds,
+        );
+        solana_escrow::cpi::confirm_seller(cpi_ctx)
+    }
+
+    /// Release an escrow via CPI, forwarding the buyer's signature and nonce.
+    pub fn release(ctx: Context<Release>, nonce: u64) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.escrow_program.to_account_info(),
+            EscrowRelease {
+                buyer: ctx.accounts.buyer.to_account_info(),
+                settle_authority: None,
+                mint: ctx.accounts.mint.to_account_info(),
+                escrow: ctx.accounts.escrow.to_account_info(),
+                vault: ctx.accounts.vault.to_account_info(),
+                seller_token_account: ctx.accounts.seller_token_account.to_account_info(),
+                config: ctx.accounts.config.to_account_info(),
+                fee_token_account: None,
+                callback_program: None,
+                memo_program: None,
+                oracle: None,
+                buyer_token_account: None,
+                referrer_token_account: None,
+                seller: None,
+                payer: None,
+                associated_token_program: None,
+                system_program: None,
+                instructions: Some(ctx.accounts.instructions.to_account_info()),
+                unwrap_account: None,
+            relayer: None,
+                receipt: None,
+                mint_policy: ctx.accounts.mint_policy.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        );
+        solana_escrow::cpi::release(cpi_ctx, nonce)
+    }
+
+    /// Mock DEX: takes `amount_in` from the vault-signed source into the pool
+    /// and pays the same amount of the output mint from the pool's reserves.
+    /// Ignores `min_out`, so the escrow's own slippage check is what's tested.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, _min_out: u64) -> Result<()> {
+        let accounts = &ctx.accounts;
+        token::transfer_checked(
+            CpiContext::new(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.source.to_account_info(),
+                    mint: accounts.input_mint.to_account_info(),
+                    to: accounts.pool_in.to_account_info(),
+                    authority: accounts.source.to_account_info(),
+                },
+            ),
+            amount_in,
+            accounts.input_mint.decimals,
+        )?;
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"pool", &[ctx.bumps.pool]]];
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.pool_out.to_account_info(),
+                    mint: accounts.output_mint.to_account_info(),
+                    to: accounts.destination.to_account_info(),
+                    authority: accounts.pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount_in,
+            accounts.output_mint.decimals,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct Confirm<'info> {
+    /// CHECK: PDA seller controlled by this program. Seeds: ["seller"].
+    #[account(seeds = [b"seller"], bump)]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: Instructions sysvar, forwarded to the escrow program.
+    pub instructions: UncheckedAccount<'info>,
+
+    pub escrow_program: Program<'info, SolanaEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct Release<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Validated by the escrow program.
+    pub mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: Validated by the escrow program.
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the escrow program.
+    #[account(mut)]
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the escrow program.
+    pub config: UncheckedAccount<'info>,
+
+    /// CHECK: Instructions sysvar, forwarded to the escrow program.
+    pub instructions: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the escrow program.
+    #[account(mut)]
+    pub mint_policy: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the escrow program.
+    pub token_program: UncheckedAccount<'info>,
+
+    pub escrow_program: Program<'info, SolanaEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    /// Escrow vault, signed by the escrow program; its own authority.
+    #[account(mut)]
+    pub source: Signer<'info>,
+
+    pub input_mint: Account<'info, Mint>,
+
+    /// Pool's input-mint reserve.
+    #[account(mut, token::mint = input_mint, token::authority = pool)]
+    pub pool_in: Account<'info, TokenAccount>,
+
+    pub output_mint: Account<'info, Mint>,
+
+    /// Pool's output-mint reserve.
+    #[account(mut, token::mint = output_mint, token::authority = pool)]
+    pub pool_out: Account<'info, TokenAccount>,
+
+    /// Seller's output-mint token account.
+    #[account(mut, token::mint = output_mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA owning the pool's reserves. Seeds: ["pool"].
+    #[account(seeds = [b"pool"], bump)]
+    pub pool: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
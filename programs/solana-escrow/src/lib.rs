@@ -1,11 +1,125 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use anchor_lang::solana_program::instruction::{
+    get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::solana_program::program::{invoke, invoke_signed, MAX_RETURN_DATA};
+use anchor_lang::system_program;
+use anchor_lang::Bumps;
+use anchor_spl::associated_token::{
+    self, get_associated_token_address_with_program_id, AssociatedToken,
+};
+use anchor_spl::memo::{self, BuildMemo, Memo};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_interface::{
+    self, spl_token_2022, CloseAccount, InitializeAccount3, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+use solana_instructions_sysvar::{get_instruction_relative, load_instruction_at_checked};
+use solana_sha256_hasher::hash;
 
 declare_id!("HgCVVxrJ3sV6Z2a7B37jz98u9rSuquEcfNnTj26YvdM2");
 
-/// Maximum escrow duration: 90 days in seconds.
+/// Default maximum escrow duration for a new config: 90 days in seconds.
 const MAX_DEADLINE_SECS: i64 = 90 * 24 * 60 * 60;
 
+/// Highest maximum escrow duration `set_max_deadline` accepts: 10 years.
+pub const MAX_DEADLINE_LIMIT_SECS: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// Deadline passed to `initialize` for an open-ended escrow.
+pub const NO_DEADLINE: i64 = 0;
+
+/// Maximum number of milestones per escrow. Bounds account size and the
+/// cost of iterating milestones.
+pub const MAX_MILESTONES: usize = 10;
+
+/// Length of the rolling window `max_releases_per_day` counts over.
+pub const RELEASE_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Deadline extensions (`reopen` and `extend_by`) allowed per escrow when
+/// `InitOptions::max_extensions` is unset.
+pub const DEFAULT_MAX_EXTENSIONS: u8 = 3;
+
+/// Maximum number of dated installments in a `Scheduled` escrow.
+pub const MAX_SCHEDULED_PAYMENTS: usize = 8;
+
+/// Maximum size of an arbiter panel. Votes are tracked in `u8` bitmaps.
+pub const MAX_ARBITERS: usize = 5;
+
+/// Maximum number of release approvers. Approvals are tracked in a `u8` bitmap.
+pub const MAX_APPROVERS: usize = 5;
+
+/// Maximum number of extra assets in a basket escrow, on top of the primary
+/// mint. Bounds account size and the transfers made at settlement.
+pub const MAX_BASKET_ASSETS: usize = 4;
+
+/// Maximum number of third-party contributors per escrow.
+pub const MAX_CONTRIBUTORS: usize = 4;
+
+/// Maximum number of tags per escrow.
+pub const MAX_TAGS: usize = 4;
+
+/// Maximum number of recipients the protocol fee can be split between.
+pub const MAX_FEE_RECIPIENTS: usize = 4;
+
+/// Most escrows `close_many` handles in one call, to stay within the
+/// compute budget.
+pub const MAX_CLOSE_BATCH: usize = 10;
+
+/// Most escrows in one escrow group, so `release_group` fits in a
+/// transaction and the compute budget.
+pub const MAX_GROUP_SIZE: u8 = 4;
+
+/// Accounts per escrow passed to `release_group`, before its fee split accounts.
+pub const GROUP_LEG_ACCOUNTS: usize = 7;
+
+/// How far a `rug_guard` escrow's mint supply may grow past its supply at
+/// `initialize` before releases are blocked, in basis points.
+pub const RUG_GUARD_SUPPLY_BPS: u64 = 1_000;
+
+/// Longest label `get_status_label` returns, in bytes.
+pub const MAX_STATUS_LABEL_LEN: usize = 64;
+
+/// Anchor discriminator of the callback instruction invoked on settlement:
+/// `sha256("global:on_escrow_settled")[..8]`. Callback programs implement
+/// `on_escrow_settled(escrow: Pubkey, outcome: SettlementOutcome)`.
+const CALLBACK_DISCRIMINATOR: [u8; 8] = [172, 205, 68, 121, 144, 255, 247, 16];
+
+/// Anchor discriminator of the swap instruction invoked by swap-on-release:
+/// `sha256("global:swap")[..8]`. Whitelisted swap programs implement
+/// `swap(amount_in: u64, min_out: u64)`, signed by the vault.
+const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+/// `msg!` for escrows created with `verbose`. Formatting a log line costs
+/// compute on every call; quiet escrows rely on the emitted events instead.
+macro_rules! log {
+    ($escrow:expr, $($arg:tt)+) => {
+        if $escrow.verbose {
+            msg!($($arg)+);
+        }
+    };
+}
+
+/// Pyth pull-oracle receiver program, owner of `PriceUpdateV2` accounts.
+pub const PYTH_RECEIVER_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Anchor discriminator of Pyth's `PriceUpdateV2`:
+/// `sha256("account:PriceUpdateV2")[..8]`.
+const PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// Oracle prices older than this are rejected at release.
+pub const MAX_ORACLE_AGE_SECS: i64 = 60;
+
+/// Decimals of `Escrow::reference_value`, e.g. 100_000_000 = $100.
+pub const REFERENCE_DECIMALS: u32 = 6;
+
+/// Compute budget program. Its instructions only set limits and priority
+/// fees, so `strict_tx` escrows allow them next to a release.
+pub const COMPUTE_BUDGET_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
+
+/// Ed25519 signature verification precompile, checked by
+/// `release_with_signature`.
+pub const ED25519_PROGRAM_ID: Pubkey = pubkey!("Ed25519SigVerify111111111111111111111111111");
+
 #[program]
 pub mod solana_escrow {
     use super::*;
@@ -14,31 +128,368 @@ pub mod solana_escrow {
     /// The seller can receive funds only when the buyer calls `release`.
     /// The buyer can cancel (reclaim funds) at any time before release.
     /// If the deadline passes without release, the escrow is still cancellable.
+    /// A `deadline` of `NO_DEADLINE` creates an escrow that never expires.
     pub fn initialize(
         ctx: Context<Initialize>,
         amount: u64,
         deadline: i64,
+        options: InitOptions,
     ) -> Result<()> {
         require!(amount > 0, EscrowError::ZeroAmount);
+        validate_kind(options.kind, &ctx.accounts.mint, amount)?;
+        validate_milestones(options.kind, &options.milestones, amount)?;
+        let arbiter = options.arbiter.unwrap_or_default();
+        require!(
+            arbiter != ctx.accounts.buyer.key() && arbiter != ctx.accounts.seller.key(),
+            EscrowError::InvalidArbiter
+        );
+        validate_arbiters(
+            &options.arbiters,
+            arbiter,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.seller.key(),
+        )?;
+        validate_approvers(
+            &options.approvers,
+            &options.approver_weights,
+            options.approval_threshold,
+        )?;
+        require!(options.tags.len() <= MAX_TAGS, EscrowError::TooManyTags);
+
+        let oracle = options.oracle.unwrap_or_default();
+        if oracle != Pubkey::default() {
+            require!(options.reference_value > 0, EscrowError::ZeroAmount);
+            require!(
+                matches!(options.kind, EscrowKind::Standard | EscrowKind::Native),
+                EscrowError::WrongEscrowKind
+            );
+        }
+
+        let referrer = options.referrer.unwrap_or_default();
+        if referrer != Pubkey::default() {
+            require!(
+                matches!(
+                    options.kind,
+                    EscrowKind::Standard | EscrowKind::Nft | EscrowKind::Native
+                ),
+                EscrowError::WrongEscrowKind
+            );
+        }
+        let target_mint = options.target_mint.unwrap_or_default();
+        require!(
+            (options.kind == EscrowKind::Swap) == (target_mint != Pubkey::default()),
+            EscrowError::WrongEscrowKind
+        );
+        if target_mint != Pubkey::default() {
+            require!(
+                oracle == Pubkey::default()
+                    && referrer == Pubkey::default()
+                    && target_mint != ctx.accounts.mint.key(),
+                EscrowError::WrongEscrowKind
+            );
+            require!(options.min_out > 0, EscrowError::ZeroAmount);
+        }
+        if options.group_id != [0; 32] {
+            require!(
+                matches!(
+                    options.kind,
+                    EscrowKind::Standard | EscrowKind::Nft | EscrowKind::Native
+                ) && oracle == Pubkey::default()
+                    && referrer == Pubkey::default()
+                    && target_mint == Pubkey::default()
+                    && options.authorized_caller.is_none()
+                    && options.callback_program.is_none()
+                    && options.relayer.is_none()
+                    && !options.strict_tx
+                    && !options.create_receipt
+                    && !options.create_seller_ata,
+                EscrowError::WrongEscrowKind
+            );
+            require!(
+                (2..=MAX_GROUP_SIZE).contains(&options.group_size),
+                EscrowError::GroupMismatch
+            );
+        }
+        if options.oracle_pubkey.is_some() {
+            require!(
+                matches!(
+                    options.kind,
+                    EscrowKind::Standard | EscrowKind::Nft | EscrowKind::Native | EscrowKind::Swap
+                ) && options.group_id == [0; 32]
+                    && !options.strict_tx,
+                EscrowError::WrongEscrowKind
+            );
+        }
+        if options.unwrap_on_release {
+            require!(
+                is_native_mint(&ctx.accounts.mint.key()),
+                EscrowError::NotNativeMint
+            );
+            require!(
+                matches!(options.kind, EscrowKind::Standard | EscrowKind::Native)
+                    && target_mint == Pubkey::default()
+                    && !options.create_seller_ata
+                    && options.group_id == [0; 32],
+                EscrowError::WrongEscrowKind
+            );
+        }
+        if options.create_receipt {
+            require!(
+                matches!(
+                    options.kind,
+                    EscrowKind::Standard | EscrowKind::Nft | EscrowKind::Native | EscrowKind::Swap
+                ),
+                EscrowError::WrongEscrowKind
+            );
+        }
+        require!(
+            (referrer != Pubkey::default() || options.referrer_bps == 0)
+                && u128::from(options.referrer_bps) + u128::from(ctx.accounts.config.fee_bps)
+                    <= BPS_DENOMINATOR,
+            EscrowError::InvalidFee
+        );
 
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
-        require!(deadline > now, EscrowError::DeadlineInPast);
+        let max_deadline_secs = i64::from(ctx.accounts.config.max_deadline_secs);
         require!(
-            deadline <= now + MAX_DEADLINE_SECS,
+            i64::from(options.dispute_buffer_secs) <= max_deadline_secs,
             EscrowError::DeadlineTooFar
         );
 
+        require!(
+            (arbiter != Pubkey::default() || options.arbiter_fee_bps == 0)
+                && u128::from(options.arbiter_fee_bps) + u128::from(ctx.accounts.config.fee_bps)
+                    <= BPS_DENOMINATOR,
+            EscrowError::InvalidFee
+        );
+
+        let expire_buyer_bps = options.expire_buyer_bps.unwrap_or(BPS_DENOMINATOR as u16);
+        require!(
+            u128::from(expire_buyer_bps) <= BPS_DENOMINATOR,
+            EscrowError::InvalidSplit
+        );
+
+        let now = current_time(&ctx)?;
+        let no_deadline = deadline == NO_DEADLINE;
+        if !no_deadline {
+            validate_deadline(deadline, now, max_deadline_secs)?;
+            require!(
+                deadline >= now + i64::from(ctx.accounts.config.min_deadline_secs),
+                EscrowError::DeadlineTooSoon
+            );
+        }
+        validate_schedule(options.kind, &options.schedule, amount, deadline)?;
+
+        if options.require_seller_ata {
+            require_seller_ata(
+                ctx.accounts.seller_token_account.as_ref(),
+                &ctx.accounts.seller.key(),
+                &ctx.accounts.mint.key(),
+                &ctx.accounts.token_program.key(),
+            )?;
+        }
+        if options.strict_buyer_ata {
+            require_buyer_ata(
+                &ctx.accounts.buyer_token_account.key(),
+                &ctx.accounts.buyer.key(),
+                &ctx.accounts.mint.key(),
+                &ctx.accounts.token_program.key(),
+            )?;
+        }
+        if options.require_system_seller {
+            require_keys_eq!(
+                *ctx.accounts.seller.owner,
+                system_program::ID,
+                EscrowError::InvalidSellerAccount
+            );
+        }
+
+        let mint_policy = &mut ctx.accounts.mint_policy;
+        mint_policy.mint = ctx.accounts.mint.key();
+        mint_policy.bump = ctx.bumps.mint_policy;
+        mint_policy.lock(amount)?;
+
+        if ctx.accounts.config.permissioned {
+            let approved = ctx
+                .accounts
+                .approved_buyer
+                .as_ref()
+                .ok_or(EscrowError::BuyerNotApproved)?;
+            require!(approved.active, EscrowError::BuyerNotApproved);
+        }
+        if ctx.accounts.config.permissioned_sellers {
+            let approved = ctx
+                .accounts
+                .approved_seller
+                .as_ref()
+                .ok_or(EscrowError::SellerNotApproved)?;
+            require!(approved.active, EscrowError::SellerNotApproved);
+        }
+
+        // Flat SOL fee: buyer → treasury
+        let lamport_fee = ctx.accounts.config.lamport_fee;
+        if lamport_fee > 0 {
+            let treasury = ctx
+                .accounts
+                .treasury
+                .as_ref()
+                .ok_or(EscrowError::MissingTreasury)?;
+            require!(
+                ctx.accounts.buyer.lamports() >= lamport_fee,
+                EscrowError::InsufficientLamports
+            );
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: treasury.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.system_program.to_account_info();
+            system_program::transfer(CpiContext::new(cpi_program, cpi_accounts), lamport_fee)?;
+        }
+
+        // Relayer rebate: buyer → escrow PDA, held apart from the vault
+        let relayer = options.relayer.unwrap_or_default();
+        let relayer_rebate = if relayer == Pubkey::default() {
+            0
+        } else {
+            options.relayer_rebate
+        };
+        if relayer_rebate > 0 {
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.escrow.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.system_program.to_account_info();
+            system_program::transfer(CpiContext::new(cpi_program, cpi_accounts), relayer_rebate)?;
+        }
+
         // Populate escrow state
         let escrow = &mut ctx.accounts.escrow;
         escrow.buyer = ctx.accounts.buyer.key();
         escrow.seller = ctx.accounts.seller.key();
         escrow.mint = ctx.accounts.mint.key();
         escrow.amount = amount;
+        escrow.deposit = amount;
+        escrow.original_amount = amount;
+        escrow.original_deadline = deadline;
+        escrow.original_seller = ctx.accounts.seller.key();
+        escrow.fee_waived = amount < ctx.accounts.config.fee_waiver_threshold;
         escrow.deadline = deadline;
+        escrow.no_deadline = no_deadline;
         escrow.bump = ctx.bumps.escrow;
         escrow.vault_bump = ctx.bumps.vault;
-        escrow.state = EscrowState::Locked;
+        escrow.state = if options.require_seller_confirm {
+            EscrowState::PendingSellerConfirm
+        } else {
+            EscrowState::Locked
+        };
+        escrow.pending_seller = if options.require_seller_confirm {
+            ctx.accounts.seller.key()
+        } else {
+            Pubkey::default()
+        };
+        escrow.callback_program = options.callback_program.unwrap_or_default();
+        escrow.callback_strict = options.callback_strict;
+        escrow.notice_secs = options.notice_secs;
+        escrow.expire_buyer_bps = expire_buyer_bps;
+        escrow.seller_program = options.seller_program.unwrap_or_default();
+        escrow.max_releases = options.max_releases;
+        escrow.release_count = 0;
+        escrow.max_releases_per_day = options.max_releases_per_day;
+        escrow.releases_today = 0;
+        escrow.releases_window_start = 0;
+        escrow.oracle = oracle;
+        escrow.reference_value = if oracle == Pubkey::default() {
+            0
+        } else {
+            options.reference_value
+        };
+        escrow.authorized_caller = options.authorized_caller.unwrap_or_default();
+        escrow.terms_hash = options.terms_hash;
+        escrow.hash_lock = options.hash_lock;
+        escrow.oracle_pubkey = options.oracle_pubkey.unwrap_or_default();
+        escrow.tags = options.tags;
+        escrow.fee_collected = 0;
+        escrow.last_snapshot_amount = 0;
+        escrow.last_snapshot_at = 0;
+        escrow.assets = Vec::new();
+        escrow.contributors = Vec::new();
+        escrow.settle_authority = options
+            .settle_authority
+            .unwrap_or(ctx.accounts.buyer.key());
+        escrow.auto_close = options.auto_close;
+        escrow.event_seq = 0;
+        escrow.max_extensions = options.max_extensions.unwrap_or(DEFAULT_MAX_EXTENSIONS);
+        escrow.extension_count = 0;
+        escrow.referrer = referrer;
+        escrow.referrer_bps = options.referrer_bps;
+        escrow.strict_tx = options.strict_tx;
+        escrow.approval_threshold = if options.approvers.is_empty() {
+            0
+        } else {
+            options.approval_threshold
+        };
+        escrow.approvers = options.approvers;
+        escrow.approver_weights = options.approver_weights;
+        escrow.approvals = 0;
+        escrow.approval_window_secs = options.approval_window_secs;
+        escrow.first_confirm_at = 0;
+        escrow.vault_closed = false;
+        escrow.frozen = false;
+        escrow.create_seller_ata = options.create_seller_ata;
+        escrow.create_receipt = options.create_receipt;
+        escrow.require_delivery = options.require_delivery;
+        escrow.lock_on_delivery = options.lock_on_delivery;
+        escrow.pooled = options.pooled;
+        escrow.target_mint = target_mint;
+        escrow.verbose = options.verbose;
+        escrow.dispute_buffer_secs = options.dispute_buffer_secs;
+        escrow.collateral_amount = 0;
+        escrow.collateral_forfeited = false;
+        escrow.strict_buyer_ata = options.strict_buyer_ata;
+        escrow.rug_guard = options.rug_guard;
+        escrow.arbiter_fee_bps = options.arbiter_fee_bps;
+        escrow.unwrap_on_release = options.unwrap_on_release;
+        escrow.min_release_amount = options.min_release_amount;
+        escrow.relayer = relayer;
+        escrow.relayer_rebate = relayer_rebate;
+        escrow.token_program = ctx.accounts.token_program.key();
+        escrow.mint_authority = ctx.accounts.mint.mint_authority.unwrap_or_default();
+        escrow.mint_supply = ctx.accounts.mint.supply;
+        (escrow.group_id, escrow.group_size) = if options.group_id == [0; 32] {
+            ([0; 32], 0)
+        } else {
+            (options.group_id, options.group_size)
+        };
+        escrow.min_out = options.min_out;
+        escrow.delivery_hash = [0; 32];
+        escrow.cancel_requested_at = 0;
+        escrow.objected = false;
+        escrow.last_action_nonce = 0;
+        escrow.min_action_gap_slots = options.min_action_gap_slots;
+        escrow.last_action_slot = 0;
+        escrow.escrow_type = options.kind;
+        escrow.arbiter = arbiter;
+        escrow.arbiters = options.arbiters;
+        escrow.votes_cast = 0;
+        escrow.votes_to_seller = 0;
+        escrow.dispute_open = false;
+        escrow.decimals = ctx.accounts.mint.decimals;
+        escrow.milestones = options
+            .milestones
+            .iter()
+            .map(|&amount| Milestone {
+                amount,
+                released: false,
+            })
+            .collect();
+        escrow.schedule = options
+            .schedule
+            .iter()
+            .map(|installment| ScheduledPayment {
+                unlock_at: installment.unlock_at,
+                amount: installment.amount,
+                paid: false,
+            })
+            .collect();
 
         // Transfer tokens from buyer's ATA → vault
         let decimals = ctx.accounts.mint.decimals;
@@ -52,6 +503,21 @@ pub mod solana_escrow {
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
 
+        require_rent_exempt(&ctx.accounts.escrow.to_account_info())?;
+        require_rent_exempt(&ctx.accounts.vault.to_account_info())?;
+
+        let seq = ctx.accounts.escrow.next_event_seq();
+        emit!(EscrowInitialized {
+            escrow: ctx.accounts.escrow.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: ctx.accounts.seller.key(),
+            mint: ctx.accounts.mint.key(),
+            amount,
+            deadline,
+            terms_hash: ctx.accounts.escrow.terms_hash,
+            seq,
+        });
+
         msg!(
             "Escrow initialized: {} tokens locked until {}",
             amount,
@@ -60,250 +526,6202 @@ pub mod solana_escrow {
         Ok(())
     }
 
-    /// Release: buyer approves delivery and funds are sent to the seller.
-    pub fn release(ctx: Context<Release>) -> Result<()> {
-        let escrow = &ctx.accounts.escrow;
-        require!(escrow.state == EscrowState::Locked, EscrowError::NotLocked);
+    /// Initialize with duration: like `initialize`, but the deadline is
+    /// `duration_secs` after the current on-chain time, sparing clients the
+    /// clock-skew guesswork of an absolute timestamp. Fails with
+    /// `DurationTooLong` beyond the config's `max_deadline_secs`.
+    pub fn initialize_with_duration(
+        ctx: Context<Initialize>,
+        amount: u64,
+        duration_secs: u64,
+        options: InitOptions,
+    ) -> Result<()> {
+        let duration = i64::try_from(duration_secs)
+            .ok()
+            .filter(|&secs| secs <= i64::from(ctx.accounts.config.max_deadline_secs))
+            .ok_or(EscrowError::DurationTooLong)?;
+        let deadline = current_time(&ctx)?
+            .checked_add(duration)
+            .ok_or(EscrowError::MathOverflow)?;
+        initialize(ctx, amount, deadline, options)
+    }
 
-        let amount = escrow.amount;
-        let decimals = ctx.accounts.mint.decimals;
-        let escrow_key = escrow.key();
+    /// Create template: store escrow parameters an operator reuses across
+    /// many escrows in an `EscrowTemplate` PDA owned by the signer. They are
+    /// validated like `initialize` options when an escrow is created from
+    /// the template.
+    pub fn create_template(
+        ctx: Context<CreateTemplate>,
+        template_id: u64,
+        params: TemplateParams,
+    ) -> Result<()> {
+        let template = &mut ctx.accounts.template;
+        template.authority = ctx.accounts.authority.key();
+        template.template_id = template_id;
+        template.params = params;
+        template.bump = ctx.bumps.template;
 
-        // PDA signer seeds for the vault
-        let seeds = &[
-            b"vault".as_ref(),
-            escrow_key.as_ref(),
-            &[escrow.vault_bump],
-        ];
-        let signer = &[&seeds[..]];
+        msg!("Template {} created", template_id);
+        Ok(())
+    }
+
+    /// Initialize from template: `initialize` with the options and duration
+    /// of the `template` account, so only the amount is passed. A template
+    /// without a duration creates an open-ended escrow. Fails with
+    /// `TemplateNotFound` without the template account.
+    pub fn initialize_from_template(ctx: Context<Initialize>, amount: u64) -> Result<()> {
+        let template = ctx
+            .accounts
+            .template
+            .as_ref()
+            .ok_or(EscrowError::TemplateNotFound)?;
+        let options = template.params.to_options();
+        let duration_secs = u64::from(template.params.deadline_secs);
+        if duration_secs == 0 {
+            return initialize(ctx, amount, NO_DEADLINE, options);
+        }
+        initialize_with_duration(ctx, amount, duration_secs, options)
+    }
+
+    /// Add asset: the buyer deposits `amount` of another mint into its own
+    /// vault, turning a `Standard` or `Swap` escrow into a basket. Basket assets are
+    /// paid out in full with the primary deposit, to the seller on release and
+    /// to the buyer on refund, with their accounts passed in
+    /// `remaining_accounts` (see `settle_basket`). No protocol fee is taken on
+    /// them. `vault_index` numbers the asset's vault and must be the next free
+    /// index, starting at 1 (the primary vault is index 0).
+    pub fn add_asset(ctx: Context<AddAsset>, amount: u64, vault_index: u8) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        match escrow.state {
+            EscrowState::Locked | EscrowState::PendingSellerConfirm => {}
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        escrow.require_kind(&[EscrowKind::Standard, EscrowKind::Swap])?;
+        // Baskets settle all-or-nothing: no oracle quote or split on expiry.
+        // Auto-close would strand the asset vaults' rent.
+        // Contributors are refunded from the primary vault only.
+        require!(
+            escrow.oracle == Pubkey::default()
+                && u128::from(escrow.expire_buyer_bps) == BPS_DENOMINATOR
+                && !escrow.auto_close
+                && escrow.contributors.is_empty(),
+            EscrowError::WrongEscrowKind
+        );
+        require!(amount > 0, EscrowError::ZeroAmount);
+        require!(
+            escrow.assets.len() < MAX_BASKET_ASSETS,
+            EscrowError::TooManyAssets
+        );
+        require_eq!(
+            usize::from(vault_index),
+            escrow.assets.len() + 1,
+            EscrowError::InvalidVaultIndex
+        );
 
-        // Transfer tokens from vault → seller's ATA
+        // Transfer tokens from buyer's account → asset vault
+        let decimals = ctx.accounts.mint.decimals;
         let cpi_accounts = TransferChecked {
-            from: ctx.accounts.vault.to_account_info(),
+            from: ctx.accounts.buyer_token_account.to_account_info(),
             mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.seller_token_account.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.asset_vault.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+        require_rent_exempt(&ctx.accounts.asset_vault.to_account_info())?;
 
-        // Update state
         let escrow = &mut ctx.accounts.escrow;
-        escrow.state = EscrowState::Released;
+        escrow.assets.push(VaultEntry {
+            mint: ctx.accounts.mint.key(),
+            vault_index,
+            vault_bump: ctx.bumps.asset_vault,
+            amount,
+        });
 
-        msg!("Escrow released: {} tokens sent to seller", amount);
+        msg!(
+            "Basket asset {} added: {} tokens of {}",
+            escrow.assets.len(),
+            amount,
+            ctx.accounts.mint.key()
+        );
         Ok(())
     }
 
-    /// Cancel: buyer reclaims funds. The buyer can cancel at any time
-    /// while the escrow is still locked (before release).
-    pub fn cancel(ctx: Context<Cancel>) -> Result<()> {
+    /// Deposit: the buyer tops up a `Standard` or `Swap` escrow by `amount`. Escrows
+    /// created with `pooled` also take deposits from third parties; on cancel,
+    /// the refund is split between the buyer and the contributors in
+    /// proportion to what each put in.
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
         let escrow = &ctx.accounts.escrow;
-        require!(escrow.state == EscrowState::Locked, EscrowError::NotLocked);
-
-        let amount = escrow.amount;
-        let decimals = ctx.accounts.mint.decimals;
-        let escrow_key = escrow.key();
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        escrow.require_kind(&[EscrowKind::Standard, EscrowKind::Swap])?;
+        // An oracle quote would refund the surplus to the buyer alone
+        require!(
+            escrow.oracle == Pubkey::default() && escrow.assets.is_empty(),
+            EscrowError::WrongEscrowKind
+        );
+        require!(amount > 0, EscrowError::ZeroAmount);
 
-        // PDA signer seeds for the vault
-        let seeds = &[
-            b"vault".as_ref(),
-            escrow_key.as_ref(),
-            &[escrow.vault_bump],
-        ];
-        let signer = &[&seeds[..]];
+        let contributor = ctx.accounts.contributor.key();
+        let is_buyer = contributor == escrow.buyer;
+        require!(is_buyer || escrow.pooled, EscrowError::UnauthorizedBuyer);
+        let index = escrow.contributors.iter().position(|c| c.account == contributor);
+        require!(
+            is_buyer || index.is_some() || escrow.contributors.len() < MAX_CONTRIBUTORS,
+            EscrowError::TooManyContributors
+        );
 
-        // Transfer tokens from vault → buyer's ATA
+        // Transfer tokens from contributor → vault
         let cpi_accounts = TransferChecked {
-            from: ctx.accounts.vault.to_account_info(),
+            from: ctx.accounts.contributor_token_account.to_account_info(),
             mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.buyer_token_account.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.contributor.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+        ctx.accounts.mint_policy.lock(amount)?;
 
-        // Update state
         let escrow = &mut ctx.accounts.escrow;
-        escrow.state = EscrowState::Cancelled;
+        escrow.amount = escrow.amount.checked_add(amount).ok_or(EscrowError::MathOverflow)?;
+        escrow.deposit = escrow.deposit.checked_add(amount).ok_or(EscrowError::MathOverflow)?;
+        escrow.fee_waived &= escrow.deposit < ctx.accounts.config.fee_waiver_threshold;
+        // The buyer's own top-ups stay in the buyer's share
+        match index {
+            _ if is_buyer => {}
+            Some(i) => {
+                let entry = &mut escrow.contributors[i];
+                entry.amount = entry.amount.checked_add(amount).ok_or(EscrowError::MathOverflow)?;
+            }
+            None => escrow.contributors.push(Contributor {
+                account: contributor,
+                amount,
+            }),
+        }
+
+        let seq = escrow.next_event_seq();
+        emit!(EscrowDeposited {
+            escrow: escrow.key(),
+            contributor,
+            amount,
+            seq,
+        });
 
-        msg!("Escrow cancelled: {} tokens returned to buyer", amount);
+        log!(escrow, "Contributor {} deposited {} tokens", contributor, amount);
         Ok(())
     }
-}
 
-// ---------------------------------------------------------------------------
-// State
-// ---------------------------------------------------------------------------
+    /// Release: buyer approves delivery and funds are sent to the seller,
+    /// minus the protocol fee configured in `Config`. An escrow priced in a
+    /// reference asset pays out the oracle-quoted token amount instead
+    /// (capped by the deposit) and refunds the rest of the vault to the buyer.
+    /// `nonce` must exceed the escrow's `last_action_nonce` (`StaleNonce`), so
+    /// a retried transaction can't act twice.
+    pub fn release<'info>(ctx: Context<'_, '_, '_, 'info, Release<'info>>, nonce: u64) -> Result<()> {
+        ctx.accounts.escrow.use_nonce(nonce)?;
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_settler(escrow, &ctx.accounts.buyer, ctx.accounts.settle_authority.as_ref())?;
+        escrow.require_approved(current_time(&ctx)?)?;
+        escrow.require_delivered()?;
+        escrow.require_mint_unchanged(&ctx.accounts.mint)?;
+        // Unwrapped payouts go to the seller's wallet, not a token account
+        if !escrow.unwrap_on_release {
+            prepare_seller_token_account(ctx.accounts)?;
+        }
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require_strict_tx(escrow, ctx.accounts.instructions.as_ref())?;
+        require_oracle_signature(escrow, ctx.accounts.instructions.as_ref())?;
+        escrow.require_kind(&[
+            EscrowKind::Standard,
+            EscrowKind::Nft,
+            EscrowKind::Native,
+            EscrowKind::Swap,
+        ])?;
+        require!(!escrow.is_grouped(), EscrowError::GroupNotReady);
+        require_vault_authority(&ctx.accounts.vault)?;
+        require_distinct_destinations(ctx.accounts)?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct Escrow {
-    /// The buyer who deposited tokens.
-    pub buyer: Pubkey,
-    /// The seller who will receive tokens on release.
-    pub seller: Pubkey,
-    /// SPL token mint.
-    pub mint: Pubkey,
-    /// Amount of tokens locked.
-    pub amount: u64,
-    /// Unix timestamp after which the buyer can cancel.
-    pub deadline: i64,
-    /// PDA bump for the escrow account.
-    pub bump: u8,
-    /// PDA bump for the vault token account.
-    pub vault_bump: u8,
-    /// Current escrow state.
-    pub state: EscrowState,
-}
+        // Nothing left to pay (e.g. drained by partial releases): close out
+        // the escrow without a zero-amount transfer.
+        if escrow.amount == 0 && escrow.assets.is_empty() {
+            let escrow = &mut ctx.accounts.escrow;
+            escrow.state = EscrowState::Released;
+            ctx.accounts.mint_policy.unlock(escrow.deposit);
+            emit_settled(escrow, SettlementOutcome::Released, 0);
+            pay_relayer_rebate(escrow, ctx.accounts.relayer.as_ref())?;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
-pub enum EscrowState {
-    Locked,
-    Released,
-    Cancelled,
-}
+            log!(escrow, "Escrow released: nothing left to pay");
+            return Ok(());
+        }
 
-// ---------------------------------------------------------------------------
-// Accounts
-// ---------------------------------------------------------------------------
+        let amount = escrow.amount;
+        let due = if escrow.oracle == Pubkey::default() {
+            amount
+        } else {
+            let oracle = ctx
+                .accounts
+                .oracle
+                .as_ref()
+                .ok_or(EscrowError::OraclePriceError)?;
+            let (price, exponent) = read_oracle_price(oracle, current_time(&ctx)?)?;
+            quote_token_amount(escrow.reference_value, price, exponent, escrow.decimals)?
+                .min(amount)
+        };
+        let has_refund = due < amount;
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    /// Buyer creating the escrow; pays for account creation and deposits tokens.
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+        // The referrer's cut comes off the top; the fee is charged on the rest
+        let referral = u64::try_from(
+            u128::from(due) * u128::from(escrow.referrer_bps) / BPS_DENOMINATOR,
+        )
+        .map_err(|_| EscrowError::MathOverflow)?;
+        if referral > 0 {
+            let referrer_token_account = ctx
+                .accounts
+                .referrer_token_account
+                .as_ref()
+                .ok_or(EscrowError::MissingReferrerAccount)?;
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                referrer_token_account.to_account_info(),
+                referral,
+            )?;
+            ctx.accounts.vault.reload()?;
+        }
 
-    /// Seller's public key (does not need to sign).
-    /// CHECK: We only store the seller's pubkey; no data is read from this account.
-    pub seller: UncheckedAccount<'info>,
+        // Transfer the fee, then pay the seller. Unless part of the deposit
+        // goes back to the buyer, the seller sweeps the rest of the vault.
+        let (fee, seller_amount) = if escrow.target_mint == Pubkey::default() {
+            let to = if escrow.unwrap_on_release {
+                open_unwrap_account(ctx.accounts, ctx.bumps.unwrap_account)?
+            } else {
+                ctx.accounts.seller_token_account.to_account_info()
+            };
+            pay_with_fee(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                &ctx.accounts.config,
+                ctx.accounts.fee_token_account.as_ref(),
+                fee_split_accounts(escrow, ctx.remaining_accounts),
+                to,
+                due - referral,
+                !has_refund,
+            )?
+        } else {
+            swap_with_fee(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                &ctx.accounts.config,
+                ctx.accounts.fee_token_account.as_ref(),
+                fee_split_accounts(escrow, ctx.remaining_accounts),
+                due,
+            )?
+        };
+        if escrow.unwrap_on_release {
+            unwrap_to_seller(ctx.accounts, ctx.bumps.unwrap_account, seller_amount)?;
+        }
 
-    /// SPL token mint for the escrowed asset.
-    pub mint: InterfaceAccount<'info, Mint>,
+        // Refund whatever the oracle quote didn't cover
+        let mut refund = 0;
+        if has_refund {
+            let buyer_token_account = ctx
+                .accounts
+                .buyer_token_account
+                .as_ref()
+                .ok_or(EscrowError::MissingBuyerTokenAccount)?;
+            refund = ctx
+                .accounts
+                .vault
+                .amount
+                .checked_sub(fee + seller_amount)
+                .ok_or(EscrowError::MathOverflow)?;
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                buyer_token_account.to_account_info(),
+                refund,
+            )?;
+        }
+        settle_basket(
+            &ctx.accounts.token_program,
+            escrow,
+            ctx.remaining_accounts,
+            escrow.seller,
+        )?;
+        if escrow.create_receipt {
+            let now = current_time(&ctx)?;
+            write_receipt(ctx.accounts, ctx.bumps.receipt, due, now)?;
+        }
 
-    /// Buyer's token account (source of deposited tokens).
-    #[account(
-        mut,
-        token::mint = mint,
-        token::authority = buyer,
-    )]
-    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.state = EscrowState::Released;
+        ctx.accounts.mint_policy.unlock(escrow.deposit);
 
-    /// Escrow state PDA. Seeds: ["escrow", buyer, seller, mint].
-    #[account(
-        init,
-        payer = buyer,
-        space = 8 + Escrow::INIT_SPACE,
-        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), mint.key().as_ref()],
-        bump,
-    )]
-    pub escrow: Account<'info, Escrow>,
+        write_memo(
+            &ctx.accounts.config,
+            ctx.accounts.memo_program.as_ref(),
+            escrow,
+            SettlementOutcome::Released,
+        )?;
+        notify_callback(
+            escrow,
+            ctx.accounts.callback_program.as_ref(),
+            SettlementOutcome::Released,
+        )?;
+        emit_settled(escrow, SettlementOutcome::Released, referral + fee + seller_amount);
+        pay_relayer_rebate(escrow, ctx.accounts.relayer.as_ref())?;
 
-    /// Vault token account PDA that holds the escrowed tokens.
-    /// Authority is itself (the vault PDA) so only the program can move funds.
-    #[account(
-        init,
-        payer = buyer,
-        token::mint = mint,
-        token::authority = vault,
-        seeds = [b"vault", escrow.key().as_ref()],
-        bump,
-    )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+        log!(
+            escrow,
+            "Escrow released: {} tokens sent to seller, {} to referrer, {} fee, {} refunded",
+            seller_amount,
+            referral,
+            fee,
+            refund
+        );
+        Ok(())
+    }
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-}
+    /// Release with signature: `release` for escrows gated by an off-chain
+    /// authority, e.g. a delivery oracle. The instruction right before this
+    /// one must be an Ed25519 program instruction verifying `signature` by
+    /// the escrow's `oracle_pubkey` over `message`, and `message` must start
+    /// with the escrow's address and the next nonce (u64, little-endian), so
+    /// a signature is good for one release of one escrow; otherwise it fails
+    /// with `InvalidSignature`. The release itself is checked and paid
+    /// exactly as in `release`, using that nonce. Must be called at the top
+    /// level of the transaction, with the instructions sysvar.
+    pub fn release_with_signature<'info>(
+        ctx: Context<'_, '_, '_, 'info, Release<'info>>,
+        message: Vec<u8>,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require!(
+            escrow.oracle_pubkey != Pubkey::default(),
+            EscrowError::InvalidSignature
+        );
+        let instructions = ctx
+            .accounts
+            .instructions
+            .as_ref()
+            .ok_or(EscrowError::InvalidSignature)?;
+        let nonce = escrow
+            .last_action_nonce
+            .checked_add(1)
+            .ok_or(EscrowError::MathOverflow)?;
+        require!(
+            message.get(..32) == Some(escrow.key().as_ref())
+                && message.get(32..40) == Some(&nonce.to_le_bytes()[..]),
+            EscrowError::InvalidSignature
+        );
+        verify_ed25519_instruction(instructions, &escrow.oracle_pubkey, &message, &signature)?;
 
-#[derive(Accounts)]
-pub struct Release<'info> {
-    /// Only the buyer can release funds.
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+        release(ctx, nonce)
+    }
 
-    /// SPL token mint (needed for transfer_checked).
-    pub mint: InterfaceAccount<'info, Mint>,
+    /// Release group: pay out every escrow of an escrow group at once, so no
+    /// leg of a multi-party deal settles unless all of them do. Grouped
+    /// escrows can't be released one by one. `remaining_accounts` holds
+    /// `GROUP_LEG_ACCOUNTS` accounts per escrow: escrow, vault, mint, mint
+    /// policy, seller token account (owned by the seller), fee token account
+    /// (this program's ID if no fee is due), and the escrow's buyer or settle
+    /// authority as a signer, followed by the leg's fee split token accounts
+    /// (one per `config.fee_split` entry). Fails with `GroupMismatch` unless every escrow
+    /// of the group is passed, and with `GroupNotReady` before paying anyone
+    /// if any of them couldn't be released right now.
+    pub fn release_group<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReleaseGroup<'info>>,
+        group_id: [u8; 32],
+    ) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        let leg_len = GROUP_LEG_ACCOUNTS + ctx.accounts.config.fee_split.len();
+        require!(
+            group_id != [0; 32] && accounts.len() % leg_len == 0,
+            EscrowError::GroupMismatch
+        );
+        let legs = accounts.len() / leg_len;
+        let now = current_time(&ctx)?;
 
-    /// Escrow state — must be locked and belong to this buyer.
-    #[account(
-        mut,
-        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
-        bump = escrow.bump,
-        has_one = buyer,
-        has_one = mint,
-    )]
-    pub escrow: Account<'info, Escrow>,
+        // Check every leg before paying any
+        let mut escrows: Vec<Account<Escrow>> = Vec::with_capacity(legs);
+        for leg in accounts.chunks(leg_len) {
+            let escrow = Account::<Escrow>::try_from(&leg[0])?;
+            require!(
+                escrow.group_id == group_id
+                    && usize::from(escrow.group_size) == legs
+                    && escrows.iter().all(|other| other.key() != escrow.key()),
+                EscrowError::GroupMismatch
+            );
+            let settler = &leg[6];
+            require!(
+                settler.is_signer
+                    && (settler.key() == escrow.buyer || settler.key() == escrow.settle_authority),
+                EscrowError::Unauthorized
+            );
+            require!(escrow.is_group_releasable(now), EscrowError::GroupNotReady);
+            escrows.push(escrow);
+        }
 
-    /// Vault holding the tokens.
-    #[account(
-        mut,
-        seeds = [b"vault", escrow.key().as_ref()],
-        bump = escrow.vault_bump,
-    )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+        for (leg, mut escrow) in accounts.chunks(leg_len).zip(escrows) {
+            release_group_leg(
+                &ctx.accounts.token_program,
+                &ctx.accounts.config,
+                ctx.accounts.memo_program.as_ref(),
+                leg,
+                &mut escrow,
+            )?;
+        }
 
-    /// Seller's token account to receive funds.
-    #[account(
-        mut,
-        token::mint = mint,
-    )]
-    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+        msg!("Released {} escrows of the group", legs);
+        Ok(())
+    }
 
-    pub token_program: Interface<'info, TokenInterface>,
-}
+    /// Release a single milestone of a `Milestone` escrow to the seller (minus
+    /// the protocol fee). The escrow becomes `Released` once every milestone
+    /// has been paid.
+    pub fn release_milestone<'info>(ctx: Context<'_, '_, '_, 'info, Release<'info>>, index: u8) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_settler(escrow, &ctx.accounts.buyer, ctx.accounts.settle_authority.as_ref())?;
+        escrow.require_approved(current_time(&ctx)?)?;
+        escrow.require_delivered()?;
+        escrow.require_mint_unchanged(&ctx.accounts.mint)?;
+        prepare_seller_token_account(ctx.accounts)?;
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require_strict_tx(escrow, ctx.accounts.instructions.as_ref())?;
+        escrow.require_kind(&[EscrowKind::Milestone])?;
 
-#[derive(Accounts)]
-pub struct Cancel<'info> {
-    /// Only the buyer can cancel.
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+        let milestone = escrow
+            .milestones
+            .get(index as usize)
+            .ok_or(EscrowError::InvalidMilestone)?;
+        require!(!milestone.released, EscrowError::MilestoneAlreadyReleased);
+        let amount = milestone.amount;
+        let is_last = escrow
+            .milestones
+            .iter()
+            .enumerate()
+            .all(|(i, m)| m.released || i == index as usize);
 
-    /// SPL token mint (needed for transfer_checked).
-    pub mint: InterfaceAccount<'info, Mint>,
+        // Transfer tokens from vault → fee recipient and seller. The last
+        // milestone sweeps the vault so no dust is left behind.
+        let (fee, paid) = pay_with_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            &ctx.accounts.config,
+            ctx.accounts.fee_token_account.as_ref(),
+            fee_split_accounts(escrow, ctx.remaining_accounts),
+            ctx.accounts.seller_token_account.to_account_info(),
+            amount,
+            is_last,
+        )?;
 
-    /// Escrow state — must be locked and belong to this buyer.
-    #[account(
-        mut,
-        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
-        bump = escrow.bump,
-        has_one = buyer,
-        has_one = mint,
-    )]
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.fee_collected = escrow.fee_collected.saturating_add(fee);
+        escrow.milestones[index as usize].released = true;
+        escrow.amount = escrow
+            .amount
+            .checked_sub(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        if is_last {
+            escrow.state = EscrowState::Released;
+            ctx.accounts.mint_policy.unlock(escrow.deposit);
+            write_memo(
+                &ctx.accounts.config,
+                ctx.accounts.memo_program.as_ref(),
+                escrow,
+                SettlementOutcome::Released,
+            )?;
+            notify_callback(
+                escrow,
+                ctx.accounts.callback_program.as_ref(),
+                SettlementOutcome::Released,
+            )?;
+            emit_settled(escrow, SettlementOutcome::Released, fee + paid);
+        }
+
+        log!(escrow, "Milestone {} released: {} tokens", index, amount);
+        Ok(())
+    }
+
+    /// Pay every matured, unpaid installment of a `Scheduled` escrow to the
+    /// seller (minus the protocol fee) in one transfer. The escrow becomes
+    /// `Released` once every installment has been paid.
+    pub fn release_scheduled<'info>(ctx: Context<'_, '_, '_, 'info, Release<'info>>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_settler(escrow, &ctx.accounts.buyer, ctx.accounts.settle_authority.as_ref())?;
+        escrow.require_approved(now)?;
+        escrow.require_delivered()?;
+        escrow.require_mint_unchanged(&ctx.accounts.mint)?;
+        prepare_seller_token_account(ctx.accounts)?;
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require_strict_tx(escrow, ctx.accounts.instructions.as_ref())?;
+        escrow.require_kind(&[EscrowKind::Scheduled])?;
+
+        let mut amount: u64 = 0;
+        for payment in escrow.schedule.iter().filter(|p| !p.paid && p.unlock_at <= now) {
+            amount = amount
+                .checked_add(payment.amount)
+                .ok_or(EscrowError::MathOverflow)?;
+        }
+        require!(amount > 0, EscrowError::NothingMatured);
+        let is_last = escrow.schedule.iter().all(|p| p.paid || p.unlock_at <= now);
+
+        // The final installment sweeps the vault so no dust is left behind
+        let (fee, paid) = pay_with_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            &ctx.accounts.config,
+            ctx.accounts.fee_token_account.as_ref(),
+            fee_split_accounts(escrow, ctx.remaining_accounts),
+            ctx.accounts.seller_token_account.to_account_info(),
+            amount,
+            is_last,
+        )?;
+
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.fee_collected = escrow.fee_collected.saturating_add(fee);
+        for payment in escrow.schedule.iter_mut().filter(|p| p.unlock_at <= now) {
+            payment.paid = true;
+        }
+        escrow.amount = escrow
+            .amount
+            .checked_sub(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        if is_last {
+            escrow.state = EscrowState::Released;
+            ctx.accounts.mint_policy.unlock(escrow.deposit);
+            write_memo(
+                &ctx.accounts.config,
+                ctx.accounts.memo_program.as_ref(),
+                escrow,
+                SettlementOutcome::Released,
+            )?;
+            notify_callback(
+                escrow,
+                ctx.accounts.callback_program.as_ref(),
+                SettlementOutcome::Released,
+            )?;
+            emit_settled(escrow, SettlementOutcome::Released, fee + paid);
+        }
+
+        log!(escrow, "Scheduled installments released: {} tokens", amount);
+        Ok(())
+    }
+
+    /// Release part of a `Standard` or `Native` escrow to the seller (minus the
+    /// protocol fee). The escrow becomes `Released` once nothing is left. At
+    /// most `max_releases` partial releases are allowed (0 = unlimited).
+    pub fn release_partial<'info>(ctx: Context<'_, '_, '_, 'info, Release<'info>>, amount: u64) -> Result<()> {
+        let now = current_time(&ctx)?;
+        ctx.accounts.escrow.count_daily_release(now)?;
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_settler(escrow, &ctx.accounts.buyer, ctx.accounts.settle_authority.as_ref())?;
+        escrow.require_approved(now)?;
+        escrow.require_delivered()?;
+        escrow.require_mint_unchanged(&ctx.accounts.mint)?;
+        prepare_seller_token_account(ctx.accounts)?;
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require_strict_tx(escrow, ctx.accounts.instructions.as_ref())?;
+        escrow.require_kind(&[EscrowKind::Standard, EscrowKind::Native])?;
+        require!(!escrow.is_grouped(), EscrowError::GroupNotReady);
+        // Oracle-priced, basket, referred, swapped, and signature-gated
+        // escrows settle in a single release
+        require!(
+            escrow.oracle == Pubkey::default()
+                && escrow.assets.is_empty()
+                && escrow.referrer == Pubkey::default()
+                && escrow.target_mint == Pubkey::default()
+                && escrow.oracle_pubkey == Pubkey::default(),
+            EscrowError::WrongEscrowKind
+        );
+        require!(amount > 0, EscrowError::ZeroAmount);
+        require!(amount <= escrow.amount, EscrowError::AmountExceedsEscrow);
+        require!(
+            escrow.max_releases == 0 || escrow.release_count < escrow.max_releases,
+            EscrowError::TooManyReleases
+        );
+        let is_last = amount == escrow.amount;
+        // The final release may clear any balance, however small
+        require!(
+            is_last || amount >= escrow.min_release_amount,
+            EscrowError::ReleaseBelowMinimum
+        );
+
+        // The final partial release sweeps the vault so no dust is left behind
+        let (fee, paid) = pay_with_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            &ctx.accounts.config,
+            ctx.accounts.fee_token_account.as_ref(),
+            fee_split_accounts(escrow, ctx.remaining_accounts),
+            ctx.accounts.seller_token_account.to_account_info(),
+            amount,
+            is_last,
+        )?;
+
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.fee_collected = escrow.fee_collected.saturating_add(fee);
+        escrow.amount -= amount;
+        escrow.release_count = escrow.release_count.saturating_add(1);
+        if is_last {
+            escrow.state = EscrowState::Released;
+            ctx.accounts.mint_policy.unlock(escrow.deposit);
+            write_memo(
+                &ctx.accounts.config,
+                ctx.accounts.memo_program.as_ref(),
+                escrow,
+                SettlementOutcome::Released,
+            )?;
+            notify_callback(
+                escrow,
+                ctx.accounts.callback_program.as_ref(),
+                SettlementOutcome::Released,
+            )?;
+            emit_settled(escrow, SettlementOutcome::Released, fee + paid);
+        }
+
+        log!(
+            escrow,
+            "Partial release {}: {} tokens, {} remaining",
+            escrow.release_count,
+            amount,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    /// Confirm seller: the pending seller signs to accept the escrow, moving it
+    /// from `PendingSellerConfirm` to `Locked`. Catches misaddressed escrows
+    /// before the seller can be paid. If the escrow names a `seller_program`,
+    /// the seller must be a PDA signing through a CPI from that program.
+    pub fn confirm_seller(ctx: Context<ConfirmSeller>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        if escrow.state != EscrowState::PendingSellerConfirm {
+            return Err(escrow.state_error().into());
+        }
+        require_keys_eq!(
+            ctx.accounts.seller.key(),
+            escrow.pending_seller,
+            EscrowError::Unauthorized
+        );
+        require_seller_program(escrow, &ctx.accounts.instructions)?;
+
+        escrow.pending_seller = Pubkey::default();
+        escrow.state = EscrowState::Locked;
+
+        msg!("Seller {} confirmed", escrow.seller);
+        Ok(())
+    }
+
+    /// Confirm release: an approver of the escrow records their approval.
+    /// Releases need approvals worth at least `approval_threshold` in total,
+    /// collected within `approval_window_secs` of the first if the escrow has
+    /// a window.
+    pub fn confirm_release(ctx: Context<ConfirmRelease>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        let bit = 1u8 << escrow.approver_index(&ctx.accounts.approver.key())?;
+
+        // A quorum not reached within the window starts over. One already
+        // reached stands.
+        let reached = escrow.approved_weight() >= u32::from(escrow.approval_threshold);
+        if !reached && escrow.approval_window_expired(now) {
+            escrow.approvals = 0;
+            msg!("Approval window expired; confirmations reset");
+        }
+        if escrow.approvals == 0 {
+            escrow.first_confirm_at = now;
+        }
+        escrow.approvals |= bit;
+
+        msg!(
+            "Release approved: weight {} of {}",
+            escrow.approved_weight(),
+            escrow.approval_threshold
+        );
+        Ok(())
+    }
+
+    /// Submit delivery: the seller records a hash of the deliverable. Escrows
+    /// created with `require_delivery` can't be released before this.
+    pub fn submit_delivery(ctx: Context<SubmitDelivery>, hash: [u8; 32]) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        escrow.require_locked()?;
+        require!(hash != [0; 32], EscrowError::NoDelivery);
+
+        escrow.delivery_hash = hash;
+
+        msg!("Delivery submitted for escrow {}", escrow.key());
+        Ok(())
+    }
+
+    /// Add tag: the buyer labels a `Locked` escrow for off-chain filtering.
+    /// Adding a tag the escrow already has is a no-op; fails with
+    /// `TooManyTags` past `MAX_TAGS`.
+    pub fn add_tag(ctx: Context<UpdateTags>, tag: [u8; 16]) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.require_locked()?;
+        if escrow.tags.contains(&tag) {
+            return Ok(());
+        }
+        require!(escrow.tags.len() < MAX_TAGS, EscrowError::TooManyTags);
+
+        escrow.tags.push(tag);
+
+        msg!("Tag added: {} of {}", escrow.tags.len(), MAX_TAGS);
+        Ok(())
+    }
+
+    /// Remove tag: the buyer drops a tag from a `Locked` escrow. Removing a
+    /// tag the escrow doesn't have is a no-op.
+    pub fn remove_tag(ctx: Context<UpdateTags>, tag: [u8; 16]) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.require_locked()?;
+
+        escrow.tags.retain(|t| *t != tag);
+
+        msg!("Tag removed: {} of {}", escrow.tags.len(), MAX_TAGS);
+        Ok(())
+    }
+
+    /// Expire: once the deadline has passed, anyone can mark a locked escrow
+    /// `Expired`. The buyer can still cancel, or both parties can `reopen`.
+    pub fn expire(ctx: Context<Expire>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+
+        require!(escrow.deadline_passed(now), EscrowError::NotExpired);
+
+        escrow.state = EscrowState::Expired;
+
+        msg!("Escrow expired at {}", now);
+        Ok(())
+    }
+
+    /// Settle on expire: permissionless after the deadline. Splits the escrow
+    /// per `expire_buyer_bps`: that share is refunded to the buyer (split
+    /// pro rata with any pooled contributors) and the rest paid to the seller
+    /// (minus the protocol fee), which is reported as a `Released`
+    /// settlement of the seller's share.
+    pub fn settle_on_expire<'info>(ctx: Context<'_, '_, '_, 'info, SettleOnExpire<'info>>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &ctx.accounts.escrow;
+        match escrow.state {
+            EscrowState::Locked | EscrowState::Expired => {}
+            EscrowState::PendingSellerConfirm => return err!(EscrowError::SellerNotConfirmed),
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        escrow.require_kind(&[
+            EscrowKind::Standard,
+            EscrowKind::Nft,
+            EscrowKind::Native,
+            EscrowKind::Swap,
+        ])?;
+        require!(escrow.deadline_passed(now), EscrowError::NotExpired);
+
+        let buyer_share = u64::try_from(
+            u128::from(escrow.amount) * u128::from(escrow.expire_buyer_bps) / BPS_DENOMINATOR,
+        )
+        .map_err(|_| EscrowError::MathOverflow)?;
+        let seller_share = escrow.amount - buyer_share;
+        // Pooled escrows pass the contributors' token accounts first
+        let (contributor_accounts, rest) = ctx
+            .remaining_accounts
+            .split_at(escrow.contributors.len().min(ctx.remaining_accounts.len()));
+
+        // Seller first: the buyer's transfer then sweeps whatever is left
+        let mut seller_fee = 0;
+        if seller_share > 0 {
+            (seller_fee, _) = pay_with_fee(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                &ctx.accounts.config,
+                ctx.accounts.fee_token_account.as_ref(),
+                fee_split_accounts(escrow, rest),
+                ctx.accounts.seller_token_account.to_account_info(),
+                seller_share,
+                false,
+            )?;
+        }
+        let buyer_amount = ctx
+            .accounts
+            .vault
+            .amount
+            .checked_sub(seller_share)
+            .ok_or(EscrowError::MathOverflow)?;
+        if buyer_amount > 0 {
+            let contributed = refund_contributors(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                contributor_accounts,
+                buyer_amount,
+            )?;
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                ctx.accounts.buyer_token_account.to_account_info(),
+                buyer_amount - contributed,
+            )?;
+        }
+        // Baskets only exist with a full refund on expiry
+        settle_basket(
+            &ctx.accounts.token_program,
+            escrow,
+            ctx.remaining_accounts,
+            escrow.buyer,
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        ctx.accounts.mint_policy.unlock(escrow.deposit);
+        if seller_share == 0 {
+            escrow.state = EscrowState::Refunded;
+            emit_settled(escrow, SettlementOutcome::Refunded, buyer_amount);
+        } else {
+            escrow.state = EscrowState::Settled;
+            escrow.fee_collected = escrow.fee_collected.saturating_add(seller_fee);
+            emit_settled(escrow, SettlementOutcome::Released, seller_share);
+        }
+
+        log!(
+            escrow,
+            "Escrow split on expiry: {} tokens to buyer, {} to seller, {} fee",
+            buyer_amount,
+            seller_share - seller_fee,
+            seller_fee
+        );
+        Ok(())
+    }
+
+    /// Claim: past the deadline, the seller takes the whole escrow (minus the
+    /// protocol fee) once the buyer's account is closed, leaving no one to
+    /// cancel, approve, or release. Approvals and delivery requirements are
+    /// not checked. Fails with `BuyerStillActive` while the buyer's account
+    /// holds lamports.
+    pub fn claim<'info>(ctx: Context<'_, '_, '_, 'info, Claim<'info>>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &ctx.accounts.escrow;
+        match escrow.state {
+            EscrowState::Locked | EscrowState::Expired => {}
+            EscrowState::PendingSellerConfirm => return err!(EscrowError::SellerNotConfirmed),
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        escrow.require_kind(&[
+            EscrowKind::Standard,
+            EscrowKind::Nft,
+            EscrowKind::Native,
+            EscrowKind::Swap,
+        ])?;
+        require!(
+            escrow.oracle_pubkey == Pubkey::default(),
+            EscrowError::InvalidSignature
+        );
+        require!(escrow.deadline_passed(now), EscrowError::NotExpired);
+        require!(
+            ctx.accounts.buyer.lamports() == 0,
+            EscrowError::BuyerStillActive
+        );
+
+        let (fee, seller_amount) = pay_with_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            &ctx.accounts.config,
+            ctx.accounts.fee_token_account.as_ref(),
+            fee_split_accounts(escrow, ctx.remaining_accounts),
+            ctx.accounts.seller_token_account.to_account_info(),
+            escrow.amount,
+            true,
+        )?;
+        settle_basket(
+            &ctx.accounts.token_program,
+            escrow,
+            ctx.remaining_accounts,
+            escrow.seller,
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.state = EscrowState::Released;
+        ctx.accounts.mint_policy.unlock(escrow.deposit);
+        emit_settled(escrow, SettlementOutcome::Released, fee + seller_amount);
+
+        log!(
+            escrow,
+            "Escrow claimed by seller: {} tokens, {} fee",
+            seller_amount,
+            fee
+        );
+        Ok(())
+    }
+
+    /// Reopen: buyer and seller jointly revive an expired escrow with a fresh
+    /// deadline, subject to the same bounds as `initialize`. At most
+    /// `max_extensions` times per escrow.
+    pub fn reopen(ctx: Context<Reopen>, new_deadline: i64) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        require!(escrow.state == EscrowState::Expired, EscrowError::NotExpired);
+        require!(
+            escrow.extension_count < escrow.max_extensions,
+            EscrowError::TooManyExtensions
+        );
+
+        validate_deadline(
+            new_deadline,
+            now,
+            i64::from(ctx.accounts.config.max_deadline_secs),
+        )?;
+
+        escrow.extension_count += 1;
+        escrow.deadline = new_deadline;
+        escrow.state = EscrowState::Locked;
+
+        msg!("Escrow reopened until {}", new_deadline);
+        Ok(())
+    }
+
+    /// Extend by: buyer pushes a `Locked` escrow's deadline back by
+    /// `additional_secs`, sparing clients the absolute-timestamp arithmetic.
+    /// The new deadline must still be within `max_deadline_secs` of now.
+    /// Fails with `DeadlineNotExtended` unless `additional_secs` is positive.
+    /// Counts against `max_extensions`, like `reopen`.
+    pub fn extend_by(ctx: Context<ExtendBy>, additional_secs: i64) -> Result<()> {
+        require!(additional_secs > 0, EscrowError::DeadlineNotExtended);
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.no_deadline, EscrowError::DeadlineNotExtended);
+        require!(
+            escrow.extension_count < escrow.max_extensions,
+            EscrowError::TooManyExtensions
+        );
+
+        let deadline = escrow
+            .deadline
+            .checked_add(additional_secs)
+            .ok_or(EscrowError::MathOverflow)?;
+        validate_deadline(
+            deadline,
+            now,
+            i64::from(ctx.accounts.config.max_deadline_secs),
+        )?;
+        escrow.extension_count += 1;
+        escrow.deadline = deadline;
+
+        let seq = escrow.next_event_seq();
+        emit!(DeadlineExtended {
+            escrow: escrow.key(),
+            deadline,
+            seq,
+        });
+
+        msg!("Deadline extended by {}s to {}", additional_secs, deadline);
+        Ok(())
+    }
+
+    /// Amend: buyer and seller jointly change non-critical parameters of a
+    /// `Locked` escrow. Each field set in `changes` is validated like the
+    /// matching `initialize` option and all are applied together. Fails with
+    /// `UnauthorizedAmendment` unless both parties sign.
+    pub fn amend(ctx: Context<Amend>, changes: AmendmentSpec) -> Result<()> {
+        require!(
+            ctx.accounts.buyer.is_signer && ctx.accounts.seller.is_signer,
+            EscrowError::UnauthorizedAmendment
+        );
+        let now = current_time(&ctx)?;
+        let config = &ctx.accounts.config;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+
+        if let Some(deadline) = changes.deadline {
+            if deadline != NO_DEADLINE {
+                validate_deadline(deadline, now, i64::from(config.max_deadline_secs))?;
+                require!(
+                    deadline >= now + i64::from(config.min_deadline_secs),
+                    EscrowError::DeadlineTooSoon
+                );
+                require!(
+                    escrow.schedule.iter().all(|p| p.unlock_at < deadline),
+                    EscrowError::InvalidSchedule
+                );
+            }
+            escrow.deadline = deadline;
+            escrow.no_deadline = deadline == NO_DEADLINE;
+        }
+        if let Some(arbiter_fee_bps) = changes.arbiter_fee_bps {
+            require!(
+                (escrow.arbiter != Pubkey::default() || arbiter_fee_bps == 0)
+                    && u128::from(arbiter_fee_bps) + u128::from(config.fee_bps)
+                        <= BPS_DENOMINATOR,
+                EscrowError::InvalidFee
+            );
+            escrow.arbiter_fee_bps = arbiter_fee_bps;
+        }
+        if let Some(terms_hash) = changes.terms_hash {
+            escrow.terms_hash = terms_hash;
+        }
+
+        msg!(
+            "Escrow amended: deadline {}, arbiter fee {} bps",
+            escrow.deadline,
+            escrow.arbiter_fee_bps
+        );
+        Ok(())
+    }
+
+    /// Freeze escrow: buyer and seller jointly freeze the escrow, e.g. while
+    /// they negotiate off-chain. Until unfrozen, no instruction moves funds
+    /// and the deadline can't be acted on.
+    pub fn freeze_escrow(ctx: Context<FreezeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        match escrow.state {
+            EscrowState::Locked
+            | EscrowState::PendingSellerConfirm
+            | EscrowState::Expired
+            | EscrowState::CancelRequested => {}
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+
+        escrow.frozen = true;
+
+        msg!("Escrow frozen");
+        Ok(())
+    }
+
+    /// Unfreeze escrow: buyer and seller jointly lift a freeze.
+    pub fn unfreeze_escrow(ctx: Context<FreezeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        require!(escrow.frozen, EscrowError::NotFrozen);
+
+        escrow.frozen = false;
+
+        msg!("Escrow unfrozen");
+        Ok(())
+    }
+
+    /// Reduce amount: buyer and seller agree on a lower price before delivery.
+    /// The difference is refunded to the buyer, taken off `deposit` and the
+    /// mint's TVL, and the escrow stays `Locked`. Increases are not allowed
+    /// here.
+    pub fn reduce_amount(ctx: Context<ReduceAmount>, new_amount: u64) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        escrow.require_kind(&[EscrowKind::Standard, EscrowKind::Native, EscrowKind::Swap])?;
+        // The difference goes to the buyer alone
+        require!(escrow.contributors.is_empty(), EscrowError::WrongEscrowKind);
+        require!(new_amount > 0, EscrowError::ZeroAmount);
+        require!(new_amount < escrow.amount, EscrowError::CannotIncrease);
+
+        let refund = escrow.amount - new_amount;
+
+        // Transfer the difference from vault → buyer
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            ctx.accounts.buyer_token_account.to_account_info(),
+            refund,
+        )?;
+
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.amount = new_amount;
+        let unlocked = refund.min(escrow.deposit);
+        escrow.deposit -= unlocked;
+        ctx.accounts.mint_policy.unlock(unlocked);
+
+        msg!(
+            "Escrow amount reduced to {}: {} tokens refunded to buyer",
+            new_amount,
+            refund
+        );
+        Ok(())
+    }
+
+    /// Rescue excess: return tokens sent straight to the vault (on top of the
+    /// escrowed amount) to the buyer. The escrowed amount stays locked.
+    pub fn rescue_excess(ctx: Context<RescueExcess>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+
+        let excess = ctx.accounts.vault.amount.saturating_sub(escrow.amount);
+        require!(excess > 0, EscrowError::NoExcess);
+
+        // Transfer the excess from vault → buyer
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            escrow,
+            ctx.accounts.buyer_token_account.to_account_info(),
+            excess,
+        )?;
+
+        msg!("Rescued {} excess tokens to buyer", excess);
+        Ok(())
+    }
+
+    /// Cancel: buyer reclaims funds. The buyer can cancel at any time
+    /// while the escrow is still locked (before release), including before
+    /// the seller has confirmed. With `auto_close`, the vault and escrow are
+    /// closed in the same transaction and their rent returned to the buyer.
+    /// Takes the same `nonce` as `release`.
+    pub fn cancel<'info>(ctx: Context<'_, '_, '_, 'info, Cancel<'info>>, nonce: u64) -> Result<()> {
+        ctx.accounts.escrow.use_nonce(nonce)?;
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_cancellable()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require_vault_authority(&ctx.accounts.vault)?;
+
+        let accounts = ctx.accounts;
+        let fee = charge_cancel_fee(
+            &accounts.token_program,
+            &accounts.mint,
+            &mut accounts.vault,
+            &mut accounts.escrow,
+            &accounts.config,
+            accounts.fee_token_account.as_ref(),
+        )?;
+        let amount = refund_buyer(
+            &accounts.token_program,
+            &accounts.mint,
+            &accounts.vault,
+            &mut accounts.escrow,
+            &accounts.buyer_token_account,
+            &accounts.config,
+            accounts.memo_program.as_ref(),
+            accounts.callback_program.as_ref(),
+            ctx.remaining_accounts,
+            SettlementOutcome::Cancelled,
+        )?;
+        accounts.mint_policy.unlock(accounts.escrow.deposit);
+        pay_relayer_rebate(&mut accounts.escrow, accounts.relayer.as_ref())?;
+        if accounts.escrow.auto_close {
+            close_escrow_accounts(
+                &accounts.token_program,
+                Some(&mut accounts.vault),
+                &accounts.escrow,
+                accounts.buyer.to_account_info(),
+            )?;
+        }
+
+        log!(
+            accounts.escrow,
+            "Escrow cancelled: {} tokens returned to buyer, {} cancel fee",
+            amount,
+            fee
+        );
+        Ok(())
+    }
+
+    /// Force expire and refund: keeper action bundling `expire` + `cancel`.
+    /// Permissionless once the deadline has passed, for escrows whose expiry
+    /// policy refunds the buyer in full (`expire_buyer_bps` = 10_000).
+    pub fn force_expire_and_refund<'info>(ctx: Context<'_, '_, '_, 'info, ForceExpireAndRefund<'info>>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &ctx.accounts.escrow;
+        match escrow.state {
+            EscrowState::Locked | EscrowState::Expired => {}
+            EscrowState::PendingSellerConfirm => return err!(EscrowError::SellerNotConfirmed),
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require!(escrow.deadline_passed(now), EscrowError::NotExpired);
+        require!(
+            u128::from(escrow.expire_buyer_bps) == BPS_DENOMINATOR,
+            EscrowError::RefundNotAllowed
+        );
+
+        let accounts = ctx.accounts;
+        let amount = refund_buyer(
+            &accounts.token_program,
+            &accounts.mint,
+            &accounts.vault,
+            &mut accounts.escrow,
+            &accounts.buyer_token_account,
+            &accounts.config,
+            accounts.memo_program.as_ref(),
+            accounts.callback_program.as_ref(),
+            ctx.remaining_accounts,
+            SettlementOutcome::Refunded,
+        )?;
+        accounts.mint_policy.unlock(accounts.escrow.deposit);
+
+        log!(accounts.escrow, "Escrow expired and refunded: {} tokens returned to buyer", amount);
+        Ok(())
+    }
+
+    /// Request cancel: first step of the cancellation flow for escrows with a
+    /// notice period. Moves to `CancelRequested`; the seller can `object` (or
+    /// open a dispute) before `finalize_cancel` becomes available.
+    pub fn request_cancel(ctx: Context<RequestCancel>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        require!(!escrow.objected, EscrowError::ObjectionRaised);
+        match escrow.state {
+            EscrowState::Locked | EscrowState::PendingSellerConfirm => {}
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        escrow.require_undelivered()?;
+
+        escrow.cancel_requested_at = now;
+        escrow.state = EscrowState::CancelRequested;
+
+        msg!(
+            "Cancel requested at {}; final after {} seconds",
+            now,
+            escrow.notice_secs
+        );
+        Ok(())
+    }
+
+    /// Finalize cancel: refund the buyer once the notice period after
+    /// `request_cancel` has elapsed. Honors `auto_close` like `cancel`.
+    pub fn finalize_cancel<'info>(ctx: Context<'_, '_, '_, 'info, Cancel<'info>>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &ctx.accounts.escrow;
+        require!(!escrow.objected, EscrowError::ObjectionRaised);
+        if escrow.state != EscrowState::CancelRequested {
+            return Err(escrow.state_error().into());
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        escrow.require_undelivered()?;
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require!(now >= escrow.notice_ends()?, EscrowError::NoticePeriodActive);
+
+        let accounts = ctx.accounts;
+        charge_cancel_fee(
+            &accounts.token_program,
+            &accounts.mint,
+            &mut accounts.vault,
+            &mut accounts.escrow,
+            &accounts.config,
+            accounts.fee_token_account.as_ref(),
+        )?;
+        let amount = refund_buyer(
+            &accounts.token_program,
+            &accounts.mint,
+            &accounts.vault,
+            &mut accounts.escrow,
+            &accounts.buyer_token_account,
+            &accounts.config,
+            accounts.memo_program.as_ref(),
+            accounts.callback_program.as_ref(),
+            ctx.remaining_accounts,
+            SettlementOutcome::Cancelled,
+        )?;
+        accounts.mint_policy.unlock(accounts.escrow.deposit);
+        pay_relayer_rebate(&mut accounts.escrow, accounts.relayer.as_ref())?;
+        if accounts.escrow.auto_close {
+            close_escrow_accounts(
+                &accounts.token_program,
+                Some(&mut accounts.vault),
+                &accounts.escrow,
+                accounts.buyer.to_account_info(),
+            )?;
+        }
+
+        log!(accounts.escrow, "Escrow cancelled after notice: {} tokens returned to buyer", amount);
+        Ok(())
+    }
+
+    /// Object: the seller blocks a pending cancel request during the notice
+    /// period. The escrow returns to `Locked` with a dispute open, so only the
+    /// arbiter can settle it; the buyer can't request a cancel again.
+    pub fn object(ctx: Context<Object>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        if escrow.state != EscrowState::CancelRequested {
+            return Err(escrow.state_error().into());
+        }
+        require!(now < escrow.notice_ends()?, EscrowError::ObjectionWindowClosed);
+        require!(
+            escrow.arbiter != Pubkey::default() || !escrow.arbiters.is_empty(),
+            EscrowError::NoArbiter
+        );
+
+        escrow.state = EscrowState::Locked;
+        escrow.objected = true;
+        escrow.dispute_open = true;
+        escrow.votes_cast = 0;
+        escrow.votes_to_seller = 0;
+        extend_for_dispute(escrow, now);
+
+        msg!("Seller objected to the cancel request; dispute opened");
+        Ok(())
+    }
+
+    /// Cancel a wrapped-SOL escrow and refund the buyer in native lamports.
+    /// Settles like `cancel` (nonce, cancel fee, memo, callback, relayer
+    /// rebate, `auto_close`), then closes the buyer's wSOL token account to
+    /// the buyer's wallet. Pass a temporary wSOL account if the buyer wants
+    /// to keep an existing wSOL balance wrapped.
+    pub fn cancel_native<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelNative<'info>>,
+        nonce: u64,
+    ) -> Result<()> {
+        ctx.accounts.escrow.use_nonce(nonce)?;
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_cancellable()?;
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        require_authorized_caller(escrow, ctx.accounts.instructions.as_ref())?;
+        require!(
+            is_native_mint(&ctx.accounts.mint.key()),
+            EscrowError::NotNativeMint
+        );
+        escrow.require_kind(&[EscrowKind::Native])?;
+        require_vault_authority(&ctx.accounts.vault)?;
+
+        let accounts = ctx.accounts;
+        let fee = charge_cancel_fee(
+            &accounts.token_program,
+            &accounts.mint,
+            &mut accounts.vault,
+            &mut accounts.escrow,
+            &accounts.config,
+            accounts.fee_token_account.as_ref(),
+        )?;
+        let amount = refund_buyer(
+            &accounts.token_program,
+            &accounts.mint,
+            &accounts.vault,
+            &mut accounts.escrow,
+            &accounts.buyer_token_account,
+            &accounts.config,
+            accounts.memo_program.as_ref(),
+            accounts.callback_program.as_ref(),
+            ctx.remaining_accounts,
+            SettlementOutcome::Cancelled,
+        )?;
+
+        // Close the buyer's wSOL account → lamports (rent + refund) go to the buyer
+        let cpi_accounts = CloseAccount {
+            account: accounts.buyer_token_account.to_account_info(),
+            destination: accounts.buyer.to_account_info(),
+            authority: accounts.buyer.to_account_info(),
+        };
+        let cpi_program = accounts.token_program.to_account_info();
+        token_interface::close_account(CpiContext::new(cpi_program, cpi_accounts))?;
+
+        accounts.mint_policy.unlock(accounts.escrow.deposit);
+        pay_relayer_rebate(&mut accounts.escrow, accounts.relayer.as_ref())?;
+        if accounts.escrow.auto_close {
+            close_escrow_accounts(
+                &accounts.token_program,
+                Some(&mut accounts.vault),
+                &accounts.escrow,
+                accounts.buyer.to_account_info(),
+            )?;
+        }
+
+        log!(
+            accounts.escrow,
+            "Escrow cancelled: {} lamports returned to buyer, {} cancel fee",
+            amount,
+            fee
+        );
+        Ok(())
+    }
+
+    /// Open a dispute: buyer or seller freezes the escrow until the arbiter
+    /// rules. While open, `release` and `cancel` are blocked. A deadline less
+    /// than `dispute_buffer_secs` away is pushed out to give the arbiter time.
+    pub fn open_dispute(ctx: Context<OpenDispute>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        // A dispute during the notice period withdraws the cancel request
+        if escrow.state == EscrowState::CancelRequested {
+            escrow.state = EscrowState::Locked;
+        }
+        escrow.require_locked()?;
+        require!(
+            escrow.arbiter != Pubkey::default() || !escrow.arbiters.is_empty(),
+            EscrowError::NoArbiter
+        );
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+
+        let party = ctx.accounts.party.key();
+        require!(
+            party == escrow.buyer || party == escrow.seller,
+            EscrowError::Unauthorized
+        );
+
+        escrow.dispute_open = true;
+        escrow.votes_cast = 0;
+        escrow.votes_to_seller = 0;
+        extend_for_dispute(escrow, now);
+
+        msg!("Dispute opened by {}", party);
+        Ok(())
+    }
+
+    /// Vote on a dispute: each arbiter on a panel casts one vote, which can't
+    /// be changed. Funds move only once `resolve_dispute` sees a strict
+    /// majority.
+    pub fn vote_dispute(ctx: Context<VoteDispute>, to_seller: bool) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.throttle(Clock::get()?.slot)?;
+        require!(escrow.dispute_open, EscrowError::NoDispute);
+        let bit = 1u8 << escrow.arbiter_index(&ctx.accounts.arbiter.key())?;
+        require!(escrow.votes_cast & bit == 0, EscrowError::AlreadyVoted);
+
+        escrow.votes_cast |= bit;
+        if to_seller {
+            escrow.votes_to_seller |= bit;
+        } else {
+            escrow.votes_to_seller &= !bit;
+        }
+
+        msg!(
+            "Dispute votes: {} to seller, {} to buyer, {} needed",
+            escrow.votes_for(true),
+            escrow.votes_for(false),
+            escrow.arbiter_threshold()
+        );
+        Ok(())
+    }
+
+    /// Resolve a dispute: the arbiter sends the escrowed funds to the seller
+    /// (minus the protocol fee) or back to the buyer and any pooled
+    /// contributors, pro rata. On escrows with an arbiter panel, any panel
+    /// member may execute the majority outcome.
+    pub fn resolve_dispute<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveDispute<'info>>,
+        to_seller: bool,
+    ) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        escrow.require_locked()?;
+        require!(escrow.dispute_open, EscrowError::NoDispute);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        escrow.require_ruling(&ctx.accounts.arbiter.key(), to_seller)?;
+
+        // The arbiter's fee comes out of the vault before the winner is paid
+        let arbiter_fee = compute_fee(
+            ctx.accounts.vault.amount,
+            escrow.arbiter_fee_bps,
+            ctx.accounts.config.fee_rounding,
+        )?;
+        if arbiter_fee > 0 {
+            let arbiter_token_account = ctx
+                .accounts
+                .arbiter_token_account
+                .as_ref()
+                .ok_or(EscrowError::MissingArbiterAccount)?;
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                arbiter_token_account.to_account_info(),
+                arbiter_fee,
+            )?;
+            ctx.accounts.vault.reload()?;
+        }
+        let amount = escrow.amount.saturating_sub(arbiter_fee);
+
+        let paid_out = if to_seller {
+            // Transfer the fee, then sweep the rest of the vault → seller
+            let (fee, paid) = pay_with_fee(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                &ctx.accounts.config,
+                ctx.accounts.fee_token_account.as_ref(),
+                fee_split_accounts(escrow, ctx.remaining_accounts),
+                ctx.accounts.seller_token_account.to_account_info(),
+                amount,
+                true,
+            )?;
+            fee + paid
+        } else {
+            // Sweep the vault → contributors and buyer. No fee is charged on
+            // the refund, and none is refunded: `fee_collected` was charged
+            // only on payouts the seller keeps.
+            let refund = ctx.accounts.vault.amount;
+            let contributed = refund_contributors(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                ctx.remaining_accounts,
+                refund,
+            )?;
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                escrow,
+                ctx.accounts.buyer_token_account.to_account_info(),
+                refund - contributed,
+            )?;
+            refund
+        };
+        let winner = if to_seller { escrow.seller } else { escrow.buyer };
+        settle_basket(
+            &ctx.accounts.token_program,
+            escrow,
+            ctx.remaining_accounts,
+            winner,
+        )?;
+
+        // Update state
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.dispute_open = false;
+        escrow.state = if to_seller {
+            EscrowState::Released
+        } else {
+            EscrowState::Cancelled
+        };
+        escrow.collateral_forfeited = !to_seller;
+        ctx.accounts.mint_policy.unlock(escrow.deposit);
+        let outcome = if to_seller {
+            SettlementOutcome::Released
+        } else {
+            SettlementOutcome::Cancelled
+        };
+        write_memo(&ctx.accounts.config, ctx.accounts.memo_program.as_ref(), escrow, outcome)?;
+        notify_callback(escrow, ctx.accounts.callback_program.as_ref(), outcome)?;
+        emit_settled(escrow, outcome, arbiter_fee + paid_out);
+
+        log!(
+            escrow,
+            "Dispute resolved: {} tokens to {}, {} arbiter fee",
+            amount,
+            if to_seller { "seller" } else { "buyer" },
+            arbiter_fee
+        );
+        Ok(())
+    }
+
+    /// Close a dispute without moving funds, returning control to the parties.
+    /// Arbiter only.
+    pub fn close_dispute(ctx: Context<CloseDispute>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        require!(escrow.dispute_open, EscrowError::NoDispute);
+
+        escrow.dispute_open = false;
+
+        msg!("Dispute closed by arbiter");
+        Ok(())
+    }
+
+    /// Close escrow: close a settled escrow and its emptied vault, returning
+    /// both rents to the buyer. Callable by the buyer or the config's
+    /// `finalizer`, a cleanup service that can't take the rent. After
+    /// `close_vault_only`, pass no vault; just the record is closed.
+    pub fn close_escrow(ctx: Context<CloseEscrow>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require!(escrow.is_terminal(), EscrowError::NotTerminal);
+        // Asset vaults aren't tracked once the escrow is gone
+        require!(escrow.assets.is_empty(), EscrowError::WrongEscrowKind);
+        let caller = ctx.accounts.caller.key();
+        let finalizer = ctx.accounts.config.finalizer;
+        require!(
+            caller == escrow.buyer || (finalizer != Pubkey::default() && caller == finalizer),
+            EscrowError::Unauthorized
+        );
+
+        let accounts = ctx.accounts;
+        let vault = if accounts.escrow.vault_closed {
+            None
+        } else {
+            Some(accounts.vault.as_mut().ok_or(EscrowError::MissingVault)?)
+        };
+        close_escrow_accounts(
+            &accounts.token_program,
+            vault,
+            &accounts.escrow,
+            accounts.buyer.to_account_info(),
+        )?;
+
+        msg!("Escrow closed by {}", caller);
+        Ok(())
+    }
+
+    /// Close vault only: like `close_escrow`, but closes just the emptied
+    /// vault, returning its rent to the buyer. The `Escrow` stays in its
+    /// terminal state, readable for reference. Callable by the buyer or the
+    /// config's `finalizer`.
+    pub fn close_vault_only(ctx: Context<CloseEscrow>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require!(escrow.is_terminal(), EscrowError::NotTerminal);
+        // Asset vaults aren't closed here
+        require!(escrow.assets.is_empty(), EscrowError::WrongEscrowKind);
+        let caller = ctx.accounts.caller.key();
+        let finalizer = ctx.accounts.config.finalizer;
+        require!(
+            caller == escrow.buyer || (finalizer != Pubkey::default() && caller == finalizer),
+            EscrowError::Unauthorized
+        );
+
+        let accounts = ctx.accounts;
+        let vault = accounts.vault.as_mut().ok_or(EscrowError::MissingVault)?;
+        close_vault(
+            &accounts.token_program,
+            vault,
+            &accounts.escrow,
+            accounts.buyer.to_account_info(),
+        )?;
+        accounts.escrow.vault_closed = true;
+
+        msg!("Vault closed by {}; escrow kept", caller);
+        Ok(())
+    }
+
+    /// Post collateral: the seller locks `amount` of the escrow's mint as a
+    /// performance bond, in a vault of its own. Can be topped up while the
+    /// escrow is open; `settle_collateral` pays it out once the escrow settles.
+    pub fn post_collateral(ctx: Context<PostCollateral>, amount: u64) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        match escrow.state {
+            EscrowState::Locked | EscrowState::PendingSellerConfirm => {}
+            _ => return Err(escrow.state_error().into()),
+        }
+        require!(!escrow.dispute_open, EscrowError::DisputeActive);
+        require!(!escrow.frozen, EscrowError::EscrowFrozen);
+        // Closing with the escrow would strand the collateral vault
+        require!(!escrow.auto_close, EscrowError::WrongEscrowKind);
+        require!(amount > 0, EscrowError::ZeroAmount);
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.seller_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.collateral_amount = escrow
+            .collateral_amount
+            .checked_add(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        escrow.collateral_vault_bump = ctx.bumps.collateral_vault;
+
+        log!(escrow, "Seller posted {} tokens of collateral", amount);
+        Ok(())
+    }
+
+    /// Settle collateral: once the escrow has settled, return the seller's
+    /// collateral to them, or pay it to the buyer if the seller lost a
+    /// dispute or let the escrow run out to a full refund. Permissionless;
+    /// the collateral vault's rent goes back to the seller.
+    pub fn settle_collateral(ctx: Context<SettleCollateral>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require!(escrow.is_terminal(), EscrowError::NotTerminal);
+        require!(escrow.collateral_amount > 0, EscrowError::NoCollateral);
+        let recipient = if escrow.forfeits_collateral() {
+            escrow.buyer
+        } else {
+            escrow.seller
+        };
+        require_keys_eq!(
+            ctx.accounts.recipient_token_account.owner,
+            recipient,
+            EscrowError::WrongCollateralRecipient
+        );
+
+        let escrow_key = escrow.key();
+        let seeds = &[
+            b"collateral".as_ref(),
+            escrow_key.as_ref(),
+            &[escrow.collateral_vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        let collateral_vault = &ctx.accounts.collateral_vault;
+        require_keys_eq!(
+            *collateral_vault.owner,
+            ctx.accounts.token_program.key(),
+            ErrorCode::ConstraintTokenTokenProgram
+        );
+        let amount = TokenAccount::try_deserialize(&mut &collateral_vault.try_borrow_data()?[..])?.amount;
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = TransferChecked {
+            from: collateral_vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.recipient_token_account.to_account_info(),
+            authority: collateral_vault.to_account_info(),
+        };
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+        let cpi_accounts = CloseAccount {
+            account: collateral_vault.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: collateral_vault.to_account_info(),
+        };
+        token_interface::close_account(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer))?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.collateral_amount = 0;
+
+        log!(escrow, "Collateral of {} tokens paid to {}", amount, recipient);
+        Ok(())
+    }
+
+    /// Close many: `close_escrow` for a batch of escrows, passed as
+    /// `[escrow, vault, buyer]` triples in `remaining_accounts` (at most
+    /// `MAX_CLOSE_BATCH`). Escrows that aren't settled, hold a basket, or
+    /// still have tokens in the vault are skipped; those whose vault
+    /// `close_vault_only` already closed have just the record closed.
+    /// Returns how many were closed.
+    pub fn close_many<'info>(ctx: Context<'_, '_, 'info, 'info, CloseMany<'info>>) -> Result<u8> {
+        let accounts = ctx.remaining_accounts;
+        require!(
+            accounts.len() % 3 == 0 && accounts.len() / 3 <= MAX_CLOSE_BATCH,
+            EscrowError::InvalidBatch
+        );
+        let caller = ctx.accounts.caller.key();
+        let finalizer = ctx.accounts.config.finalizer;
+
+        let mut closed = 0u8;
+        for group in accounts.chunks(3) {
+            let escrow = Account::<Escrow>::try_from(&group[0])?;
+            let vault_seeds = &[b"vault".as_ref(), group[0].key.as_ref(), &[escrow.vault_bump]];
+            let expected = Pubkey::create_program_address(vault_seeds, &crate::ID)
+                .map_err(|_| error!(EscrowError::InvalidBatch))?;
+            require_keys_eq!(group[1].key(), expected, EscrowError::InvalidBatch);
+            require_keys_eq!(group[2].key(), escrow.buyer, EscrowError::InvalidBatch);
+            require!(
+                caller == escrow.buyer || (finalizer != Pubkey::default() && caller == finalizer),
+                EscrowError::Unauthorized
+            );
+
+            if !escrow.is_terminal() || !escrow.assets.is_empty() || escrow.collateral_amount > 0 {
+                continue;
+            }
+            // A vault closed by `close_vault_only` leaves just the record
+            let mut vault = if escrow.vault_closed {
+                None
+            } else {
+                let vault = InterfaceAccount::<TokenAccount>::try_from(&group[1])?;
+                if vault.amount > 0 {
+                    continue;
+                }
+                Some(vault)
+            };
+            close_escrow_accounts(
+                &ctx.accounts.token_program,
+                vault.as_mut(),
+                &escrow,
+                group[2].clone(),
+            )?;
+            closed += 1;
+        }
+
+        msg!("Closed {} of {} escrows", closed, accounts.len() / 3);
+        Ok(closed)
+    }
+
+    /// Get escrow: return the deserialized escrow via return data, for clients
+    /// that can't fetch and decode the account themselves. Read-only; call it
+    /// through simulation. Only an escrow with most of its optional lists
+    /// filled can outgrow return data; it fails with `ReturnDataTooLarge`.
+    pub fn get_escrow(ctx: Context<GetEscrow>) -> Result<Escrow> {
+        let escrow = ctx.accounts.escrow.clone().into_inner();
+        let size = borsh::to_vec(&escrow)?.len();
+        require!(size <= MAX_RETURN_DATA, EscrowError::ReturnDataTooLarge);
+        Ok(escrow)
+    }
+
+    /// Get status label: a short human-readable status for minimal clients,
+    /// e.g. "Locked, 3d 4h remaining" or "Released". Read-only; call it
+    /// through simulation. At most `MAX_STATUS_LABEL_LEN` bytes.
+    pub fn get_status_label(ctx: Context<GetEscrow>) -> Result<String> {
+        let now = current_time(&ctx)?;
+        let mut label = ctx.accounts.escrow.status_label(now);
+        label.truncate(MAX_STATUS_LABEL_LEN);
+        Ok(label)
+    }
+
+    /// Get vault balance: return the vault's current token balance via return
+    /// data. Authoritative where `escrow.amount` may drift from the vault
+    /// (partial releases, top-ups, transfer-fee mints). Read-only; call it
+    /// through simulation.
+    pub fn get_vault_balance(ctx: Context<GetVaultBalance>) -> Result<u64> {
+        Ok(ctx.accounts.vault.amount)
+    }
+
+    /// Snapshot: record the vault's current balance and the time on the
+    /// escrow and emit a `Snapshot` event, for auditors reconciling vaults
+    /// against escrows. Permissionless; touches nothing else.
+    pub fn snapshot(ctx: Context<TakeSnapshot>) -> Result<()> {
+        let now = current_time(&ctx)?;
+        let amount = ctx.accounts.vault.amount;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.last_snapshot_amount = amount;
+        escrow.last_snapshot_at = now;
+
+        let seq = escrow.next_event_seq();
+        emit!(Snapshot {
+            escrow: escrow.key(),
+            amount,
+            at: now,
+            seq,
+        });
+
+        msg!("Snapshot: {} tokens in the vault", amount);
+        Ok(())
+    }
+
+    /// Verify preimage: return via return data whether the SHA-256 of
+    /// `preimage` matches the escrow's `hash_lock` (always false without
+    /// one), so HTLC counterparties can check a revealed secret before acting
+    /// on their chain. Moves no funds; call it through simulation.
+    pub fn verify_preimage(ctx: Context<GetEscrow>, preimage: Vec<u8>) -> Result<bool> {
+        let hash_lock = ctx.accounts.escrow.hash_lock;
+        Ok(hash_lock != [0; 32] && hash(&preimage).to_bytes() == hash_lock)
+    }
+
+    /// Derive vault: return the canonical vault PDA and bump for `escrow` via
+    /// return data. The vault is a custom-seeded token account, not an ATA,
+    /// so SDKs should use this instead of deriving it themselves. Reads no
+    /// accounts; call it through simulation.
+    pub fn derive_vault(ctx: Context<DeriveVault>, escrow: Pubkey) -> Result<VaultAddress> {
+        let (address, bump) =
+            Pubkey::find_program_address(&[b"vault", escrow.as_ref()], ctx.program_id);
+        Ok(VaultAddress { address, bump })
+    }
+
+    /// Verify bumps: recompute the canonical bumps of the escrow and vault
+    /// PDAs and repair the stored `bump`/`vault_bump` if they drifted.
+    /// Permissionless; fails with `BumpMismatch` if either address does not
+    /// derive from the escrow's seeds at all.
+    pub fn verify_bumps(ctx: Context<VerifyBumps>) -> Result<()> {
+        let escrow_key = ctx.accounts.escrow.key();
+        let escrow = &mut ctx.accounts.escrow;
+
+        let (expected_escrow, bump) = Pubkey::find_program_address(
+            &[
+                b"escrow",
+                escrow.buyer.as_ref(),
+                escrow.seller.as_ref(),
+                escrow.mint.as_ref(),
+            ],
+            ctx.program_id,
+        );
+        require_keys_eq!(expected_escrow, escrow_key, EscrowError::BumpMismatch);
+
+        let (expected_vault, vault_bump) =
+            Pubkey::find_program_address(&[b"vault", escrow_key.as_ref()], ctx.program_id);
+        require_keys_eq!(
+            expected_vault,
+            ctx.accounts.vault.key(),
+            EscrowError::BumpMismatch
+        );
+
+        if escrow.bump != bump || escrow.vault_bump != vault_bump {
+            msg!(
+                "Repaired bumps: escrow {} -> {}, vault {} -> {}",
+                escrow.bump,
+                bump,
+                escrow.vault_bump,
+                vault_bump
+            );
+            escrow.bump = bump;
+            escrow.vault_bump = vault_bump;
+        }
+        Ok(())
+    }
+
+    /// Create the global `Config` PDA. The signer becomes the admin.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        params.validate(MAX_DEADLINE_SECS)?;
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.bump = ctx.bumps.config;
+        config.max_deadline_secs = MAX_DEADLINE_SECS as u32;
+        config.apply(&params);
+
+        msg!("Config initialized: fee {} bps", params.fee_bps);
+        Ok(())
+    }
+
+    /// Update the global `Config`. Admin only.
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        params.validate(i64::from(ctx.accounts.config.max_deadline_secs))?;
+
+        let config = &mut ctx.accounts.config;
+        config.apply(&params);
+
+        msg!("Config updated: fee {} bps", params.fee_bps);
+        Ok(())
+    }
+
+    /// Set the longest escrow duration `initialize` (and `reopen`) accepts,
+    /// in seconds. Admin only. Must be between the config's
+    /// `min_deadline_secs` (else `DeadlineTooSoon`) and
+    /// `MAX_DEADLINE_LIMIT_SECS` (else `DeadlineTooFar`). Existing escrows
+    /// keep their deadlines.
+    pub fn set_max_deadline(ctx: Context<UpdateConfig>, secs: u32) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            i64::from(secs) <= MAX_DEADLINE_LIMIT_SECS,
+            EscrowError::DeadlineTooFar
+        );
+        require!(
+            secs > 0 && secs >= config.min_deadline_secs,
+            EscrowError::DeadlineTooSoon
+        );
+
+        config.max_deadline_secs = secs;
+
+        msg!("Max escrow duration set to {} seconds", secs);
+        Ok(())
+    }
+
+    /// Add or remove a buyer from the allowlist used when `config.permissioned`
+    /// is set. Admin only.
+    pub fn set_approved_buyer(
+        ctx: Context<SetApprovedBuyer>,
+        buyer: Pubkey,
+        active: bool,
+    ) -> Result<()> {
+        let approved_buyer = &mut ctx.accounts.approved_buyer;
+        approved_buyer.buyer = buyer;
+        approved_buyer.active = active;
+        approved_buyer.bump = ctx.bumps.approved_buyer;
+
+        msg!("Buyer {} approved: {}", buyer, active);
+        Ok(())
+    }
+
+    /// Add or remove a seller from the allowlist used when
+    /// `config.permissioned_sellers` is set. Admin only.
+    pub fn set_approved_seller(
+        ctx: Context<SetApprovedSeller>,
+        seller: Pubkey,
+        active: bool,
+    ) -> Result<()> {
+        let approved_seller = &mut ctx.accounts.approved_seller;
+        approved_seller.seller = seller;
+        approved_seller.active = active;
+        approved_seller.bump = ctx.bumps.approved_seller;
+
+        msg!("Seller {} approved: {}", seller, active);
+        Ok(())
+    }
+
+    /// Cap how much of `mint` all escrows together may hold (0 = unlimited).
+    /// Admin only.
+    pub fn set_mint_policy(ctx: Context<SetMintPolicy>, max_tvl: u64) -> Result<()> {
+        let mint_policy = &mut ctx.accounts.mint_policy;
+        mint_policy.mint = ctx.accounts.mint.key();
+        mint_policy.max_tvl = max_tvl;
+        mint_policy.bump = ctx.bumps.mint_policy;
+
+        msg!(
+            "Mint {} capped at {} (currently {})",
+            mint_policy.mint,
+            max_tvl,
+            mint_policy.tvl
+        );
+        Ok(())
+    }
+
+    /// Set the overridden clock read by `current_time`. Only compiled with
+    /// the `test-clock` feature; never part of a production build.
+    #[cfg(feature = "test-clock")]
+    pub fn set_test_clock(ctx: Context<SetTestClock>, unix_timestamp: i64) -> Result<()> {
+        let test_clock = &mut ctx.accounts.test_clock;
+        test_clock.unix_timestamp = unix_timestamp;
+        test_clock.bump = ctx.bumps.test_clock;
+
+        msg!("Test clock set to {}", unix_timestamp);
+        Ok(())
+    }
+
+    /// Overwrite the stored bumps so tests can exercise `verify_bumps`. Only
+    /// compiled with the `test-bumps` feature; never part of a production build.
+    #[cfg(feature = "test-bumps")]
+    pub fn set_test_bumps(ctx: Context<SetTestBumps>, bump: u8, vault_bump: u8) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.bump = bump;
+        escrow.vault_bump = vault_bump;
+        Ok(())
+    }
+
+    /// Overwrite the escrowed amount so tests can reach states the normal
+    /// flow can't. Only compiled with the `test-amount` feature; never part
+    /// of a production build.
+    #[cfg(feature = "test-amount")]
+    pub fn set_test_amount(ctx: Context<SetTestAmount>, amount: u64) -> Result<()> {
+        ctx.accounts.escrow.amount = amount;
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Returns true if `mint` is the wrapped-SOL mint of either token program.
+fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::ID || *mint == spl_token_2022::native_mint::ID
+}
+
+/// Current unix time from the `Clock` sysvar.
+#[cfg(not(feature = "test-clock"))]
+fn current_time<T: Bumps>(_ctx: &Context<T>) -> Result<i64> {
+    Ok(Clock::get()?.unix_timestamp)
+}
+
+/// Current unix time. With `test-clock`, a `TestClock` PDA passed in
+/// `remaining_accounts` overrides the `Clock` sysvar.
+#[cfg(feature = "test-clock")]
+fn current_time<T: Bumps>(ctx: &Context<T>) -> Result<i64> {
+    let (address, _) = Pubkey::find_program_address(&[b"test_clock"], ctx.program_id);
+    match ctx.remaining_accounts.iter().find(|info| info.key() == address) {
+        Some(info) => {
+            require_keys_eq!(*info.owner, *ctx.program_id, ErrorCode::AccountOwnedByWrongProgram);
+            let test_clock = TestClock::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            Ok(test_clock.unix_timestamp)
+        }
+        None => Ok(Clock::get()?.unix_timestamp),
+    }
+}
+
+/// Defensive check that a freshly created account can't be garbage-collected.
+/// `init` funds accounts for rent exemption, so this should never fire.
+fn require_rent_exempt(info: &AccountInfo) -> Result<()> {
+    require!(
+        Rent::get()?.is_exempt(info.lamports(), info.data_len()),
+        EscrowError::NotRentExempt
+    );
+    Ok(())
+}
+
+/// When `config.reject_overlapping_destinations` is set, fails unless the
+/// seller, fee, referrer, and buyer token accounts passed to `release` are
+/// all different. Otherwise overlaps are allowed: each payout is its own
+/// transfer, so an account passed twice simply receives both amounts.
+fn require_distinct_destinations(accounts: &Release) -> Result<()> {
+    if !accounts.config.reject_overlapping_destinations {
+        return Ok(());
+    }
+    let mut destinations = vec![accounts.seller_token_account.key()];
+    destinations.extend(accounts.fee_token_account.as_ref().map(|a| a.key()));
+    destinations.extend(accounts.referrer_token_account.as_ref().map(|a| a.key()));
+    destinations.extend(accounts.buyer_token_account.as_ref().map(|a| a.key()));
+    for (i, key) in destinations.iter().enumerate() {
+        require!(
+            !destinations[..i].contains(key),
+            EscrowError::OverlappingDestinations
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless the vault is still its own authority. Nothing in this program
+/// changes it, so a mismatch means the vault was tampered with.
+fn require_vault_authority(vault: &InterfaceAccount<TokenAccount>) -> Result<()> {
+    require_keys_eq!(vault.owner, vault.key(), EscrowError::VaultAuthorityChanged);
+    Ok(())
+}
+
+/// Fails unless `account` is the seller's associated token account for `mint`
+/// and already exists, so the release has somewhere to go.
+fn require_seller_ata(
+    account: Option<&UncheckedAccount>,
+    seller: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<()> {
+    let account = account.ok_or(EscrowError::SellerAccountMissing)?;
+    let expected = get_associated_token_address_with_program_id(seller, mint, token_program);
+    require_keys_eq!(account.key(), expected, EscrowError::SellerAccountMissing);
+    require!(
+        account.owner == token_program && !account.data_is_empty(),
+        EscrowError::SellerAccountMissing
+    );
+    Ok(())
+}
+
+/// Fails with `NotCanonicalAta` unless `account` is the buyer's associated
+/// token account for `mint`.
+fn require_buyer_ata(
+    account: &Pubkey,
+    buyer: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<()> {
+    let expected = get_associated_token_address_with_program_id(buyer, mint, token_program);
+    require_keys_eq!(*account, expected, EscrowError::NotCanonicalAta);
+    Ok(())
+}
+
+/// A deadline must be in the future and within `max_deadline_secs` of `now`.
+fn validate_deadline(deadline: i64, now: i64, max_deadline_secs: i64) -> Result<()> {
+    require!(deadline > now, EscrowError::DeadlineInPast);
+    require!(
+        deadline <= now + max_deadline_secs,
+        EscrowError::DeadlineTooFar
+    );
+    Ok(())
+}
+
+/// Format a positive number of seconds as its two largest units, e.g.
+/// "3d 4h", "2h 5m", "5m 3s", or "42s".
+fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3_600, secs % 3_600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Checks that the deposited asset fits the requested escrow kind.
+fn validate_kind(kind: EscrowKind, mint: &InterfaceAccount<Mint>, amount: u64) -> Result<()> {
+    match kind {
+        EscrowKind::Native => {
+            require!(is_native_mint(&mint.key()), EscrowError::NotNativeMint)
+        }
+        // A single indivisible token
+        EscrowKind::Nft => require!(
+            mint.decimals == 0 && amount == 1,
+            EscrowError::WrongEscrowKind
+        ),
+        EscrowKind::Standard
+        | EscrowKind::Swap
+        | EscrowKind::Milestone
+        | EscrowKind::Scheduled => {}
+    }
+    Ok(())
+}
+
+/// Milestones are required for (and only allowed on) `Milestone` escrows,
+/// bounded by `MAX_MILESTONES`, and must add up to the deposit.
+fn validate_milestones(kind: EscrowKind, milestones: &[u64], amount: u64) -> Result<()> {
+    if kind != EscrowKind::Milestone {
+        require!(milestones.is_empty(), EscrowError::WrongEscrowKind);
+        return Ok(());
+    }
+    require!(!milestones.is_empty(), EscrowError::InvalidMilestone);
+    require!(
+        milestones.len() <= MAX_MILESTONES,
+        EscrowError::TooManyMilestones
+    );
+    require!(milestones.iter().all(|&m| m > 0), EscrowError::ZeroAmount);
+
+    let mut total: u64 = 0;
+    for &m in milestones {
+        total = total.checked_add(m).ok_or(EscrowError::MathOverflow)?;
+    }
+    require_eq!(total, amount, EscrowError::MilestoneSumMismatch);
+    Ok(())
+}
+
+/// A schedule is required for (and only allowed on) `Scheduled` escrows,
+/// bounded by `MAX_SCHEDULED_PAYMENTS`, ordered by unlock time, and must add
+/// up to the deposit. Installments must unlock by the deadline (if any),
+/// after which the escrow can no longer be released.
+fn validate_schedule(
+    kind: EscrowKind,
+    schedule: &[Installment],
+    amount: u64,
+    deadline: i64,
+) -> Result<()> {
+    if kind != EscrowKind::Scheduled {
+        require!(schedule.is_empty(), EscrowError::WrongEscrowKind);
+        return Ok(());
+    }
+    require!(!schedule.is_empty(), EscrowError::InvalidSchedule);
+    require!(
+        schedule.len() <= MAX_SCHEDULED_PAYMENTS,
+        EscrowError::TooManyInstallments
+    );
+    require!(schedule.iter().all(|i| i.amount > 0), EscrowError::ZeroAmount);
+    require!(
+        schedule.windows(2).all(|w| w[0].unlock_at <= w[1].unlock_at)
+            && (deadline == NO_DEADLINE || schedule.iter().all(|i| i.unlock_at < deadline)),
+        EscrowError::InvalidSchedule
+    );
+
+    let mut total: u64 = 0;
+    for installment in schedule {
+        total = total
+            .checked_add(installment.amount)
+            .ok_or(EscrowError::MathOverflow)?;
+    }
+    require_eq!(total, amount, EscrowError::ScheduleSumMismatch);
+    Ok(())
+}
+
+/// An arbiter panel holds at most `MAX_ARBITERS` distinct keys, excluding the
+/// parties, and replaces the single arbiter rather than adding to it.
+fn validate_arbiters(
+    arbiters: &[Pubkey],
+    arbiter: Pubkey,
+    buyer: Pubkey,
+    seller: Pubkey,
+) -> Result<()> {
+    if arbiters.is_empty() {
+        return Ok(());
+    }
+    require!(
+        arbiters.len() <= MAX_ARBITERS && arbiter == Pubkey::default(),
+        EscrowError::InvalidArbiter
+    );
+    for (i, key) in arbiters.iter().enumerate() {
+        require!(
+            *key != Pubkey::default() && *key != buyer && *key != seller,
+            EscrowError::InvalidArbiter
+        );
+        require!(!arbiters[..i].contains(key), EscrowError::InvalidArbiter);
+    }
+    Ok(())
+}
+
+/// Release approvers come with one non-zero weight each: at most
+/// `MAX_APPROVERS` distinct keys, and a threshold they can reach together.
+fn validate_approvers(approvers: &[Pubkey], weights: &[u16], threshold: u16) -> Result<()> {
+    if approvers.is_empty() {
+        require!(weights.is_empty(), EscrowError::InvalidApprovers);
+        return Ok(());
+    }
+    require!(
+        approvers.len() <= MAX_APPROVERS && weights.len() == approvers.len(),
+        EscrowError::InvalidApprovers
+    );
+    for (i, key) in approvers.iter().enumerate() {
+        require!(
+            *key != Pubkey::default() && !approvers[..i].contains(key),
+            EscrowError::InvalidApprovers
+        );
+    }
+    require!(weights.iter().all(|&w| w > 0), EscrowError::InvalidApprovers);
+    let total: u32 = weights.iter().map(|&w| u32::from(w)).sum();
+    require!(
+        threshold > 0 && u32::from(threshold) <= total,
+        EscrowError::InvalidApprovers
+    );
+    Ok(())
+}
+
+/// Basis-point denominator for fee calculations.
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Protocol fee on `amount` at `fee_bps`, rounded per `rounding`.
+/// Never exceeds `amount` as long as `fee_bps <= 10_000`.
+fn compute_fee(amount: u64, fee_bps: u16, rounding: FeeRounding) -> Result<u64> {
+    let product = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .ok_or(EscrowError::MathOverflow)?;
+    let fee = match rounding {
+        FeeRounding::Down => product / BPS_DENOMINATOR,
+        FeeRounding::Up => product
+            .checked_add(BPS_DENOMINATOR - 1)
+            .ok_or(EscrowError::MathOverflow)?
+            / BPS_DENOMINATOR,
+        FeeRounding::Nearest => product
+            .checked_add(BPS_DENOMINATOR / 2)
+            .ok_or(EscrowError::MathOverflow)?
+            / BPS_DENOMINATOR,
+    };
+    u64::try_from(fee).map_err(|_| error!(EscrowError::MathOverflow))
+}
+
+/// Read `(price, exponent)` from a fully verified Pyth `PriceUpdateV2`
+/// account, failing if it was published more than `MAX_ORACLE_AGE_SECS`
+/// before `now`.
+fn read_oracle_price(oracle: &AccountInfo, now: i64) -> Result<(i64, i32)> {
+    require_keys_eq!(*oracle.owner, PYTH_RECEIVER_ID, EscrowError::OraclePriceError);
+    let data = oracle.try_borrow_data()?;
+    require!(
+        data.len() >= 41 && data[..8] == PRICE_UPDATE_DISCRIMINATOR,
+        EscrowError::OraclePriceError
+    );
+
+    // discriminator, write_authority, verification_level (1 = Full; a
+    // partially verified update carries an extra byte and is rejected)
+    require!(data[40] == 1, EscrowError::OraclePriceError);
+    // price_message: feed_id, then price, conf, exponent, publish_time
+    let (price, _conf, exponent, publish_time) =
+        <(i64, u64, i32, i64)>::deserialize(&mut data.get(73..).unwrap_or_default())
+            .map_err(|_| error!(EscrowError::OraclePriceError))?;
+
+    require!(price > 0, EscrowError::OraclePriceError);
+    require!(
+        now.saturating_sub(publish_time) <= MAX_ORACLE_AGE_SECS,
+        EscrowError::StaleOracle
+    );
+    Ok((price, exponent))
+}
+
+/// Raw token amount worth `reference_value` (in `REFERENCE_DECIMALS`) at a
+/// price of `price * 10^exponent` per whole token, rounded down. Saturates at
+/// `u64::MAX`; callers cap it by the deposit anyway.
+fn quote_token_amount(reference_value: u64, price: i64, exponent: i32, decimals: u8) -> Result<u64> {
+    let pow10 = |exp: u32| 10u128.checked_pow(exp).ok_or(EscrowError::MathOverflow);
+
+    let mut numerator = (reference_value as u128)
+        .checked_mul(pow10(decimals as u32)?)
+        .ok_or(EscrowError::MathOverflow)?;
+    let mut denominator = (price as u128)
+        .checked_mul(pow10(REFERENCE_DECIMALS)?)
+        .ok_or(EscrowError::MathOverflow)?;
+    if exponent < 0 {
+        numerator = numerator
+            .checked_mul(pow10(exponent.unsigned_abs())?)
+            .ok_or(EscrowError::MathOverflow)?;
+    } else {
+        denominator = denominator
+            .checked_mul(pow10(exponent as u32)?)
+            .ok_or(EscrowError::MathOverflow)?;
+    }
+    Ok(u64::try_from(numerator / denominator).unwrap_or(u64::MAX))
+}
+
+/// Pay `amount` out of the vault to `to`, first deducting the protocol fee
+/// (unless the escrow's fee is waived) and sending it to the fee recipient, or across `config.fee_split` into
+/// the `fee_split` token accounts. Returns `(fee, paid)`.
+///
+/// With `sweep` set this is the final transfer of a settlement: `to` receives
+/// whatever the vault actually holds after the fee instead of `amount - fee`,
+/// so the vault always ends at exactly zero.
+#[allow(clippy::too_many_arguments)]
+fn pay_with_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    config: &Config,
+    fee_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    fee_split: &[AccountInfo<'info>],
+    to: AccountInfo<'info>,
+    amount: u64,
+    sweep: bool,
+) -> Result<(u64, u64)> {
+    let fee = charge_fee(
+        token_program,
+        mint,
+        vault,
+        escrow,
+        config,
+        fee_token_account,
+        fee_split,
+        amount,
+    )?;
+    let net = if sweep {
+        vault.amount.checked_sub(fee).ok_or(EscrowError::MathOverflow)?
+    } else {
+        amount.checked_sub(fee).ok_or(EscrowError::MathOverflow)?
+    };
+
+    transfer_from_vault(token_program, mint, vault, escrow, to, net)?;
+    Ok((fee, net))
+}
+
+/// Transfer the protocol fee on `amount` out of the vault, to the fee split
+/// or `fee_token_account`. Returns the fee (0 for fee-waived escrows).
+#[allow(clippy::too_many_arguments)]
+fn charge_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    config: &Config,
+    fee_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    fee_split: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<u64> {
+    let fee = if escrow.fee_waived {
+        0
+    } else {
+        compute_fee(amount, config.fee_bps, config.fee_rounding)?
+    };
+
+    if fee > 0 && !config.fee_split.is_empty() {
+        split_fee(token_program, mint, vault, escrow, config, fee_split, fee)?;
+    } else if fee > 0 {
+        let fee_token_account = fee_token_account.ok_or(EscrowError::MissingFeeAccount)?;
+        transfer_from_vault(
+            token_program,
+            mint,
+            vault,
+            escrow,
+            fee_token_account.to_account_info(),
+            fee,
+        )?;
+    }
+    Ok(fee)
+}
+
+/// Send `config.cancel_fee_bps` of the vault to the fee recipient ahead of a
+/// cancel's refund, which then returns the rest. Returns the fee (0 for
+/// fee-waived escrows).
+fn charge_cancel_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &mut InterfaceAccount<'info, TokenAccount>,
+    escrow: &mut Account<'info, Escrow>,
+    config: &Config,
+    fee_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+) -> Result<u64> {
+    if escrow.fee_waived || config.cancel_fee_bps == 0 {
+        return Ok(0);
+    }
+    let fee = compute_fee(vault.amount, config.cancel_fee_bps, config.fee_rounding)?;
+    if fee == 0 {
+        return Ok(0);
+    }
+
+    let fee_token_account = fee_token_account.ok_or(EscrowError::MissingFeeAccount)?;
+    transfer_from_vault(
+        token_program,
+        mint,
+        vault,
+        escrow,
+        fee_token_account.to_account_info(),
+        fee,
+    )?;
+    vault.reload()?;
+    escrow.amount = escrow.amount.saturating_sub(fee);
+    Ok(fee)
+}
+
+/// Pay the escrow's `relayer_rebate` out of the lamports reserved on the
+/// escrow PDA at `initialize`. Skipped when nothing is owed or the relayer's
+/// account isn't passed (the reserve then returns to the buyer when the
+/// escrow closes). Fails with `InsufficientRebate` if paying would leave the
+/// escrow below rent exemption.
+fn pay_relayer_rebate(
+    escrow: &mut Account<Escrow>,
+    relayer: Option<&UncheckedAccount>,
+) -> Result<()> {
+    let rebate = escrow.relayer_rebate;
+    let Some(relayer) = relayer else {
+        return Ok(());
+    };
+    if rebate == 0 {
+        return Ok(());
+    }
+
+    let info = escrow.to_account_info();
+    let rent_floor = Rent::get()?.minimum_balance(info.data_len());
+    require!(
+        info.lamports().saturating_sub(rent_floor) >= rebate,
+        EscrowError::InsufficientRebate
+    );
+    escrow.sub_lamports(rebate)?;
+    relayer.add_lamports(rebate)?;
+    escrow.relayer_rebate = 0;
+    Ok(())
+}
+
+/// Charge the protocol fee, then swap the rest of the vault into the
+/// escrow's `target_mint` through the config's `swap_program`, paying the
+/// seller directly. `accounts` (after the fee split's) starts with `[swap
+/// program, seller's target-mint token account]`; the rest are forwarded to
+/// the swap as-is, with the vault signing. Returns the fee and the amount
+/// swapped; fails with `SlippageExceeded` if the seller got less than
+/// `min_out`.
+#[allow(clippy::too_many_arguments)]
+fn swap_with_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    config: &Config,
+    fee_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<(u64, u64)> {
+    let fee = charge_fee(
+        token_program,
+        mint,
+        vault,
+        escrow,
+        config,
+        fee_token_account,
+        accounts,
+        amount,
+    )?;
+    let amount_in = vault.amount.checked_sub(fee).ok_or(EscrowError::MathOverflow)?;
+
+    let [swap_program, seller_account, route @ ..] = accounts
+        .get(config.fee_split.len()..)
+        .unwrap_or(&[])
+    else {
+        return err!(EscrowError::InvalidSwapAccounts);
+    };
+    require!(
+        config.swap_program != Pubkey::default()
+            && swap_program.key() == config.swap_program
+            && swap_program.executable,
+        EscrowError::InvalidSwapAccounts
+    );
+    let before = target_balance(seller_account, escrow)?;
+
+    let vault_key = vault.key();
+    let mut data = SWAP_DISCRIMINATOR.to_vec();
+    (amount_in, escrow.min_out).serialize(&mut data)?;
+    let ix = Instruction {
+        program_id: swap_program.key(),
+        accounts: route
+            .iter()
+            .map(|info| AccountMeta {
+                pubkey: info.key(),
+                is_signer: info.is_signer || info.key() == vault_key,
+                is_writable: info.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let escrow_key = escrow.key();
+    let seeds = &[b"vault".as_ref(), escrow_key.as_ref(), &[escrow.vault_bump]];
+    invoke_signed(&ix, route, &[&seeds[..]])?;
+
+    let out = target_balance(seller_account, escrow)?
+        .checked_sub(before)
+        .ok_or(EscrowError::MathOverflow)?;
+    require!(out >= escrow.min_out, EscrowError::SlippageExceeded);
+
+    log!(escrow, "Swapped {} tokens into {} of {}", amount_in, out, escrow.target_mint);
+    Ok((fee, amount_in))
+}
+
+/// Balance of the seller's `target_mint` token account. Fails with
+/// `InvalidSwapAccounts` if `account` isn't one.
+fn target_balance(account: &AccountInfo, escrow: &Escrow) -> Result<u64> {
+    require!(
+        *account.owner == spl_token::ID || *account.owner == spl_token_2022::ID,
+        EscrowError::InvalidSwapAccounts
+    );
+    let token_account = TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    require!(
+        token_account.mint == escrow.target_mint && token_account.owner == escrow.seller,
+        EscrowError::InvalidSwapAccounts
+    );
+    Ok(token_account.amount)
+}
+
+/// Accounts after the basket's triples in `remaining_accounts`: the fee
+/// split's token accounts, one per `config.fee_split` entry.
+fn fee_split_accounts<'a, 'info>(
+    escrow: &Escrow,
+    remaining: &'a [AccountInfo<'info>],
+) -> &'a [AccountInfo<'info>] {
+    remaining.get(escrow.assets.len() * 3..).unwrap_or(&[])
+}
+
+/// Send `fee` to the config's fee recipients, each getting its `bps` share of
+/// `fee_bps`. The last recipient also takes the rounding dust.
+fn split_fee<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    config: &Config,
+    accounts: &[AccountInfo<'info>],
+    fee: u64,
+) -> Result<()> {
+    require!(
+        accounts.len() >= config.fee_split.len(),
+        EscrowError::FeeSplitMismatch
+    );
+
+    let mut remaining = fee;
+    for (i, (recipient, to)) in config.fee_split.iter().zip(accounts).enumerate() {
+        let destination = TokenAccount::try_deserialize(&mut &to.try_borrow_data()?[..])?;
+        require!(
+            *to.owner == token_program.key()
+                && destination.mint == escrow.mint
+                && destination.owner == recipient.account,
+            EscrowError::FeeSplitMismatch
+        );
+        let share = if i + 1 == config.fee_split.len() {
+            remaining
+        } else {
+            u64::try_from(
+                u128::from(fee) * u128::from(recipient.bps) / u128::from(config.fee_bps),
+            )
+            .map_err(|_| EscrowError::MathOverflow)?
+        };
+        transfer_from_vault(token_program, mint, vault, escrow, to.clone(), share)?;
+        remaining -= share;
+    }
+    Ok(())
+}
+
+/// Attach an SPL Memo naming the escrow and outcome when the config requires
+/// it (e.g. for travel-rule compliance).
+fn write_memo<'info>(
+    config: &Config,
+    memo_program: Option<&Program<'info, Memo>>,
+    escrow: &Account<'info, Escrow>,
+    outcome: SettlementOutcome,
+) -> Result<()> {
+    if !config.require_memo {
+        return Ok(());
+    }
+    let memo_program = memo_program.ok_or(EscrowError::MissingMemoProgram)?;
+
+    let label = match outcome {
+        SettlementOutcome::Released => "released",
+        SettlementOutcome::Cancelled => "cancelled",
+        SettlementOutcome::Refunded => "refunded",
+    };
+    let text = format!("escrow:{}:{}", escrow.key(), label);
+    let cpi_ctx = CpiContext::new(memo_program.to_account_info(), BuildMemo {});
+    memo::build_memo(cpi_ctx, text.as_bytes())
+}
+
+/// Fails unless the current top-level instruction belongs to the escrow's
+/// `seller_program`, i.e. we were reached by CPI from the program controlling
+/// the seller PDA. No-op when no seller program is set.
+fn require_seller_program(escrow: &Escrow, instructions: &AccountInfo) -> Result<()> {
+    if escrow.seller_program == Pubkey::default() {
+        return Ok(());
+    }
+    let current = get_instruction_relative(0, instructions)?;
+    require_keys_eq!(
+        current.program_id,
+        escrow.seller_program,
+        EscrowError::UnauthorizedProgram
+    );
+    Ok(())
+}
+
+/// Make sure the release can pay `seller_token_account`. For escrows with
+/// `create_seller_ata`, a missing seller ATA is created first, paid by
+/// `payer`. Fails unless the account is then a token account for the mint.
+fn prepare_seller_token_account(accounts: &Release) -> Result<()> {
+    let account = &accounts.seller_token_account;
+    if accounts.escrow.create_seller_ata && account.data_is_empty() {
+        let (Some(seller), Some(payer), Some(ata_program), Some(system_program)) = (
+            accounts.seller.as_ref(),
+            accounts.payer.as_ref(),
+            accounts.associated_token_program.as_ref(),
+            accounts.system_program.as_ref(),
+        ) else {
+            return err!(EscrowError::AtaCreationFailed);
+        };
+        let expected = get_associated_token_address_with_program_id(
+            &seller.key(),
+            &accounts.mint.key(),
+            &accounts.token_program.key(),
+        );
+        require_keys_eq!(account.key(), expected, EscrowError::AtaCreationFailed);
+
+        let cpi_accounts = associated_token::Create {
+            payer: payer.to_account_info(),
+            associated_token: account.to_account_info(),
+            authority: seller.to_account_info(),
+            mint: accounts.mint.to_account_info(),
+            system_program: system_program.to_account_info(),
+            token_program: accounts.token_program.to_account_info(),
+        };
+        associated_token::create(CpiContext::new(ata_program.to_account_info(), cpi_accounts))
+            .map_err(|_| error!(EscrowError::AtaCreationFailed))?;
+    }
+
+    require_keys_eq!(
+        *account.owner,
+        accounts.token_program.key(),
+        ErrorCode::ConstraintTokenTokenProgram
+    );
+    let token_account = TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    // Checked against the escrow itself, not just the passed mint, so an
+    // exotic token-program mix-up can't pay out in the wrong mint
+    require_keys_eq!(token_account.mint, accounts.escrow.mint, EscrowError::MintMismatch);
+
+    // Paying into the vault, or into an account the escrow's PDAs own, would
+    // be a no-op or trap the funds
+    let vault = accounts.vault.key();
+    require_keys_neq!(account.key(), vault, EscrowError::InvalidDestination);
+    require!(
+        token_account.owner != vault && token_account.owner != accounts.escrow.key(),
+        EscrowError::InvalidDestination
+    );
+    // A settle authority signs releases too; it must not pick where the
+    // seller's payout goes
+    require_keys_eq!(
+        token_account.owner,
+        accounts.escrow.seller,
+        ErrorCode::ConstraintTokenOwner
+    );
+    Ok(())
+}
+
+/// Check one `release_group` leg's accounts against its escrow, pay the
+/// seller (minus the protocol fee), and mark the escrow `Released`.
+fn release_group_leg<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    config: &Config,
+    memo_program: Option<&Program<'info, Memo>>,
+    leg: &'info [AccountInfo<'info>],
+    escrow: &mut Account<'info, Escrow>,
+) -> Result<()> {
+    require_keys_eq!(
+        token_program.key(),
+        escrow.token_program,
+        EscrowError::TokenProgramMismatch
+    );
+    let vault_seeds = &[b"vault".as_ref(), leg[0].key.as_ref(), &[escrow.vault_bump]];
+    let expected = Pubkey::create_program_address(vault_seeds, &crate::ID)
+        .map_err(|_| error!(EscrowError::GroupMismatch))?;
+    require_keys_eq!(leg[1].key(), expected, EscrowError::GroupMismatch);
+    require_keys_eq!(leg[2].key(), escrow.mint, EscrowError::GroupMismatch);
+    let vault = InterfaceAccount::<TokenAccount>::try_from(&leg[1])?;
+    let mint = InterfaceAccount::<Mint>::try_from(&leg[2])?;
+    let mut mint_policy = Account::<MintPolicy>::try_from(&leg[3])?;
+    require_keys_eq!(mint_policy.mint, escrow.mint, EscrowError::GroupMismatch);
+    escrow.require_mint_unchanged(&mint)?;
+    require_vault_authority(&vault)?;
+
+    let seller_account = &leg[4];
+    require_keys_eq!(
+        *seller_account.owner,
+        token_program.key(),
+        ErrorCode::ConstraintTokenTokenProgram
+    );
+    let token_account = TokenAccount::try_deserialize(&mut &seller_account.try_borrow_data()?[..])?;
+    require_keys_eq!(token_account.mint, escrow.mint, EscrowError::MintMismatch);
+    require_keys_eq!(token_account.owner, escrow.seller, ErrorCode::ConstraintTokenOwner);
+
+    let fee_token_account = if leg[5].key() == crate::ID {
+        None
+    } else {
+        let account = InterfaceAccount::<TokenAccount>::try_from(&leg[5])?;
+        require_keys_eq!(account.mint, escrow.mint, ErrorCode::ConstraintTokenMint);
+        require_keys_eq!(account.owner, config.fee_recipient, ErrorCode::ConstraintTokenOwner);
+        Some(account)
+    };
+
+    let (fee, seller_amount) = pay_with_fee(
+        token_program,
+        &mint,
+        &vault,
+        escrow,
+        config,
+        fee_token_account.as_ref(),
+        &leg[GROUP_LEG_ACCOUNTS..],
+        seller_account.clone(),
+        escrow.amount,
+        true,
+    )?;
+
+    escrow.state = EscrowState::Released;
+    mint_policy.unlock(escrow.deposit);
+    write_memo(config, memo_program, escrow, SettlementOutcome::Released)?;
+    emit_settled(escrow, SettlementOutcome::Released, fee + seller_amount);
+
+    log!(
+        escrow,
+        "Group escrow released: {} tokens sent to seller, {} fee",
+        seller_amount,
+        fee
+    );
+    // Persist now: a later leg may share this leg's mint policy
+    escrow.exit(&crate::ID)?;
+    mint_policy.exit(&crate::ID)
+}
+
+/// Create the temporary wSOL account an `unwrap_on_release` escrow pays the
+/// seller through, at the `["unwrap", escrow]` PDA, with `payer` funding its
+/// rent. Fails with `MissingUnwrapAccount` without the accounts
+/// `unwrap_to_seller` needs.
+fn open_unwrap_account<'info>(
+    accounts: &Release<'info>,
+    bump: Option<u8>,
+) -> Result<AccountInfo<'info>> {
+    let (Some(account), Some(bump), Some(payer), Some(system_program), Some(_)) = (
+        accounts.unwrap_account.as_ref(),
+        bump,
+        accounts.payer.as_ref(),
+        accounts.system_program.as_ref(),
+        accounts.seller.as_ref(),
+    ) else {
+        return err!(EscrowError::MissingUnwrapAccount);
+    };
+
+    let escrow_key = accounts.escrow.key();
+    let seeds = &[b"unwrap".as_ref(), escrow_key.as_ref(), &[bump]];
+    create_pda_account(
+        payer,
+        account,
+        system_program,
+        anchor_spl::token::TokenAccount::LEN,
+        &accounts.token_program.key(),
+        seeds,
+    )?;
+
+    // The account is its own authority, like the vault
+    let cpi_accounts = InitializeAccount3 {
+        account: account.to_account_info(),
+        mint: accounts.mint.to_account_info(),
+        authority: account.to_account_info(),
+    };
+    token_interface::initialize_account3(CpiContext::new(
+        accounts.token_program.to_account_info(),
+        cpi_accounts,
+    ))?;
+    Ok(account.to_account_info())
+}
+
+/// Close the temporary wSOL account to `payer`, which gets its rent back
+/// along with the unwrapped lamports, and forward `amount` lamports from
+/// `payer` to the seller's wallet.
+fn unwrap_to_seller(accounts: &Release, bump: Option<u8>, amount: u64) -> Result<()> {
+    let (Some(account), Some(bump), Some(payer), Some(system_program), Some(seller)) = (
+        accounts.unwrap_account.as_ref(),
+        bump,
+        accounts.payer.as_ref(),
+        accounts.system_program.as_ref(),
+        accounts.seller.as_ref(),
+    ) else {
+        return err!(EscrowError::MissingUnwrapAccount);
+    };
+
+    let escrow_key = accounts.escrow.key();
+    let seeds = &[b"unwrap".as_ref(), escrow_key.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: account.to_account_info(),
+        destination: payer.to_account_info(),
+        authority: account.to_account_info(),
+    };
+    token_interface::close_account(CpiContext::new_with_signer(
+        accounts.token_program.to_account_info(),
+        cpi_accounts,
+        signer,
+    ))?;
+
+    let cpi_accounts = system_program::Transfer {
+        from: payer.to_account_info(),
+        to: seller.to_account_info(),
+    };
+    system_program::transfer(
+        CpiContext::new(system_program.to_account_info(), cpi_accounts),
+        amount,
+    )
+}
+
+/// Create the escrow's `Receipt` PDA, paid by the release's `payer`,
+/// recording `amount` released at `now`. Fails with `MissingReceiptAccount`
+/// without the receipt account, the payer, or the system program.
+fn write_receipt(accounts: &Release, bump: Option<u8>, amount: u64, now: i64) -> Result<()> {
+    let (Some(receipt), Some(bump), Some(payer), Some(system_program)) = (
+        accounts.receipt.as_ref(),
+        bump,
+        accounts.payer.as_ref(),
+        accounts.system_program.as_ref(),
+    ) else {
+        return err!(EscrowError::MissingReceiptAccount);
+    };
+
+    let escrow_key = accounts.escrow.key();
+    let seeds = &[b"receipt".as_ref(), escrow_key.as_ref(), &[bump]];
+    create_pda_account(
+        payer,
+        receipt,
+        system_program,
+        8 + Receipt::INIT_SPACE,
+        &crate::ID,
+        seeds,
+    )?;
+
+    let record = Receipt {
+        escrow: escrow_key,
+        buyer: accounts.escrow.buyer,
+        seller: accounts.escrow.seller,
+        mint: accounts.escrow.mint,
+        amount,
+        released_at: now,
+        bump,
+    };
+    record.try_serialize(&mut &mut receipt.try_borrow_mut_data()?[..])
+}
+
+/// Create the PDA `account`, signed for by `seeds`, with `space` bytes owned
+/// by `owner` and `payer` funding its rent. Unlike a bare `create_account`,
+/// this still works when someone has already sent lamports to the address:
+/// the balance is topped up to rent exemption, then allocated and assigned.
+fn create_pda_account<'info>(
+    payer: &Signer<'info>,
+    account: &UncheckedAccount<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let signer = &[seeds];
+    let program = system_program.to_account_info();
+    let rent = Rent::get()?.minimum_balance(space);
+    let balance = account.lamports();
+    if balance == 0 {
+        let cpi_accounts = system_program::CreateAccount {
+            from: payer.to_account_info(),
+            to: account.to_account_info(),
+        };
+        return system_program::create_account(
+            CpiContext::new_with_signer(program, cpi_accounts, signer),
+            rent,
+            space as u64,
+            owner,
+        );
+    }
+
+    let top_up = rent.saturating_sub(balance);
+    if top_up > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: payer.to_account_info(),
+            to: account.to_account_info(),
+        };
+        system_program::transfer(CpiContext::new(program.clone(), cpi_accounts), top_up)?;
+    }
+    let cpi_accounts = system_program::Allocate {
+        account_to_allocate: account.to_account_info(),
+    };
+    system_program::allocate(
+        CpiContext::new_with_signer(program.clone(), cpi_accounts, signer),
+        space as u64,
+    )?;
+    let cpi_accounts = system_program::Assign {
+        account_to_assign: account.to_account_info(),
+    };
+    system_program::assign(CpiContext::new_with_signer(program, cpi_accounts, signer), owner)
+}
+
+/// Release calls must be signed by the buyer or by the escrow's
+/// `settle_authority`.
+fn require_settler(
+    escrow: &Escrow,
+    buyer: &UncheckedAccount,
+    settle_authority: Option<&Signer>,
+) -> Result<()> {
+    if buyer.is_signer {
+        return Ok(());
+    }
+    let settler = settle_authority.ok_or(EscrowError::UnauthorizedSettler)?;
+    require_keys_eq!(
+        settler.key(),
+        escrow.settle_authority,
+        EscrowError::UnauthorizedSettler
+    );
+    Ok(())
+}
+
+/// When settlement runs inside a CPI, fails unless the transaction's
+/// top-level instruction belongs to the escrow's `authorized_caller`.
+/// Top-level calls and escrows without an `authorized_caller` pass.
+fn require_authorized_caller(escrow: &Escrow, instructions: Option<&UncheckedAccount>) -> Result<()> {
+    if escrow.authorized_caller == Pubkey::default()
+        || get_stack_height() == TRANSACTION_LEVEL_STACK_HEIGHT
+    {
+        return Ok(());
+    }
+    let instructions = instructions.ok_or(EscrowError::UnauthorizedCaller)?;
+    let current = get_instruction_relative(0, instructions)?;
+    require_keys_eq!(
+        current.program_id,
+        escrow.authorized_caller,
+        EscrowError::UnauthorizedCaller
+    );
+    Ok(())
+}
+
+/// For `strict_tx` escrows, fails unless every top-level instruction in the
+/// transaction belongs to this program, the compute budget program, or the
+/// escrow's `authorized_caller`. No-op otherwise.
+fn require_strict_tx(escrow: &Escrow, instructions: Option<&UncheckedAccount>) -> Result<()> {
+    if !escrow.strict_tx {
+        return Ok(());
+    }
+    let instructions = instructions.ok_or(EscrowError::UnexpectedInstruction)?;
+    // The sysvar's data starts with the number of instructions
+    let count = match instructions.try_borrow_data()?[..] {
+        [lo, hi, ..] => u16::from_le_bytes([lo, hi]),
+        _ => return err!(EscrowError::UnexpectedInstruction),
+    };
+    for index in 0..count {
+        let ix = load_instruction_at_checked(usize::from(index), instructions)?;
+        require!(
+            ix.program_id == crate::ID
+                || ix.program_id == COMPUTE_BUDGET_ID
+                || (escrow.authorized_caller != Pubkey::default()
+                    && ix.program_id == escrow.authorized_caller),
+            EscrowError::UnexpectedInstruction
+        );
+    }
+    Ok(())
+}
+
+/// For escrows with an `oracle_pubkey`, fails with `InvalidSignature` unless
+/// the release was entered through `release_with_signature`, which checked
+/// the oracle's signature first. No-op otherwise.
+fn require_oracle_signature(escrow: &Escrow, instructions: Option<&UncheckedAccount>) -> Result<()> {
+    if escrow.oracle_pubkey == Pubkey::default() {
+        return Ok(());
+    }
+    let instructions = instructions.ok_or(EscrowError::InvalidSignature)?;
+    let current = get_instruction_relative(0, instructions)?;
+    require!(
+        current.program_id == crate::ID
+            && current
+                .data
+                .starts_with(instruction::ReleaseWithSignature::DISCRIMINATOR),
+        EscrowError::InvalidSignature
+    );
+    Ok(())
+}
+
+/// Fails with `InvalidSignature` unless the instruction before the current
+/// one is an Ed25519 program instruction verifying exactly one `signature`
+/// by `signer` over `message`, all stored in that instruction's own data.
+/// The precompile has already checked the signature by the time we run, so
+/// only what it checked needs comparing.
+fn verify_ed25519_instruction(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<()> {
+    let ix = get_instruction_relative(-1, instructions)
+        .map_err(|_| EscrowError::InvalidSignature)?;
+    require_keys_eq!(ix.program_id, ED25519_PROGRAM_ID, EscrowError::InvalidSignature);
+
+    // Signature count and padding, then seven u16 offsets: signature, its
+    // instruction, public key, its instruction, message start, message
+    // length, its instruction (u16::MAX = this instruction)
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, EscrowError::InvalidSignature);
+    let offset = |i: usize| usize::from(u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]));
+    require!(
+        [1, 3, 6].iter().all(|&i| offset(i) == usize::from(u16::MAX)),
+        EscrowError::InvalidSignature
+    );
+    let field = |start: usize, len: usize| data.get(start..start + len);
+    require!(
+        field(offset(0), 64) == Some(&signature[..])
+            && field(offset(2), 32) == Some(signer.as_ref())
+            && field(offset(4), offset(5)) == Some(message),
+        EscrowError::InvalidSignature
+    );
+    Ok(())
+}
+
+/// Sweep the vault back to the buyer and mark the escrow `Cancelled` or
+/// `Refunded` per `outcome`. Returns the amount refunded. Whatever partial
+/// releases already paid the seller is gone from the vault and stays paid.
+///
+/// `remaining` holds the basket accounts, or for pooled escrows the
+/// contributors' token accounts, which get their share of the refund first.
+#[allow(clippy::too_many_arguments)]
+fn refund_buyer<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &mut Account<'info, Escrow>,
+    buyer_token_account: &InterfaceAccount<'info, TokenAccount>,
+    config: &Config,
+    memo_program: Option<&Program<'info, Memo>>,
+    callback_program: Option<&UncheckedAccount<'info>>,
+    remaining: &[AccountInfo<'info>],
+    outcome: SettlementOutcome,
+) -> Result<u64> {
+    // Sweep the vault's actual balance, not `escrow.amount`. Partial
+    // releases lowered both, so the vault must still cover what's locked.
+    let amount = vault.amount;
+    require!(amount >= escrow.amount, EscrowError::AmountExceedsLocked);
+    if escrow.strict_buyer_ata {
+        require_buyer_ata(
+            &buyer_token_account.key(),
+            &escrow.buyer,
+            &mint.key(),
+            &token_program.key(),
+        )?;
+    }
+
+    let contributed = refund_contributors(token_program, mint, vault, escrow, remaining, amount)?;
+
+    // Transfer the rest from vault → buyer's ATA
+    transfer_from_vault(
+        token_program,
+        mint,
+        vault,
+        escrow,
+        buyer_token_account.to_account_info(),
+        amount - contributed,
+    )?;
+    settle_basket(token_program, escrow, remaining, escrow.buyer)?;
+
+    // Update state
+    escrow.state = match outcome {
+        SettlementOutcome::Refunded => EscrowState::Refunded,
+        _ => EscrowState::Cancelled,
+    };
+
+    write_memo(config, memo_program, escrow, outcome)?;
+    notify_callback(escrow, callback_program, outcome)?;
+    emit_settled(escrow, outcome, amount);
+    Ok(amount)
+}
+
+/// Pay each contributor their share of `refund`, pro rata to what they put
+/// into `escrow.deposit`, into the token accounts in `accounts` (one per
+/// contributor, in order). Returns the total paid; the buyer gets the rest.
+fn refund_contributors<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    accounts: &[AccountInfo<'info>],
+    refund: u64,
+) -> Result<u64> {
+    require!(
+        accounts.len() >= escrow.contributors.len(),
+        EscrowError::InvalidContributorAccounts
+    );
+
+    let mut paid = 0u64;
+    for (contributor, to) in escrow.contributors.iter().zip(accounts) {
+        let destination = TokenAccount::try_deserialize(&mut &to.try_borrow_data()?[..])?;
+        require!(
+            *to.owner == token_program.key()
+                && destination.mint == escrow.mint
+                && destination.owner == contributor.account,
+            EscrowError::InvalidContributorAccounts
+        );
+        let share = u64::try_from(
+            u128::from(refund) * u128::from(contributor.amount) / u128::from(escrow.deposit),
+        )
+        .map_err(|_| EscrowError::MathOverflow)?;
+        transfer_from_vault(token_program, mint, vault, escrow, to.clone(), share)?;
+        paid += share;
+    }
+    Ok(paid)
+}
+
+/// Close the emptied vault (if still open) and the escrow, returning both
+/// rents to the buyer. Fails with `VaultNotEmpty` if anything is left in the
+/// vault.
+fn close_escrow_accounts<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: Option<&mut InterfaceAccount<'info, TokenAccount>>,
+    escrow: &Account<'info, Escrow>,
+    buyer: AccountInfo<'info>,
+) -> Result<()> {
+    // The collateral vault isn't tracked once the escrow is gone
+    require!(escrow.collateral_amount == 0, EscrowError::VaultNotEmpty);
+    if let Some(vault) = vault {
+        close_vault(token_program, vault, escrow, buyer.clone())?;
+    }
+    escrow.close(buyer)
+}
+
+/// Close the emptied vault, returning its rent to the buyer. Fails with
+/// `VaultNotEmpty` if anything is left in it.
+fn close_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &mut InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    buyer: AccountInfo<'info>,
+) -> Result<()> {
+    vault.reload()?;
+    require!(vault.amount == 0, EscrowError::VaultNotEmpty);
+
+    let escrow_key = escrow.key();
+    let seeds = &[
+        b"vault".as_ref(),
+        escrow_key.as_ref(),
+        &[escrow.vault_bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination: buyer,
+        authority: vault.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    token_interface::close_account(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer))
+}
+
+/// Push the deadline out to `now + dispute_buffer_secs` when a dispute opens
+/// closer to it than that, so no time-based settlement fires before the
+/// arbiter can rule. Emits `DeadlineExtended` if the deadline moved.
+fn extend_for_dispute(escrow: &mut Account<Escrow>, now: i64) {
+    if escrow.no_deadline || escrow.dispute_buffer_secs == 0 {
+        return;
+    }
+    // The buffer was checked against the config's maximum at `initialize`
+    let deadline = now + i64::from(escrow.dispute_buffer_secs);
+    if escrow.deadline >= deadline {
+        return;
+    }
+
+    escrow.deadline = deadline;
+    let seq = escrow.next_event_seq();
+    emit!(DeadlineExtended {
+        escrow: escrow.key(),
+        deadline,
+        seq,
+    });
+}
+
+/// Emit `EscrowSettled` with `amount` in both raw and display units.
+fn emit_settled(escrow: &mut Account<Escrow>, outcome: SettlementOutcome, amount: u64) {
+    let seq = escrow.next_event_seq();
+    emit!(EscrowSettled {
+        escrow: escrow.key(),
+        outcome,
+        amount,
+        decimals: escrow.decimals,
+        ui_amount: spl_token::amount_to_ui_amount_string_trimmed(amount, escrow.decimals),
+        fee_waived: escrow.fee_waived,
+        seq,
+    });
+}
+
+/// CPI into the escrow's registered callback program, if any, passing the
+/// escrow key and outcome. The escrow account is passed read-only.
+///
+/// A callback that runs and fails aborts the whole transaction (the runtime
+/// does not let a caller catch CPI errors). What `callback_strict` controls is
+/// a missing or non-executable callback account: strict escrows fail with
+/// `CallbackFailed`, lenient ones log and settle anyway.
+fn notify_callback<'info>(
+    escrow: &Account<'info, Escrow>,
+    callback_program: Option<&UncheckedAccount<'info>>,
+    outcome: SettlementOutcome,
+) -> Result<()> {
+    if escrow.callback_program == Pubkey::default() {
+        return Ok(());
+    }
+
+    let program = match callback_program {
+        Some(program) if program.executable => program,
+        _ => {
+            require!(!escrow.callback_strict, EscrowError::CallbackFailed);
+            msg!("Callback program unavailable, skipping notification");
+            return Ok(());
+        }
+    };
+
+    let mut data = CALLBACK_DISCRIMINATOR.to_vec();
+    (escrow.key(), outcome).serialize(&mut data)?;
+    let ix = Instruction {
+        program_id: program.key(),
+        accounts: vec![AccountMeta::new_readonly(escrow.key(), false)],
+        data,
+    };
+    invoke(&ix, &[escrow.to_account_info(), program.to_account_info()])
+        .map_err(|_| error!(EscrowError::CallbackFailed))
+}
+
+/// Transfer `amount` tokens out of the vault, signed by the vault PDA.
+/// Fails if the mint's decimals no longer match those recorded at `initialize`.
+fn transfer_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow: &Account<'info, Escrow>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    require_eq!(mint.decimals, escrow.decimals, EscrowError::DecimalsMismatch);
+
+    let escrow_key = escrow.key();
+
+    // PDA signer seeds for the vault
+    let seeds = &[
+        b"vault".as_ref(),
+        escrow_key.as_ref(),
+        &[escrow.vault_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = TransferChecked {
+        from: vault.to_account_info(),
+        mint: mint.to_account_info(),
+        to,
+        authority: vault.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token_interface::transfer_checked(cpi_ctx, amount, escrow.decimals)
+}
+
+/// Pay every basket asset out of its vault, in full, to token accounts owned
+/// by `recipient`. `accounts` starts with one `[mint, asset_vault,
+/// destination]` triple per entry in `escrow.assets`, in order. No-op for
+/// escrows without a basket.
+fn settle_basket<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &Account<'info, Escrow>,
+    accounts: &[AccountInfo<'info>],
+    recipient: Pubkey,
+) -> Result<()> {
+    require!(
+        accounts.len() >= escrow.assets.len() * 3,
+        EscrowError::InvalidBasketAccounts
+    );
+    let escrow_key = escrow.key();
+
+    for (entry, group) in escrow.assets.iter().zip(accounts.chunks(3)) {
+        let (mint, vault, to) = (&group[0], &group[1], &group[2]);
+        require_keys_eq!(mint.key(), entry.mint, EscrowError::InvalidBasketAccounts);
+
+        let seeds = &[
+            b"vault".as_ref(),
+            escrow_key.as_ref(),
+            &[entry.vault_index],
+            &[entry.vault_bump],
+        ];
+        let expected = Pubkey::create_program_address(seeds, &crate::ID)
+            .map_err(|_| error!(EscrowError::InvalidBasketAccounts))?;
+        require_keys_eq!(vault.key(), expected, EscrowError::InvalidBasketAccounts);
+
+        let destination = TokenAccount::try_deserialize(&mut &to.try_borrow_data()?[..])?;
+        require!(
+            *to.owner == token_program.key()
+                && destination.mint == entry.mint
+                && destination.owner == recipient,
+            EscrowError::InvalidBasketAccounts
+        );
+        let amount = TokenAccount::try_deserialize(&mut &vault.try_borrow_data()?[..])?.amount;
+        let decimals = Mint::try_deserialize(&mut &mint.try_borrow_data()?[..])?.decimals;
+
+        let cpi_accounts = TransferChecked {
+            from: vault.clone(),
+            mint: mint.clone(),
+            to: to.clone(),
+            authority: vault.clone(),
+        };
+        let cpi_program = token_program.to_account_info();
+        let signer = &[&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// State
+// ---------------------------------------------------------------------------
+
+#[account]
+#[derive(InitSpace)]
+pub struct Escrow {
+    /// The buyer who deposited tokens.
+    pub buyer: Pubkey,
+    /// The seller who will receive tokens on release.
+    pub seller: Pubkey,
+    /// SPL token mint.
+    pub mint: Pubkey,
+    /// Amount of tokens locked.
+    pub amount: u64,
+    /// Unix timestamp after which the escrow can expire (`NO_DEADLINE` if
+    /// open-ended).
+    pub deadline: i64,
+    /// PDA bump for the escrow account.
+    pub bump: u8,
+    /// PDA bump for the vault token account.
+    pub vault_bump: u8,
+    /// Current escrow state.
+    pub state: EscrowState,
+    /// Settlement flow this escrow was created for.
+    pub escrow_type: EscrowKind,
+    /// Third party who rules on disputes (`Pubkey::default()` if none).
+    pub arbiter: Pubkey,
+    /// True while a dispute is open; blocks `release` and `cancel`.
+    pub dispute_open: bool,
+    /// Mint decimals at creation; settlement fails if the mint drifts.
+    pub decimals: u8,
+    /// Installments for `Milestone` escrows (empty otherwise).
+    #[max_len(MAX_MILESTONES)]
+    pub milestones: Vec<Milestone>,
+    /// Seller who still has to confirm (`Pubkey::default()` once confirmed
+    /// or when confirmation wasn't requested).
+    pub pending_seller: Pubkey,
+    /// Program notified via CPI on release/cancel (`Pubkey::default()` if none).
+    pub callback_program: Pubkey,
+    /// Fail settlement if the callback program is not supplied.
+    pub callback_strict: bool,
+    /// Seconds between `request_cancel` and `finalize_cancel` (0 = cancel
+    /// immediately).
+    pub notice_secs: u32,
+    /// When `request_cancel` was called (0 if never).
+    pub cancel_requested_at: i64,
+    /// The seller objected to a cancel request; only the arbiter can settle.
+    pub objected: bool,
+    /// Highest client nonce accepted by `release`/`cancel`.
+    pub last_action_nonce: u64,
+    /// Least number of slots between rate-limited actions (0 = unlimited).
+    pub min_action_gap_slots: u64,
+    /// Slot of the last rate-limited action (0 if none).
+    pub last_action_slot: u64,
+    /// Arbiter panel for majority-vote disputes (empty = single `arbiter`).
+    #[max_len(MAX_ARBITERS)]
+    pub arbiters: Vec<Pubkey>,
+    /// Bitmap of panel arbiters (by index) who have voted.
+    pub votes_cast: u8,
+    /// Bitmap of panel arbiters (by index) voting for the seller.
+    pub votes_to_seller: u8,
+    /// Share of the escrow refunded to the buyer by `settle_on_expire`, in
+    /// basis points; the seller gets the rest.
+    pub expire_buyer_bps: u16,
+    /// Program that must invoke `confirm_seller` for a PDA seller
+    /// (`Pubkey::default()` if the seller signs directly).
+    pub seller_program: Pubkey,
+    /// Cap on `release_partial` calls (0 = unlimited).
+    pub max_releases: u16,
+    /// Number of partial releases made so far.
+    pub release_count: u16,
+    /// Cap on `release_partial` calls per `RELEASE_WINDOW_SECS` (0 = unlimited).
+    pub max_releases_per_day: u16,
+    /// Partial releases made in the current window.
+    pub releases_today: u16,
+    /// Start of the current window (0 before the first partial release).
+    pub releases_window_start: i64,
+    /// Pyth price account quoting the mint in the reference asset
+    /// (`Pubkey::default()` if the escrow is priced in tokens).
+    pub oracle: Pubkey,
+    /// Value owed to the seller in the reference asset, with
+    /// `REFERENCE_DECIMALS` (0 without an oracle).
+    pub reference_value: u64,
+    /// Only program allowed to settle this escrow via CPI
+    /// (`Pubkey::default()` = any caller).
+    pub authorized_caller: Pubkey,
+    /// Hash of the off-chain terms both parties agreed to (all zeros if none).
+    pub terms_hash: [u8; 32],
+    /// SHA-256 of the secret of a cross-chain HTLC swap (all zeros if none).
+    pub hash_lock: [u8; 32],
+    /// Off-chain authority whose Ed25519 signature `release_with_signature`
+    /// requires; plain `release` is refused (`Pubkey::default()` = none).
+    /// Unrelated to the Pyth `oracle`.
+    pub oracle_pubkey: Pubkey,
+    /// Free-form labels for off-chain filtering, e.g. "auction".
+    #[max_len(MAX_TAGS)]
+    pub tags: Vec<[u8; 16]>,
+    /// Protocol fee charged so far by `release_partial`,
+    /// `release_milestone`, and `release_scheduled`, i.e. on what the
+    /// seller has already been paid.
+    pub fee_collected: u64,
+    /// Vault balance at the last `snapshot` (0 if none).
+    pub last_snapshot_amount: u64,
+    /// When the last `snapshot` was taken (0 if never).
+    pub last_snapshot_at: i64,
+    /// Extra assets of a basket escrow, settled together with the primary
+    /// deposit (empty otherwise).
+    #[max_len(MAX_BASKET_ASSETS)]
+    pub assets: Vec<VaultEntry>,
+    /// Key allowed to release besides the buyer (the buyer by default).
+    pub settle_authority: Pubkey,
+    /// Dated installments for `Scheduled` escrows (empty otherwise).
+    #[max_len(MAX_SCHEDULED_PAYMENTS)]
+    pub schedule: Vec<ScheduledPayment>,
+    /// Close the vault and escrow on cancel, refunding rent to the buyer.
+    pub auto_close: bool,
+    /// Number of events emitted for this escrow; the next event's `seq`.
+    pub event_seq: u64,
+    /// How many times `reopen` and `extend_by` may extend the deadline.
+    pub max_extensions: u8,
+    /// How many times `reopen` and `extend_by` have extended the deadline.
+    pub extension_count: u8,
+    /// Affiliate paid a cut on `release` (`Pubkey::default()` if none).
+    pub referrer: Pubkey,
+    /// Referrer's cut of the released amount, in basis points.
+    pub referrer_bps: u16,
+    /// Reject releases bundled with instructions from unexpected programs.
+    pub strict_tx: bool,
+    /// Open-ended escrow: it never expires, so only the buyer's release or
+    /// cancel (or a dispute) settles it.
+    pub no_deadline: bool,
+    /// Keys whose approval releases need (empty = no approval step).
+    #[max_len(MAX_APPROVERS)]
+    pub approvers: Vec<Pubkey>,
+    /// Weight of each approver's approval, by index into `approvers`.
+    #[max_len(MAX_APPROVERS)]
+    pub approver_weights: Vec<u16>,
+    /// Total approval weight releases need.
+    pub approval_threshold: u16,
+    /// Bitmap of approvers (by index) who have confirmed.
+    pub approvals: u8,
+    /// Time the approvers have to reach the threshold, from the first
+    /// confirmation (0 = unlimited).
+    pub approval_window_secs: u32,
+    /// When the current round of confirmations started.
+    pub first_confirm_at: i64,
+    /// The vault was closed by `close_vault_only`; the escrow is kept as a
+    /// record.
+    pub vault_closed: bool,
+    /// Set by `freeze_escrow`; blocks every instruction that moves funds.
+    pub frozen: bool,
+    /// Create the seller's associated token account on release if missing.
+    pub create_seller_ata: bool,
+    /// Amount currently counted in the mint's TVL for this escrow: the
+    /// initial deposit plus `deposit` top-ups, less `reduce_amount` refunds.
+    /// Released from the TVL when the escrow settles.
+    pub deposit: u64,
+    /// `amount` as created, kept for dispute evidence. Set once by
+    /// `initialize`; unlike `amount` and `deposit`, top-ups, reductions, and
+    /// partial releases never change it.
+    pub original_amount: u64,
+    /// `deadline` as created, kept for dispute evidence. Set once by
+    /// `initialize`; `extend_by`, `amend`, and `reopen` leave it alone.
+    pub original_deadline: i64,
+    /// `seller` as created, kept for dispute evidence. The seller is part of
+    /// the escrow's seeds, so this is recorded for completeness rather than
+    /// because it can drift today.
+    pub original_seller: Pubkey,
+    /// Write a `Receipt` on release.
+    pub create_receipt: bool,
+    /// Block releases until the seller calls `submit_delivery`.
+    pub require_delivery: bool,
+    /// Hash of the deliverable submitted by the seller (all zeros if none).
+    pub delivery_hash: [u8; 32],
+    /// Block the buyer's cancels once a delivery has been submitted.
+    pub lock_on_delivery: bool,
+    /// Accept `deposit`s from third parties, not just the buyer.
+    pub pooled: bool,
+    /// Mint the seller is paid in on `release`, via the config's swap program
+    /// (`Pubkey::default()` = paid in `mint`).
+    pub target_mint: Pubkey,
+    /// Least `target_mint` the seller must receive from the swap.
+    pub min_out: u64,
+    /// Log settlement details with `msg!` (events are emitted either way).
+    pub verbose: bool,
+    /// Least time the arbiter gets between a dispute opening and the
+    /// deadline (0 = never extended).
+    pub dispute_buffer_secs: u32,
+    /// Seller's performance bond held in the collateral vault (0 if none).
+    pub collateral_amount: u64,
+    /// PDA bump for the collateral vault.
+    pub collateral_vault_bump: u8,
+    /// The arbiter ruled for the buyer; the collateral goes to them.
+    pub collateral_forfeited: bool,
+    /// Refunds only go to the buyer's associated token account.
+    pub strict_buyer_ata: bool,
+    /// Escrow group this escrow settles with (all zeros if none).
+    pub group_id: [u8; 32],
+    /// Number of escrows in the group (0 if none).
+    pub group_size: u8,
+    /// Block releases if the mint changed suspiciously since `initialize`.
+    pub rug_guard: bool,
+    /// Token program of the mint and vault, fixed at `initialize`;
+    /// `release` and `cancel` only accept this one (`TokenProgramMismatch`).
+    pub token_program: Pubkey,
+    /// Mint authority at `initialize` (`Pubkey::default()` if none).
+    pub mint_authority: Pubkey,
+    /// Mint supply at `initialize`.
+    pub mint_supply: u64,
+    /// Share of the vault paid to the arbiter who resolves a dispute, in
+    /// basis points.
+    pub arbiter_fee_bps: u16,
+    /// `release` pays the seller in native SOL rather than wSOL.
+    pub unwrap_on_release: bool,
+    /// Smallest `release_partial` amount, except for the final one (0 = any).
+    pub min_release_amount: u64,
+    /// Relayer reimbursed on release or cancel (`Pubkey::default()` if none).
+    pub relayer: Pubkey,
+    /// Lamports reserved on the escrow PDA for the relayer (0 once paid).
+    pub relayer_rebate: u64,
+    /// Deposited less than the config's `fee_waiver_threshold`: settlements
+    /// charge no protocol fee.
+    pub fee_waived: bool,
+    /// Third parties who pooled funds via `deposit` (`Standard` escrows
+    /// without a basket only).
+    #[max_len(MAX_CONTRIBUTORS)]
+    pub contributors: Vec<Contributor>,
+}
+
+impl Escrow {
+    /// True once the funds have left the vault for good: `Released`,
+    /// `Cancelled`, `Refunded`, or `Settled`.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self.state,
+            EscrowState::Released
+                | EscrowState::Cancelled
+                | EscrowState::Refunded
+                | EscrowState::Settled
+        )
+    }
+
+    /// Fails unless the escrow is `Locked` (funded and seller confirmed).
+    pub fn require_locked(&self) -> Result<()> {
+        match self.state {
+            EscrowState::Locked => Ok(()),
+            EscrowState::PendingSellerConfirm => err!(EscrowError::SellerNotConfirmed),
+            _ => Err(self.state_error().into()),
+        }
+    }
+
+    /// Fails unless the buyer may reclaim the funds right away. Escrows with
+    /// a notice period must go through `request_cancel` unless expired, and
+    /// `lock_on_delivery` escrows can't be cancelled once delivered.
+    pub fn require_cancellable(&self) -> Result<()> {
+        self.require_undelivered()?;
+        match self.state {
+            EscrowState::Expired => Ok(()),
+            EscrowState::Locked | EscrowState::PendingSellerConfirm => {
+                require!(self.notice_secs == 0, EscrowError::NoticePeriodActive);
+                Ok(())
+            }
+            _ => Err(self.state_error().into()),
+        }
+    }
+
+    /// True once `now` has reached the deadline. Never true for open-ended
+    /// escrows.
+    pub fn deadline_passed(&self, now: i64) -> bool {
+        !self.no_deadline && now >= self.deadline
+    }
+
+    /// Whether `settle_collateral` pays the buyer: the seller lost a dispute,
+    /// or the escrow was refunded in full after the deadline.
+    pub fn forfeits_collateral(&self) -> bool {
+        self.collateral_forfeited || self.state == EscrowState::Refunded
+    }
+
+    /// Short human-readable status at `now`, e.g. "Locked, 3d 4h remaining".
+    pub fn status_label(&self, now: i64) -> String {
+        match self.state {
+            EscrowState::Locked if self.dispute_open => "Disputed".to_string(),
+            EscrowState::Locked if self.frozen => "Frozen".to_string(),
+            EscrowState::Locked if self.no_deadline => "Locked, no deadline".to_string(),
+            EscrowState::Locked if self.deadline_passed(now) => "Locked, deadline passed".to_string(),
+            EscrowState::Locked => {
+                format!("Locked, {} remaining", format_duration(self.deadline - now))
+            }
+            EscrowState::PendingSellerConfirm => "Awaiting seller confirmation".to_string(),
+            EscrowState::CancelRequested => match self.notice_ends() {
+                Ok(ends) if ends > now => {
+                    format!("Cancel requested, {} notice left", format_duration(ends - now))
+                }
+                _ => "Cancel requested, ready to finalize".to_string(),
+            },
+            EscrowState::Expired => "Expired".to_string(),
+            EscrowState::Released => "Released".to_string(),
+            EscrowState::Cancelled => "Cancelled".to_string(),
+            EscrowState::Settled => "Settled".to_string(),
+            EscrowState::Refunded => "Refunded".to_string(),
+        }
+    }
+
+    /// Index of `key` among the release approvers.
+    pub fn approver_index(&self, key: &Pubkey) -> Result<usize> {
+        self.approvers
+            .iter()
+            .position(|a| a == key)
+            .ok_or_else(|| error!(EscrowError::Unauthorized))
+    }
+
+    /// Combined weight of the approvers who have confirmed.
+    pub fn approved_weight(&self) -> u32 {
+        self.approver_weights
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.approvals & (1 << i) != 0)
+            .map(|(_, &w)| u32::from(w))
+            .sum()
+    }
+
+    /// True for an escrow that belongs to an escrow group.
+    pub fn is_grouped(&self) -> bool {
+        self.group_id != [0; 32]
+    }
+
+    /// True if `release_group` could pay this escrow out right now.
+    pub fn is_group_releasable(&self, now: i64) -> bool {
+        self.require_locked().is_ok()
+            && !self.dispute_open
+            && !self.frozen
+            && self.require_approved(now).is_ok()
+            && self.require_delivered().is_ok()
+            && self.assets.is_empty()
+            && self.amount > 0
+    }
+
+    /// Fails with `ThresholdNotMet` until confirmed approvals carry
+    /// `approval_threshold` weight, or with `ApprovalWindowExpired` once the
+    /// approval window ran out before they did. Always passes without
+    /// approvers.
+    pub fn require_approved(&self, now: i64) -> Result<()> {
+        if self.approved_weight() >= u32::from(self.approval_threshold) {
+            return Ok(());
+        }
+        require!(
+            !self.approval_window_expired(now),
+            EscrowError::ApprovalWindowExpired
+        );
+        err!(EscrowError::ThresholdNotMet)
+    }
+
+    /// True once the confirmations collected so far are older than
+    /// `approval_window_secs`, counted from the first of them. The next
+    /// `confirm_release` starts over.
+    pub fn approval_window_expired(&self, now: i64) -> bool {
+        self.approval_window_secs > 0
+            && self.approvals != 0
+            && now > self.first_confirm_at + i64::from(self.approval_window_secs)
+    }
+
+    /// Record a client-supplied `release`/`cancel` nonce. Fails with
+    /// `StaleNonce` unless it exceeds every nonce accepted before.
+    pub fn use_nonce(&mut self, nonce: u64) -> Result<()> {
+        require!(nonce > self.last_action_nonce, EscrowError::StaleNonce);
+        self.last_action_nonce = nonce;
+        Ok(())
+    }
+
+    /// Record a rate-limited action at `slot`. Fails with `RateLimited` if
+    /// it comes less than `min_action_gap_slots` after the previous one.
+    pub fn throttle(&mut self, slot: u64) -> Result<()> {
+        require!(
+            self.min_action_gap_slots == 0
+                || self.last_action_slot == 0
+                || slot >= self.last_action_slot.saturating_add(self.min_action_gap_slots),
+            EscrowError::RateLimited
+        );
+        self.last_action_slot = slot;
+        Ok(())
+    }
+
+    /// Count a `release_partial` at `now` against `max_releases_per_day`,
+    /// starting a fresh window once `RELEASE_WINDOW_SECS` have passed since
+    /// the current one began. Fails with `DailyReleaseLimit` when the window
+    /// is full.
+    pub fn count_daily_release(&mut self, now: i64) -> Result<()> {
+        if self.max_releases_per_day == 0 {
+            return Ok(());
+        }
+        if now >= self.releases_window_start.saturating_add(RELEASE_WINDOW_SECS) {
+            self.releases_window_start = now;
+            self.releases_today = 0;
+        }
+        require!(
+            self.releases_today < self.max_releases_per_day,
+            EscrowError::DailyReleaseLimit
+        );
+        self.releases_today += 1;
+        Ok(())
+    }
+
+    /// End of the notice period after `request_cancel`: `finalize_cancel`
+    /// opens, and `object` closes.
+    pub fn notice_ends(&self) -> Result<i64> {
+        Ok(self
+            .cancel_requested_at
+            .checked_add(i64::from(self.notice_secs))
+            .ok_or(EscrowError::MathOverflow)?)
+    }
+
+    /// Fails with `DeliverySubmitted` if the escrow is `lock_on_delivery`
+    /// and the seller has submitted a delivery.
+    pub fn require_undelivered(&self) -> Result<()> {
+        require!(
+            !self.lock_on_delivery || self.delivery_hash == [0; 32],
+            EscrowError::DeliverySubmitted
+        );
+        Ok(())
+    }
+
+    /// Fails with `MintChanged` if this is a `rug_guard` escrow and its mint
+    /// has gained a new mint authority, or its supply grew by more than
+    /// `RUG_GUARD_SUPPLY_BPS` since `initialize`.
+    pub fn require_mint_unchanged(&self, mint: &Mint) -> Result<()> {
+        if !self.rug_guard {
+            return Ok(());
+        }
+        let max_supply = u128::from(self.mint_supply)
+            * (BPS_DENOMINATOR + u128::from(RUG_GUARD_SUPPLY_BPS))
+            / BPS_DENOMINATOR;
+        require!(
+            mint.mint_authority.unwrap_or_default() == self.mint_authority
+                && u128::from(mint.supply) <= max_supply,
+            EscrowError::MintChanged
+        );
+        Ok(())
+    }
+
+    /// Fails with `NoDelivery` if the escrow requires a delivery proof and
+    /// the seller hasn't submitted one.
+    pub fn require_delivered(&self) -> Result<()> {
+        require!(
+            !self.require_delivery || self.delivery_hash != [0; 32],
+            EscrowError::NoDelivery
+        );
+        Ok(())
+    }
+
+    /// Index of `key` in the arbiter panel.
+    pub fn arbiter_index(&self, key: &Pubkey) -> Result<usize> {
+        self.arbiters
+            .iter()
+            .position(|a| a == key)
+            .ok_or_else(|| error!(EscrowError::NotAnArbiter))
+    }
+
+    /// Votes needed to settle a panel dispute: a strict majority of the
+    /// panel (0 without one).
+    pub fn arbiter_threshold(&self) -> u32 {
+        if self.arbiters.is_empty() {
+            0
+        } else {
+            self.arbiters.len() as u32 / 2 + 1
+        }
+    }
+
+    /// Number of panel votes for the given outcome.
+    pub fn votes_for(&self, to_seller: bool) -> u32 {
+        if to_seller {
+            self.votes_to_seller.count_ones()
+        } else {
+            (self.votes_cast & !self.votes_to_seller).count_ones()
+        }
+    }
+
+    /// Fails unless `signer` may settle the dispute in this direction: the
+    /// single arbiter, or a panel member once the outcome has a majority.
+    pub fn require_ruling(&self, signer: &Pubkey, to_seller: bool) -> Result<()> {
+        if self.arbiters.is_empty() {
+            require_keys_eq!(*signer, self.arbiter, EscrowError::Unauthorized);
+            return Ok(());
+        }
+        self.arbiter_index(signer)?;
+        require!(
+            self.votes_for(to_seller) >= self.arbiter_threshold(),
+            EscrowError::NoMajority
+        );
+        Ok(())
+    }
+
+    /// Sequence number for the next event about this escrow. Events are
+    /// numbered from 0 without gaps, so indexers can detect missed logs.
+    pub fn next_event_seq(&mut self) -> u64 {
+        let seq = self.event_seq;
+        self.event_seq += 1;
+        seq
+    }
+
+    /// The most specific error for an instruction that can't run in the
+    /// current state.
+    pub fn state_error(&self) -> EscrowError {
+        match self.state {
+            EscrowState::Released => EscrowError::AlreadyReleased,
+            EscrowState::Cancelled => EscrowError::AlreadyCancelled,
+            EscrowState::Refunded => EscrowError::AlreadyRefunded,
+            _ => EscrowError::NotLocked,
+        }
+    }
+
+    /// Fails with `WrongEscrowKind` unless this escrow is one of `allowed`.
+    pub fn require_kind(&self, allowed: &[EscrowKind]) -> Result<()> {
+        require!(
+            allowed.contains(&self.escrow_type),
+            EscrowError::WrongEscrowKind
+        );
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum EscrowState {
+    Locked,
+    Released,
+    Cancelled,
+    /// Funded, waiting for the seller to sign `confirm_seller`.
+    PendingSellerConfirm,
+    /// Deadline passed without release; cancellable or reopenable.
+    Expired,
+    /// Buyer called `request_cancel`; waiting out the notice period.
+    CancelRequested,
+    /// Split between buyer and seller by `settle_on_expire`.
+    Settled,
+    /// Refunded in full after the deadline, by `force_expire_and_refund` or a
+    /// 100% buyer share in `settle_on_expire`. Terminal, like `Cancelled`.
+    Refunded,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct Milestone {
+    /// Tokens paid to the seller when this milestone is released.
+    pub amount: u64,
+    /// Whether the milestone has been paid out.
+    pub released: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct Contributor {
+    /// Wallet that deposited; refunds go to its token account.
+    pub account: Pubkey,
+    /// Tokens deposited via `deposit`.
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct ScheduledPayment {
+    /// Unix time from which this installment can be released.
+    pub unlock_at: i64,
+    /// Tokens paid to the seller for this installment.
+    pub amount: u64,
+    /// Whether the installment has been paid out.
+    pub paid: bool,
+}
+
+/// An installment requested at `initialize`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct Installment {
+    /// Unix time from which this installment can be released.
+    pub unlock_at: i64,
+    /// Tokens paid to the seller for this installment.
+    pub amount: u64,
+}
+
+/// An extra asset held by a basket escrow in its own vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct VaultEntry {
+    /// Mint of the asset.
+    pub mint: Pubkey,
+    /// Seed distinguishing the asset's vault from the escrow's other vaults.
+    /// Seeds: ["vault", escrow, vault_index].
+    pub vault_index: u8,
+    /// PDA bump for the asset's vault.
+    pub vault_bump: u8,
+    /// Tokens deposited.
+    pub amount: u64,
+}
+
+/// Outcome reported to the callback program.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SettlementOutcome {
+    Released,
+    /// The buyer cancelled explicitly.
+    Cancelled,
+    /// The buyer was refunded in full after the deadline.
+    Refunded,
+}
+
+/// Which settlement flow an escrow uses. Each handler only accepts the kinds
+/// it knows how to settle.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum EscrowKind {
+    /// Fungible SPL tokens released in full.
+    #[default]
+    Standard,
+    /// A single token from a zero-decimal mint.
+    Nft,
+    /// Wrapped SOL that can be refunded as native lamports.
+    Native,
+    /// Fungible SPL tokens swapped into `target_mint` on `release`.
+    Swap,
+    /// Funds released in milestone installments.
+    Milestone,
+    /// Funds released in installments that unlock on fixed dates.
+    Scheduled,
+}
+
+/// Reusable escrow parameters for `initialize_from_template`. Seeds:
+/// ["template", authority, template_id].
+#[account]
+#[derive(InitSpace)]
+pub struct EscrowTemplate {
+    /// Operator who created the template.
+    pub authority: Pubkey,
+    pub template_id: u64,
+    pub params: TemplateParams,
+    /// PDA bump for the template.
+    pub bump: u8,
+}
+
+/// Parameters an `EscrowTemplate` fills in. Every other `InitOptions`
+/// field takes its default.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct TemplateParams {
+    pub kind: EscrowKind,
+    pub arbiter: Option<Pubkey>,
+    pub arbiter_fee_bps: u16,
+    /// Escrow duration from creation, in seconds (0 = open-ended).
+    pub deadline_secs: u32,
+    pub require_seller_confirm: bool,
+    pub notice_secs: u32,
+    pub expire_buyer_bps: Option<u16>,
+    pub dispute_buffer_secs: u32,
+    pub auto_close: bool,
+    pub max_extensions: Option<u8>,
+    pub referrer: Option<Pubkey>,
+    pub referrer_bps: u16,
+}
+
+impl TemplateParams {
+    fn to_options(&self) -> InitOptions {
+        InitOptions {
+            kind: self.kind,
+            arbiter: self.arbiter,
+            arbiter_fee_bps: self.arbiter_fee_bps,
+            require_seller_confirm: self.require_seller_confirm,
+            notice_secs: self.notice_secs,
+            expire_buyer_bps: self.expire_buyer_bps,
+            dispute_buffer_secs: self.dispute_buffer_secs,
+            auto_close: self.auto_close,
+            max_extensions: self.max_extensions,
+            referrer: self.referrer,
+            referrer_bps: self.referrer_bps,
+            ..InitOptions::default()
+        }
+    }
+}
+
+/// Global program settings, stored in a single PDA. Seeds: ["config"].
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    /// Authority allowed to update the config.
+    pub admin: Pubkey,
+    /// Protocol fee charged on release, in basis points.
+    pub fee_bps: u16,
+    /// Wallet whose token accounts receive protocol fees.
+    pub fee_recipient: Pubkey,
+    /// How fractional fees are rounded.
+    pub fee_rounding: FeeRounding,
+    /// Flat fee in lamports charged to the buyer on `initialize`.
+    pub lamport_fee: u64,
+    /// Account receiving `lamport_fee`.
+    pub treasury: Pubkey,
+    /// Attach an SPL Memo to every release/cancel.
+    pub require_memo: bool,
+    /// Only buyers with an active `ApprovedBuyer` entry may `initialize`.
+    pub permissioned: bool,
+    /// Cleanup service allowed to `close_escrow` on anyone's behalf
+    /// (`Pubkey::default()` if none). Rent still goes to the buyer.
+    pub finalizer: Pubkey,
+    /// Shortest escrow `initialize` accepts: deadlines must be at least this
+    /// many seconds away (0 = no floor).
+    pub min_deadline_secs: u32,
+    /// Longest escrow `initialize` accepts, in seconds. 90 days for a new
+    /// config; changed with `set_max_deadline`.
+    pub max_deadline_secs: u32,
+    /// Splits the protocol fee between several recipients instead of
+    /// `fee_recipient` (empty = no split). Their `bps` sum to `fee_bps`.
+    #[max_len(MAX_FEE_RECIPIENTS)]
+    pub fee_split: Vec<FeeRecipient>,
+    /// Escrows depositing less than this pay no protocol fee, so tiny deals
+    /// aren't charged dust (0 = never waived).
+    pub fee_waiver_threshold: u64,
+    /// DEX program trusted to swap vaults into an escrow's `target_mint` on
+    /// release (`Pubkey::default()` if none).
+    pub swap_program: Pubkey,
+    /// Fee in basis points kept from the refund on `cancel`, sent to
+    /// `fee_recipient` (0 = free cancels).
+    pub cancel_fee_bps: u16,
+    /// Only sellers with an active `ApprovedSeller` entry may be named in
+    /// `initialize`.
+    pub permissioned_sellers: bool,
+    /// `release` fails with `OverlappingDestinations` if any two of its
+    /// destination token accounts are the same. When clear, an account
+    /// passed twice receives the sum of its payouts.
+    pub reject_overlapping_destinations: bool,
+    /// PDA bump for the config account.
+    pub bump: u8,
+}
+
+impl Config {
+    fn apply(&mut self, params: &ConfigParams) {
+        self.fee_bps = params.fee_bps;
+        self.fee_recipient = params.fee_recipient;
+        self.fee_rounding = params.fee_rounding;
+        self.lamport_fee = params.lamport_fee;
+        self.treasury = params.treasury;
+        self.require_memo = params.require_memo;
+        self.permissioned = params.permissioned;
+        self.finalizer = params.finalizer;
+        self.min_deadline_secs = params.min_deadline_secs;
+        self.fee_split = params.fee_split.clone();
+        self.fee_waiver_threshold = params.fee_waiver_threshold;
+        self.swap_program = params.swap_program;
+        self.cancel_fee_bps = params.cancel_fee_bps;
+        self.permissioned_sellers = params.permissioned_sellers;
+        self.reject_overlapping_destinations = params.reject_overlapping_destinations;
+    }
+}
+
+/// One recipient's share of the protocol fee.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct FeeRecipient {
+    /// Wallet whose token account receives this share.
+    pub account: Pubkey,
+    /// Share of the released amount, in basis points (part of `fee_bps`).
+    pub bps: u16,
+}
+
+/// Allowlist entry for permissioned deployments. Seeds: ["approved_buyer", buyer].
+#[account]
+#[derive(InitSpace)]
+pub struct ApprovedBuyer {
+    pub buyer: Pubkey,
+    /// Cleared to revoke approval without closing the account.
+    pub active: bool,
+    /// PDA bump for the entry.
+    pub bump: u8,
+}
+
+/// Seller allowlist entry for deployments that restrict counterparties.
+/// Seeds: ["approved_seller", seller].
+#[account]
+#[derive(InitSpace)]
+pub struct ApprovedSeller {
+    pub seller: Pubkey,
+    /// Cleared to revoke approval without closing the account.
+    pub active: bool,
+    /// PDA bump for the entry.
+    pub bump: u8,
+}
+
+/// Proof that an escrow was released, written by `release` for escrows
+/// created with `create_receipt`. Outlives the escrow account.
+/// Seeds: ["receipt", escrow].
+#[account]
+#[derive(InitSpace)]
+pub struct Receipt {
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    /// Amount released to the seller, before fees.
+    pub amount: u64,
+    /// Unix timestamp of the release.
+    pub released_at: i64,
+    /// PDA bump for the receipt.
+    pub bump: u8,
+}
+
+/// Per-mint risk limits. Created on the mint's first escrow. Seeds:
+/// ["mint_policy", mint].
+#[account]
+#[derive(InitSpace)]
+pub struct MintPolicy {
+    pub mint: Pubkey,
+    /// Deposits held by escrows that haven't settled yet.
+    pub tvl: u64,
+    /// Cap on `tvl` (0 = unlimited).
+    pub max_tvl: u64,
+    /// PDA bump for the policy account.
+    pub bump: u8,
+}
+
+impl MintPolicy {
+    /// Count a new deposit, failing with `TvlCapExceeded` above `max_tvl`.
+    fn lock(&mut self, amount: u64) -> Result<()> {
+        let tvl = self.tvl.checked_add(amount).ok_or(EscrowError::MathOverflow)?;
+        require!(
+            self.max_tvl == 0 || tvl <= self.max_tvl,
+            EscrowError::TvlCapExceeded
+        );
+        self.tvl = tvl;
+        Ok(())
+    }
+
+    /// Stop counting a settled escrow's deposit.
+    fn unlock(&mut self, deposit: u64) {
+        self.tvl = self.tvl.saturating_sub(deposit);
+    }
+}
+
+/// Rounding applied to `amount * fee_bps / 10_000`. The seller always
+/// receives `amount - fee`, so no tokens are lost either way.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum FeeRounding {
+    /// Truncate (favors the seller).
+    Down,
+    /// Round any remainder up (favors the protocol).
+    Up,
+    /// Round half up.
+    Nearest,
+}
+
+/// Settable fields of `Config`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub fee_rounding: FeeRounding,
+    pub lamport_fee: u64,
+    pub treasury: Pubkey,
+    pub require_memo: bool,
+    pub permissioned: bool,
+    pub finalizer: Pubkey,
+    pub min_deadline_secs: u32,
+    /// At most `MAX_FEE_RECIPIENTS`; `bps` must sum to `fee_bps`.
+    pub fee_split: Vec<FeeRecipient>,
+    pub fee_waiver_threshold: u64,
+    pub swap_program: Pubkey,
+    pub cancel_fee_bps: u16,
+    pub permissioned_sellers: bool,
+    pub reject_overlapping_destinations: bool,
+}
+
+impl ConfigParams {
+    fn validate(&self, max_deadline_secs: i64) -> Result<()> {
+        require!(self.fee_bps <= 10_000, EscrowError::InvalidFee);
+        require!(self.cancel_fee_bps <= 10_000, EscrowError::InvalidFee);
+        require!(
+            i64::from(self.min_deadline_secs) <= max_deadline_secs,
+            EscrowError::DeadlineTooFar
+        );
+        if !self.fee_split.is_empty() {
+            let total: u32 = self.fee_split.iter().map(|r| u32::from(r.bps)).sum();
+            require!(
+                self.fee_split.len() <= MAX_FEE_RECIPIENTS && total == u32::from(self.fee_bps),
+                EscrowError::FeeSplitMismatch
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Vault PDA and bump returned by `derive_vault`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct VaultAddress {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+/// Changes applied by `amend`; `None` leaves a parameter as it is.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AmendmentSpec {
+    /// New deadline, within the config's bounds (`NO_DEADLINE` =
+    /// open-ended).
+    pub deadline: Option<i64>,
+    /// New arbiter share of a dispute payout, in basis points.
+    pub arbiter_fee_bps: Option<u16>,
+    /// New commitment to the off-chain terms.
+    pub terms_hash: Option<[u8; 32]>,
+}
+
+/// Optional parameters for `initialize`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct InitOptions {
+    /// Settlement flow for the new escrow.
+    pub kind: EscrowKind,
+    /// Arbiter for disputes. Without one, disputes cannot be opened.
+    pub arbiter: Option<Pubkey>,
+    /// Milestone amounts for `Milestone` escrows; must sum to `amount`.
+    pub milestones: Vec<u64>,
+    /// Start in `PendingSellerConfirm` until the seller signs `confirm_seller`.
+    pub require_seller_confirm: bool,
+    /// Program to notify via CPI when the escrow is released or cancelled.
+    pub callback_program: Option<Pubkey>,
+    /// Require the callback program to be supplied at settlement.
+    pub callback_strict: bool,
+    /// Notice period for the `request_cancel` → `finalize_cancel` flow.
+    pub notice_secs: u32,
+    /// Arbiter panel deciding disputes by majority vote. Mutually exclusive
+    /// with `arbiter`; at most `MAX_ARBITERS`.
+    pub arbiters: Vec<Pubkey>,
+    /// Buyer's share for `settle_on_expire`, in basis points. Defaults to
+    /// 10_000 (full refund).
+    pub expire_buyer_bps: Option<u16>,
+    /// Program controlling a PDA seller (e.g. a multisig); it must CPI into
+    /// `confirm_seller` on the seller's behalf.
+    pub seller_program: Option<Pubkey>,
+    /// Cap on `release_partial` calls (0 = unlimited).
+    pub max_releases: u16,
+    /// Pyth price account for escrows priced in a reference asset (e.g. USD).
+    pub oracle: Option<Pubkey>,
+    /// Amount owed in the reference asset, with `REFERENCE_DECIMALS`.
+    /// Required with `oracle`; `amount` is then the most the seller can get.
+    pub reference_value: u64,
+    /// Restrict CPI calls to release/cancel to this program. Direct calls by
+    /// the buyer are always allowed.
+    pub authorized_caller: Option<Pubkey>,
+    /// Commitment to the off-chain agreement, e.g. its SHA-256. The document
+    /// itself is never stored on-chain.
+    pub terms_hash: [u8; 32],
+    /// SHA-256 of the secret of a cross-chain HTLC swap, checked by
+    /// `verify_preimage`.
+    pub hash_lock: [u8; 32],
+    /// Labels for off-chain filtering, at most `MAX_TAGS`
+    /// (else `TooManyTags`). Changed later with `add_tag`/`remove_tag`.
+    pub tags: Vec<[u8; 16]>,
+    /// Service allowed to release on the buyer's behalf, e.g. after verifying
+    /// delivery off-chain. Defaults to the buyer.
+    pub settle_authority: Option<Pubkey>,
+    /// Fail unless the seller's associated token account for the mint already
+    /// exists (pass it as `seller_token_account`).
+    pub require_seller_ata: bool,
+    /// Dated installments for `Scheduled` escrows, in unlock order; amounts
+    /// must sum to `amount`.
+    pub schedule: Vec<Installment>,
+    /// Close the vault and escrow in the same transaction as `cancel` or
+    /// `finalize_cancel`, returning their rent to the buyer.
+    pub auto_close: bool,
+    /// Cap on `reopen` and `extend_by` calls. Defaults to
+    /// `DEFAULT_MAX_EXTENSIONS`.
+    pub max_extensions: Option<u8>,
+    /// Affiliate paid `referrer_bps` of the amount on `release`.
+    pub referrer: Option<Pubkey>,
+    /// Referrer's cut in basis points; together with the protocol fee at most
+    /// 10_000. Requires `referrer`.
+    pub referrer_bps: u16,
+    /// Only release in transactions made up of this program's instructions,
+    /// compute budget instructions, and calls from `authorized_caller`.
+    pub strict_tx: bool,
+    /// Keys that must approve via `confirm_release` before any release; at
+    /// most `MAX_APPROVERS`.
+    pub approvers: Vec<Pubkey>,
+    /// Weight of each approver, in the same order; e.g. 2 for a lead
+    /// approver whose approval counts double.
+    pub approver_weights: Vec<u16>,
+    /// Total weight of approvals required. Ignored without approvers.
+    pub approval_threshold: u16,
+    /// Let releases create the seller's associated token account if it
+    /// doesn't exist yet, paid by the release's `payer`.
+    pub create_seller_ata: bool,
+    /// Record the completed deal in a `Receipt` PDA on `release`, paid by
+    /// the release's `payer`. `Standard`, `Nft`, `Native`, and `Swap`
+    /// escrows only.
+    pub create_receipt: bool,
+    /// Releases fail with `NoDelivery` until the seller has submitted a
+    /// delivery hash via `submit_delivery`.
+    pub require_delivery: bool,
+    /// Once the seller has submitted a delivery, the buyer can no longer
+    /// cancel (`DeliverySubmitted`); only a release or a dispute settles it.
+    pub lock_on_delivery: bool,
+    /// Fail unless the seller is a wallet (owned by the System Program),
+    /// catching a program, token account, or other data account passed by
+    /// mistake.
+    pub require_system_seller: bool,
+    /// Let anyone, not just the buyer, add funds via `deposit` (a pooled,
+    /// crowd-funded escrow).
+    pub pooled: bool,
+    /// Pay the seller in this mint instead: `release` swaps the vault through
+    /// the config's `swap_program`. Required for (and only allowed on) `Swap`
+    /// escrows, which can't have an oracle or referrer.
+    pub target_mint: Option<Pubkey>,
+    /// Least `target_mint` the swap must deliver. Required with `target_mint`.
+    pub min_out: u64,
+    /// Log human-readable settlement details on deposits, releases, and
+    /// refunds. Off by default to save compute; the events carry the same data.
+    pub verbose: bool,
+    /// A dispute opened less than this many seconds before the deadline
+    /// pushes the deadline out to this far ahead. At most the config's
+    /// `max_deadline_secs`.
+    pub dispute_buffer_secs: u32,
+    /// Require `buyer_token_account` to be the buyer's associated token
+    /// account for the mint, here and on every refund (`NotCanonicalAta`).
+    pub strict_buyer_ata: bool,
+    /// Escrow group to join (all zeros = none). Grouped escrows are only
+    /// released together, via `release_group`. `Standard`, `Nft`, and
+    /// `Native` escrows without an oracle, referrer, swap, receipt, seller
+    /// ATA creation, caller restriction, callback, relayer, or `strict_tx`
+    /// only.
+    pub group_id: [u8; 32],
+    /// Number of escrows in the group, from 2 to `MAX_GROUP_SIZE`. Ignored
+    /// without a `group_id`.
+    pub group_size: u8,
+    /// Record the mint's authority and supply now, and fail releases with
+    /// `MintChanged` if the mint later gains a new authority or its supply
+    /// grows by more than `RUG_GUARD_SUPPLY_BPS`. The buyer can still cancel.
+    pub rug_guard: bool,
+    /// Share of the vault paid to the arbiter who resolves a dispute, before
+    /// the winner is paid. Requires an arbiter; together with the config's
+    /// `fee_bps` at most 10,000 (else `InvalidFee`).
+    pub arbiter_fee_bps: u16,
+    /// Pay the seller's `release` in native SOL: the wSOL goes through a
+    /// temporary account that is closed on the spot. Native-mint `Standard`
+    /// and `Native` escrows only (else `NotNativeMint`/`WrongEscrowKind`).
+    pub unwrap_on_release: bool,
+    /// Fail `release_partial` calls paying less than this with
+    /// `ReleaseBelowMinimum`, unless they clear the remaining balance.
+    pub min_release_amount: u64,
+    /// Approvals must reach `approval_threshold` within this many seconds of
+    /// the first confirmation; otherwise the next `confirm_release` clears
+    /// them and releases fail with `ApprovalWindowExpired` (0 = unlimited).
+    pub approval_window_secs: u32,
+    /// Least number of slots between the escrow's `confirm_release`,
+    /// `submit_delivery`, `freeze_escrow`, `unfreeze_escrow`,
+    /// `request_cancel`, `object`, `open_dispute`, and `vote_dispute` calls;
+    /// sooner ones fail with `RateLimited` (0 = unlimited).
+    pub min_action_gap_slots: u64,
+    /// Relayer submitting transactions on the buyer's behalf.
+    pub relayer: Option<Pubkey>,
+    /// Lamports the buyer deposits on the escrow PDA now, paid to `relayer`
+    /// on `release`, `cancel`, `cancel_native`, or `finalize_cancel`. Kept
+    /// apart from the token vault. Ignored without a `relayer`.
+    pub relayer_rebate: u64,
+    /// Off-chain authority, e.g. a delivery oracle, that must sign off on
+    /// the release; see `release_with_signature`. Only `Standard`, `Nft`,
+    /// `Native`, and `Swap` escrows outside a group and without `strict_tx`.
+    pub oracle_pubkey: Option<Pubkey>,
+    /// Cap on `release_partial` calls per rolling `RELEASE_WINDOW_SECS`;
+    /// further calls fail with `DailyReleaseLimit` (0 = unlimited).
+    pub max_releases_per_day: u16,
+}
+
+/// Clock override for integration tests. Seeds: ["test_clock"].
+#[cfg(feature = "test-clock")]
+#[account]
+#[derive(InitSpace)]
+pub struct TestClock {
+    /// Unix time returned by `current_time` when this account is supplied.
+    pub unix_timestamp: i64,
+    /// PDA bump for the test clock account.
+    pub bump: u8,
+}
+
+// ---------------------------------------------------------------------------
+// Accounts
+// ---------------------------------------------------------------------------
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// Buyer creating the escrow; pays for account creation and deposits tokens.
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// Seller's public key (does not need to sign).
+    /// CHECK: We only store the seller's pubkey; no data is read from this account.
+    pub seller: UncheckedAccount<'info>,
+
+    /// SPL token mint for the escrowed asset.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Buyer's token account (source of deposited tokens).
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (SOL fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// TVL tracking for the mint; created by the mint's first escrow.
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + MintPolicy::INIT_SPACE,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    /// Receives the flat SOL fee. Required when `config.lamport_fee` is non-zero.
+    /// CHECK: Only receives lamports; address is checked against the config.
+    #[account(mut, address = config.treasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+
+    /// Buyer's allowlist entry. Required when `config.permissioned` is set.
+    #[account(seeds = [b"approved_buyer", buyer.key().as_ref()], bump = approved_buyer.bump)]
+    pub approved_buyer: Option<Account<'info, ApprovedBuyer>>,
+
+    /// Seller's allowlist entry. Required when `config.permissioned_sellers`
+    /// is set.
+    #[account(seeds = [b"approved_seller", seller.key().as_ref()], bump = approved_seller.bump)]
+    pub approved_seller: Option<Account<'info, ApprovedSeller>>,
+
+    /// Seller's associated token account for the mint. Required when
+    /// `options.require_seller_ata` is set.
+    /// CHECK: Address and initialization are checked in the handler.
+    pub seller_token_account: Option<UncheckedAccount<'info>>,
+
+    /// Template supplying the options. Required by
+    /// `initialize_from_template`; ignored otherwise.
+    pub template: Option<Account<'info, EscrowTemplate>>,
+
+    /// Escrow state PDA. Seeds: ["escrow", buyer, seller, mint].
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault token account PDA that holds the escrowed tokens (vault index 0;
+    /// basket assets use `["vault", escrow, vault_index]`).
+    /// Authority is itself (the vault PDA) so only the program can move funds.
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = vault,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(template_id: u64)]
+pub struct CreateTemplate<'info> {
+    /// Operator creating the template; pays for it.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Template PDA. Seeds: ["template", authority, template_id].
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + EscrowTemplate::INIT_SPACE,
+        seeds = [b"template", authority.key().as_ref(), &template_id.to_le_bytes()],
+        bump,
+    )]
+    pub template: Account<'info, EscrowTemplate>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, vault_index: u8)]
+pub struct AddAsset<'info> {
+    /// Buyer adding to the basket; pays for the asset vault.
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Escrow's primary mint. Basket assets must share its token program so
+    /// one settlement can move them all.
+    #[account(
+        address = escrow.mint,
+        mint::token_program = token_program,
+    )]
+    pub primary_mint: InterfaceAccount<'info, Mint>,
+
+    /// Mint of the asset being added.
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Buyer's token account for the asset (source of the deposit).
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Vault holding this asset. Authority is itself, like the primary vault.
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = asset_vault,
+        seeds = [b"vault", escrow.key().as_ref(), vault_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub asset_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    /// The buyer, or anyone for a `pooled` escrow.
+    pub contributor: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (fee waiver threshold).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Contributor's token account (source of the deposit).
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = contributor,
+    )]
+    pub contributor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Release<'info> {
+    /// Buyer of the escrow. Must sign unless `settle_authority` does.
+    /// CHECK: Pinned by the escrow seeds and `has_one`; the signature is
+    /// checked in the handler.
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// Automated service allowed to release on the buyer's behalf.
+    pub settle_authority: Option<Signer<'info>>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Escrow state — must be locked and belong to this buyer.
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller's token account to receive funds.
+    /// CHECK: Must be a token account for the mint; checked in the handler,
+    /// after it was created if the escrow allows that.
+    #[account(mut)]
+    pub seller_token_account: UncheckedAccount<'info>,
+
+    /// Global config (fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's token account. Required when the fee is non-zero.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Program registered for settlement callbacks.
+    /// CHECK: Address is checked against the escrow; only invoked via CPI.
+    #[account(address = escrow.callback_program)]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// Pyth price account. Required when the escrow has an oracle.
+    /// CHECK: Address is checked against the escrow; owner and layout are
+    /// checked when the price is read.
+    #[account(address = escrow.oracle)]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    /// Buyer's token account for the part of the deposit an oracle quote
+    /// doesn't cover. Required only when there is something to refund.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Referrer's token account. Required when the escrow pays a referrer.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.referrer,
+    )]
+    pub referrer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Seller's wallet, owner of a seller ATA created on release, and
+    /// recipient of unwrapped SOL.
+    /// CHECK: Address is checked against the escrow; never read.
+    #[account(mut, address = escrow.seller)]
+    pub seller: Option<UncheckedAccount<'info>>,
+
+    /// Pays the rent of a seller ATA or `Receipt` created on release, or
+    /// fronts the temporary account of an unwrapping release (refunded in the
+    /// same instruction).
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
+
+    /// Required, with `seller`, `payer`, and the system program, to create
+    /// the seller's ATA on release.
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    pub system_program: Option<Program<'info, System>>,
+
+    /// Instructions sysvar. Required for `strict_tx` escrows, and when an
+    /// escrow with an `authorized_caller` is settled via CPI.
+    /// CHECK: Address is checked; read to inspect the transaction.
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    /// Temporary wSOL account for `unwrap_on_release` escrows. Required,
+    /// with `seller`, `payer`, and the system program, to unwrap on release.
+    /// CHECK: Address is checked; created and closed in the handler.
+    #[account(mut, seeds = [b"unwrap", escrow.key().as_ref()], bump)]
+    pub unwrap_account: Option<UncheckedAccount<'info>>,
+
+    /// Receipt written on `release`. Required, with `payer` and the system
+    /// program, for escrows created with `create_receipt`.
+    /// CHECK: Address is checked; created and written in the handler.
+    #[account(mut, seeds = [b"receipt", escrow.key().as_ref()], bump)]
+    pub receipt: Option<UncheckedAccount<'info>>,
+
+    /// Relayer paid the escrow's `relayer_rebate`. Without it the rebate
+    /// stays reserved.
+    /// CHECK: Address is checked against the escrow; only credited.
+    #[account(mut, address = escrow.relayer)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    /// Token program the escrow was created with.
+    #[account(address = escrow.token_program @ EscrowError::TokenProgramMismatch)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmSeller<'info> {
+    /// The intended seller.
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: Instructions sysvar, read to find the invoking program.
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmRelease<'info> {
+    /// Must be one of `escrow.approvers`.
+    pub approver: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitDelivery<'info> {
+    /// The escrow's seller.
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct Object<'info> {
+    /// The escrow's seller.
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct Expire<'info> {
+    /// Anyone may expire an escrow past its deadline.
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct SettleOnExpire<'info> {
+    /// Anyone may settle an escrow past its deadline.
+    pub caller: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account, receives the buyer's share.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller's token account, receives the seller's share.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.seller,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's token account. Required when a fee is charged.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    /// Only the seller can claim.
+    pub seller: Signer<'info>,
+
+    /// The escrow's buyer; must be closed (no lamports).
+    /// CHECK: Address is checked against the escrow; only its balance is read.
+    #[account(address = escrow.buyer)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = seller,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller's token account, receives the escrow.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = seller,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's token account. Required when a fee is charged.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Reopen<'info> {
+    pub buyer: Signer<'info>,
+
+    pub seller: Signer<'info>,
+
+    /// Expired escrow between these two parties.
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Global config (maximum duration).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendBy<'info> {
+    pub buyer: Signer<'info>,
+
+    /// Locked escrow owned by this buyer.
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Global config (maximum duration).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct Amend<'info> {
+    /// CHECK: Pinned by the escrow seeds and `has_one`; the signature is
+    /// checked in the handler.
+    pub buyer: UncheckedAccount<'info>,
+
+    /// CHECK: Pinned by the escrow seeds and `has_one`; the signature is
+    /// checked in the handler.
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Global config (deadline bounds, protocol fee).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct FreezeEscrow<'info> {
+    pub buyer: Signer<'info>,
+
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = seller,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ForceExpireAndRefund<'info> {
+    /// Anyone may run the refund once the deadline has passed.
+    pub caller: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account to receive the refund.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (memo settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Program registered for settlement callbacks.
+    /// CHECK: Address is checked against the escrow; only invoked via CPI.
+    #[account(address = escrow.callback_program)]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RequestCancel<'info> {
+    /// Only the buyer can request a cancel.
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateTags<'info> {
+    /// Only the buyer can tag the escrow.
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ReduceAmount<'info> {
+    pub buyer: Signer<'info>,
+
+    /// Seller must agree to the lower amount.
+    pub seller: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), seller.key().as_ref(), mint.key().as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = seller,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account to receive the difference.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RescueExcess<'info> {
+    pub buyer: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account to receive the excess.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Cancel<'info> {
+    /// Only the buyer can cancel.
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Escrow state — must be locked and belong to this buyer.
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account to receive refund.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (memo settings, cancel fee).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's token account. Required when a cancel fee is due.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Program registered for settlement callbacks.
+    /// CHECK: Address is checked against the escrow; only invoked via CPI.
+    #[account(address = escrow.callback_program)]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// Instructions sysvar. Required when an escrow with an
+    /// `authorized_caller` is settled via CPI.
+    /// CHECK: Address is checked; read to find the calling program.
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    /// Relayer paid the escrow's `relayer_rebate`. Without it the rebate
+    /// stays reserved.
+    /// CHECK: Address is checked against the escrow; only credited.
+    #[account(mut, address = escrow.relayer)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    /// Token program the escrow was created with.
+    #[account(address = escrow.token_program @ EscrowError::TokenProgramMismatch)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelNative<'info> {
+    /// Only the buyer can cancel; receives the unwrapped lamports.
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// Native (wSOL) mint.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Escrow state — must be locked and belong to this buyer.
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the wSOL.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's wSOL account; receives the refund and is then closed.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (memo settings, cancel fee).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's wSOL account. Required when a cancel fee is due.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Program registered for settlement callbacks.
+    /// CHECK: Address is checked against the escrow; only invoked via CPI.
+    #[account(address = escrow.callback_program)]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// Instructions sysvar. Required when an escrow with an
+    /// `authorized_caller` is settled via CPI.
+    /// CHECK: Address is checked; read to find the calling program.
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    /// Relayer paid the escrow's `relayer_rebate`. Without it the rebate
+    /// stays reserved.
+    /// CHECK: Address is checked against the escrow; only credited.
+    #[account(mut, address = escrow.relayer)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    /// Token program the escrow was created with.
+    #[account(address = escrow.token_program @ EscrowError::TokenProgramMismatch)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    /// Buyer or seller.
+    pub party: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    /// `escrow.arbiter`, or a member of `escrow.arbiters`.
+    pub arbiter: Signer<'info>,
+
+    /// SPL token mint (needed for transfer_checked).
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Escrow under dispute.
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Vault holding the tokens.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Buyer's token account, receives funds if the arbiter refunds.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.buyer,
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller's token account, receives funds if the arbiter rules for the seller.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = escrow.seller,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Global config (fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// Fee recipient's token account. Required when a fee is charged.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = config.fee_recipient,
+    )]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Resolving arbiter's token account. Required when the escrow pays an
+    /// arbiter fee.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = arbiter,
+    )]
+    pub arbiter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// TVL tracking for the mint.
+    #[account(
+        mut,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump = mint_policy.bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    /// Program registered for settlement callbacks.
+    /// CHECK: Address is checked against the escrow; only invoked via CPI.
+    #[account(address = escrow.callback_program)]
+    pub callback_program: Option<UncheckedAccount<'info>>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct VoteDispute<'info> {
+    /// Must be a member of `escrow.arbiters`.
+    pub arbiter: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+    )]
     pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDispute<'info> {
+    /// Must match `escrow.arbiter`.
+    pub arbiter: Signer<'info>,
 
-    /// Vault holding the tokens.
     #[account(
         mut,
-        seeds = [b"vault", escrow.key().as_ref()],
-        bump = escrow.vault_bump,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = arbiter @ EscrowError::Unauthorized,
     )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// Pays for the config account and becomes its admin.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Global config PDA. Seeds: ["config"].
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Config::INIT_SPACE,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    /// Must match `config.admin`.
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ EscrowError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+#[instruction(buyer: Pubkey)]
+pub struct SetApprovedBuyer<'info> {
+    /// Must match `config.admin`; pays for new entries.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ EscrowError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + ApprovedBuyer::INIT_SPACE,
+        seeds = [b"approved_buyer", buyer.as_ref()],
+        bump,
+    )]
+    pub approved_buyer: Account<'info, ApprovedBuyer>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(seller: Pubkey)]
+pub struct SetApprovedSeller<'info> {
+    /// Must match `config.admin`; pays for new entries.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ EscrowError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + ApprovedSeller::INIT_SPACE,
+        seeds = [b"approved_seller", seller.as_ref()],
+        bump,
+    )]
+    pub approved_seller: Account<'info, ApprovedSeller>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PostCollateral<'info> {
+    /// The escrow's seller; pays for the collateral vault.
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
 
-    /// Buyer's token account to receive refund.
     #[account(
         mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), seller.key().as_ref(), mint.key().as_ref()],
+        bump = escrow.bump,
+        has_one = seller,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Holds the seller's collateral. Authority is itself, like the vault.
+    #[account(
+        init_if_needed,
+        payer = seller,
         token::mint = mint,
-        token::authority = buyer,
+        token::authority = collateral_vault,
+        token::token_program = token_program,
+        seeds = [b"collateral", escrow.key().as_ref()],
+        bump,
     )]
-    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub collateral_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Seller's token account (source of the collateral).
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = seller,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleCollateral<'info> {
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.seller.as_ref(), mint.key().as_ref()],
+        bump = escrow.bump,
+        has_one = mint,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: Collateral vault; read as a token account in the handler, once
+    /// the escrow is known to hold collateral.
+    #[account(mut, seeds = [b"collateral", escrow.key().as_ref()], bump)]
+    pub collateral_vault: UncheckedAccount<'info>,
+
+    /// Seller's token account, or the buyer's if the collateral is forfeited.
+    #[account(mut, token::mint = mint)]
+    pub recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: The escrow's seller; receives the collateral vault's rent.
+    #[account(mut, address = escrow.seller)]
+    pub seller: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEscrow<'info> {
+    /// The buyer or `config.finalizer`.
+    pub caller: Signer<'info>,
+
+    /// Buyer of the escrow; receives the rent.
+    /// CHECK: Pinned by the escrow seeds and `has_one`; only receives lamports.
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer.key().as_ref(), escrow.seller.as_ref(), escrow.mint.as_ref()],
+        bump = escrow.bump,
+        has_one = buyer,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Emptied vault, closed along with the escrow. Omitted once
+    /// `close_vault_only` has closed it.
+    #[account(
+        mut,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Global config (finalizer).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseGroup<'info> {
+    /// Global config (fee settings).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// SPL Memo program. Required when `config.require_memo` is set.
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMany<'info> {
+    /// The buyer of every escrow in the batch, or `config.finalizer`.
+    pub caller: Signer<'info>,
+
+    /// Global config (finalizer).
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
 
     pub token_program: Interface<'info, TokenInterface>,
 }
 
+#[derive(Accounts)]
+pub struct SetMintPolicy<'info> {
+    /// Must match `config.admin`; pays for a new policy.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ EscrowError::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + MintPolicy::INIT_SPACE,
+        seeds = [b"mint_policy", mint.key().as_ref()],
+        bump,
+    )]
+    pub mint_policy: Account<'info, MintPolicy>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetEscrow<'info> {
+    pub escrow: Account<'info, Escrow>,
+}
+
+/// Takes no state; the system program is only here because an accounts
+/// struct can't be empty.
+#[derive(Accounts)]
+pub struct DeriveVault<'info> {
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetVaultBalance<'info> {
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct TakeSnapshot<'info> {
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyBumps<'info> {
+    /// Checked in the handler against the PDA derived from its own fields,
+    /// not the stored bump.
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: Address is checked in the handler; never read or written.
+    pub vault: UncheckedAccount<'info>,
+}
+
+#[cfg(feature = "test-bumps")]
+#[derive(Accounts)]
+pub struct SetTestBumps<'info> {
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[cfg(feature = "test-amount")]
+#[derive(Accounts)]
+pub struct SetTestAmount<'info> {
+    #[account(mut)]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[cfg(feature = "test-clock")]
+#[derive(Accounts)]
+pub struct SetTestClock<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + TestClock::INIT_SPACE,
+        seeds = [b"test_clock"],
+        bump,
+    )]
+    pub test_clock: Account<'info, TestClock>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+/// Emitted when an escrow is created and funded.
+#[event]
+pub struct EscrowInitialized {
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub deadline: i64,
+    /// Commitment to the off-chain terms, as stored on the escrow.
+    pub terms_hash: [u8; 32],
+    /// Per-escrow event sequence number; always 0 here.
+    pub seq: u64,
+}
+
+/// Emitted when `deposit` adds funds to an escrow.
+#[event]
+pub struct EscrowDeposited {
+    pub escrow: Pubkey,
+    /// Buyer or pooled contributor who deposited.
+    pub contributor: Pubkey,
+    pub amount: u64,
+    /// Per-escrow event sequence number, one past the previous event's.
+    pub seq: u64,
+}
+
+/// Emitted when opening a dispute or `extend_by` pushes the deadline out.
+#[event]
+pub struct DeadlineExtended {
+    pub escrow: Pubkey,
+    /// The new deadline.
+    pub deadline: i64,
+    /// Per-escrow event sequence number, one past the previous event's.
+    pub seq: u64,
+}
+
+/// Emitted when an escrow is released or cancelled.
+#[event]
+pub struct EscrowSettled {
+    pub escrow: Pubkey,
+    pub outcome: SettlementOutcome,
+    /// Raw token amount paid out, fee included. The buyer's refund on an
+    /// oracle-priced release is not counted. Milestone, scheduled and partial
+    /// releases count only the final tranche.
+    pub amount: u64,
+    /// Mint decimals, as stored on the escrow.
+    pub decimals: u8,
+    /// `amount` in display units, e.g. "1.5" for 1_500_000 at 6 decimals.
+    pub ui_amount: String,
+    /// No protocol fee was charged (see `Config::fee_waiver_threshold`).
+    pub fee_waived: bool,
+    /// Per-escrow event sequence number, one past the previous event's.
+    pub seq: u64,
+}
+
+/// Emitted by `snapshot` with the vault balance it recorded.
+#[event]
+pub struct Snapshot {
+    pub escrow: Pubkey,
+    /// Vault balance, in raw token units.
+    pub amount: u64,
+    /// Unix timestamp of the snapshot.
+    pub at: i64,
+    /// Per-escrow event sequence number, one past the previous event's.
+    pub seq: u64,
+}
+
 // ---------------------------------------------------------------------------
 // Errors
 // ---------------------------------------------------------------------------
 
+/// Program errors. Clients see `6000 + discriminant` as the error code, and
+/// SDKs hardcode those numbers: the discriminants are pinned and API-stable.
+/// Never renumber or reuse one; add new variants at the end with the next
+/// number.
 #[error_code]
 pub enum EscrowError {
     #[msg("Escrow amount must be greater than zero")]
-    ZeroAmount,
+    ZeroAmount = 0,
     #[msg("Deadline must be in the future")]
-    DeadlineInPast,
-    #[msg("Deadline exceeds maximum allowed duration (90 days)")]
-    DeadlineTooFar,
+    DeadlineInPast = 1,
+    #[msg("Deadline exceeds the configured maximum duration")]
+    DeadlineTooFar = 2,
     #[msg("Escrow is not in Locked state")]
-    NotLocked,
+    NotLocked = 3,
+    #[msg("Mint is not the native (wSOL) mint")]
+    NotNativeMint = 4,
+    #[msg("Instruction does not apply to this kind of escrow")]
+    WrongEscrowKind = 5,
+    #[msg("Fee basis points must not exceed 10,000")]
+    InvalidFee = 6,
+    #[msg("Fee recipient token account is required when a fee is charged")]
+    MissingFeeAccount = 7,
+    #[msg("Arithmetic overflow")]
+    MathOverflow = 8,
+    #[msg("Signer is not authorized for this action")]
+    Unauthorized = 9,
+    #[msg("Arbiter must be distinct from buyer and seller")]
+    InvalidArbiter = 10,
+    #[msg("Escrow has no arbiter")]
+    NoArbiter = 11,
+    #[msg("A dispute is open; only the arbiter can move funds")]
+    DisputeActive = 12,
+    #[msg("No dispute is open")]
+    NoDispute = 13,
+    #[msg("Mint decimals differ from those recorded at initialization")]
+    DecimalsMismatch = 14,
+    #[msg("Too many milestones")]
+    TooManyMilestones = 15,
+    #[msg("Milestone amounts must add up to the escrow amount")]
+    MilestoneSumMismatch = 16,
+    #[msg("Milestone does not exist")]
+    InvalidMilestone = 17,
+    #[msg("Milestone has already been released")]
+    MilestoneAlreadyReleased = 18,
+    #[msg("Seller has not confirmed the escrow yet")]
+    SellerNotConfirmed = 19,
+    #[msg("Treasury account is required when a SOL fee is charged")]
+    MissingTreasury = 20,
+    #[msg("Buyer cannot cover the SOL fee")]
+    InsufficientLamports = 21,
+    #[msg("Escrow has not expired")]
+    NotExpired = 22,
+    #[msg("Settlement callback could not be invoked")]
+    CallbackFailed = 23,
+    #[msg("New amount must be lower than the current amount")]
+    CannotIncrease = 24,
+    #[msg("Memo program is required by the config")]
+    MissingMemoProgram = 25,
+    #[msg("Escrow was already released")]
+    AlreadyReleased = 26,
+    #[msg("Escrow was already cancelled")]
+    AlreadyCancelled = 27,
+    #[msg("Cancellation notice period has not elapsed")]
+    NoticePeriodActive = 28,
+    #[msg("Signer is not on the arbiter panel")]
+    NotAnArbiter = 29,
+    #[msg("No strict majority of arbiters for this outcome")]
+    NoMajority = 30,
+    #[msg("Split basis points must not exceed 10,000")]
+    InvalidSplit = 31,
+    #[msg("Account address does not match its PDA seeds")]
+    BumpMismatch = 32,
+    #[msg("Instruction was not invoked by the seller's controlling program")]
+    UnauthorizedProgram = 33,
+    #[msg("Amount exceeds what is left in escrow")]
+    AmountExceedsEscrow = 34,
+    #[msg("Partial release limit reached")]
+    TooManyReleases = 35,
+    #[msg("Buyer is not approved for this deployment")]
+    BuyerNotApproved = 36,
+    #[msg("Expiry policy does not refund the buyer in full")]
+    RefundNotAllowed = 37,
+    #[msg("Oracle price is too old")]
+    StaleOracle = 38,
+    #[msg("Oracle price unavailable or invalid")]
+    OraclePriceError = 39,
+    #[msg("Buyer token account required for the refund")]
+    MissingBuyerTokenAccount = 40,
+    #[msg("Calling program is not authorized to settle this escrow")]
+    UnauthorizedCaller = 41,
+    #[msg("Basket holds the maximum number of assets")]
+    TooManyAssets = 42,
+    #[msg("Basket asset accounts missing or invalid")]
+    InvalidBasketAccounts = 43,
+    #[msg("Account is not rent-exempt")]
+    NotRentExempt = 44,
+    #[msg("Signer is neither the buyer nor the settle authority")]
+    UnauthorizedSettler = 45,
+    #[msg("Vault index is not the next free index")]
+    InvalidVaultIndex = 46,
+    #[msg("Seller's associated token account does not exist")]
+    SellerAccountMissing = 47,
+    #[msg("Vault holds no tokens beyond the escrowed amount")]
+    NoExcess = 48,
+    #[msg("Escrow has already been refunded")]
+    AlreadyRefunded = 49,
+    #[msg("Scheduled installments must add up to the escrow amount")]
+    ScheduleSumMismatch = 50,
+    #[msg("Schedule must be non-empty, ordered, and unlock before the deadline")]
+    InvalidSchedule = 51,
+    #[msg("Too many scheduled installments")]
+    TooManyInstallments = 52,
+    #[msg("No scheduled installment has matured")]
+    NothingMatured = 53,
+    #[msg("Vault must be empty before it can be closed")]
+    VaultNotEmpty = 54,
+    #[msg("Escrow deadline cannot be extended again")]
+    TooManyExtensions = 55,
+    #[msg("Referrer token account is required to pay the referrer")]
+    MissingReferrerAccount = 56,
+    #[msg("Transaction contains an instruction from an unexpected program")]
+    UnexpectedInstruction = 57,
+    #[msg("Vault authority is no longer the vault PDA")]
+    VaultAuthorityChanged = 58,
+    #[msg("Approvers, weights, or approval threshold are invalid")]
+    InvalidApprovers = 59,
+    #[msg("Approvals do not meet the release threshold")]
+    ThresholdNotMet = 60,
+    #[msg("Escrow amount exceeds the tokens locked in the vault")]
+    AmountExceedsLocked = 61,
+    #[msg("Escrow has not been settled yet")]
+    NotTerminal = 62,
+    #[msg("Escrow is frozen by its parties")]
+    EscrowFrozen = 63,
+    #[msg("Escrow is not frozen")]
+    NotFrozen = 64,
+    #[msg("Seller's associated token account could not be created")]
+    AtaCreationFailed = 65,
+    #[msg("Escrow would push the mint's total value locked above its cap")]
+    TvlCapExceeded = 66,
+    #[msg("Receipt account and system program are required to write the receipt")]
+    MissingReceiptAccount = 67,
+    #[msg("Seller has not submitted a delivery proof")]
+    NoDelivery = 68,
+    #[msg("Batch must be escrow, vault, and buyer triples, at most MAX_CLOSE_BATCH")]
+    InvalidBatch = 69,
+    #[msg("Deadline is sooner than the configured minimum duration")]
+    DeadlineTooSoon = 70,
+    #[msg("Too many contributors")]
+    TooManyContributors = 71,
+    #[msg("Contributor token accounts are missing or don't match the contributors")]
+    InvalidContributorAccounts = 72,
+    #[msg("Seller must be a wallet owned by the System Program")]
+    InvalidSellerAccount = 73,
+    #[msg("Fee split doesn't match: shares must sum to fee_bps, with one token account each")]
+    FeeSplitMismatch = 74,
+    #[msg("Seller has submitted a delivery; release or open a dispute instead")]
+    DeliverySubmitted = 75,
+    #[msg("Only the buyer can deposit into an escrow that isn't pooled")]
+    UnauthorizedBuyer = 76,
+    #[msg("Escrow is too large to return via return data")]
+    ReturnDataTooLarge = 77,
+    #[msg("Seller objected to the cancel request; wait for the arbiter")]
+    ObjectionRaised = 78,
+    #[msg("Notice period has ended; too late to object")]
+    ObjectionWindowClosed = 79,
+    #[msg("Nonce must be greater than the escrow's last action nonce")]
+    StaleNonce = 80,
+    #[msg("Swap delivered less than the escrow's min_out")]
+    SlippageExceeded = 81,
+    #[msg("Swap program or seller's target-mint account is missing or invalid")]
+    InvalidSwapAccounts = 82,
+    #[msg("Seller token account is the vault or owned by the escrow")]
+    InvalidDestination = 83,
+    #[msg("Escrow has no collateral to settle")]
+    NoCollateral = 84,
+    #[msg("Buyer token account is not the buyer's associated token account")]
+    NotCanonicalAta = 85,
+    #[msg("Escrows passed don't make up the whole escrow group")]
+    GroupMismatch = 86,
+    #[msg("Every escrow in the group must be releasable, and released together via release_group")]
+    GroupNotReady = 87,
+    #[msg("Mint authority or supply changed since the escrow was created")]
+    MintChanged = 88,
+    #[msg("Escrow duration exceeds the maximum")]
+    DurationTooLong = 89,
+    #[msg("Arbiter token account is required when an arbiter fee is charged")]
+    MissingArbiterAccount = 90,
+    #[msg("Unwrap account, seller, payer, and system program are required to unwrap on release")]
+    MissingUnwrapAccount = 91,
+    #[msg("Partial release is below the escrow's minimum release amount")]
+    ReleaseBelowMinimum = 92,
+    #[msg("Approvals didn't reach the threshold within the approval window")]
+    ApprovalWindowExpired = 93,
+    #[msg("Seller token account is for a different mint than the escrow")]
+    MintMismatch = 94,
+    #[msg("Escrow lamports can't cover the relayer rebate above rent exemption")]
+    InsufficientRebate = 95,
+    #[msg("Too soon after the escrow's last action")]
+    RateLimited = 96,
+    #[msg("Pass the escrow template to initialize from it")]
+    TemplateNotFound = 97,
+    #[msg("The buyer's account is still open")]
+    BuyerStillActive = 98,
+    #[msg("Too many tags")]
+    TooManyTags = 99,
+    #[msg("Token program differs from the one the escrow was created with")]
+    TokenProgramMismatch = 100,
+    #[msg("Amendments need both the buyer's and the seller's signatures")]
+    UnauthorizedAmendment = 101,
+    #[msg("Seller is not on the allowlist")]
+    SellerNotApproved = 102,
+    #[msg("Two settlement destinations are the same account")]
+    OverlappingDestinations = 103,
+    #[msg("Deadline extension must be positive")]
+    DeadlineNotExtended = 104,
+    #[msg("Missing or invalid oracle signature")]
+    InvalidSignature = 105,
+    #[msg("Daily partial release limit reached")]
+    DailyReleaseLimit = 106,
+    #[msg("Arbiter has already voted on this dispute")]
+    AlreadyVoted = 107,
+    #[msg("Recipient token account isn't owned by the party the collateral goes to")]
+    WrongCollateralRecipient = 108,
+    #[msg("Vault account is required until close_vault_only closes it")]
+    MissingVault = 109,
 }
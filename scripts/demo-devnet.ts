@@ -144,6 +144,94 @@ async function main() {
     return pda;
   }
 
+  // ─── Setup: global config (first run only) ───
+  const [configPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  if (!(await connection.getAccountInfo(configPDA))) {
+    console.log("Creating program config (no fees)...");
+    await program.methods
+      .initializeConfig({
+        feeBps: 0,
+        feeRecipient: walletKeypair.publicKey,
+        feeRounding: { down: {} },
+        lamportFee: new BN(0),
+        treasury: walletKeypair.publicKey,
+        requireMemo: false,
+        permissioned: false,
+        finalizer: PublicKey.default,
+        minDeadlineSecs: 0,
+        feeSplit: [],
+        feeWaiverThreshold: new BN(0),
+        swapProgram: PublicKey.default,
+        cancelFeeBps: 0,
+        permissionedSellers: false,
+        rejectOverlappingDestinations: false,
+      })
+      .accounts({
+        admin: walletKeypair.publicKey,
+        config: configPDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  }
+
+  // Plain escrow: no arbiter, milestones, confirmation, callback, or oracle
+  const initOptions = {
+    kind: { standard: {} },
+    arbiter: null,
+    arbiters: [],
+    milestones: [],
+    requireSellerConfirm: false,
+    callbackProgram: null,
+    callbackStrict: false,
+    noticeSecs: 0,
+    expireBuyerBps: null,
+    sellerProgram: null,
+    maxReleases: 0,
+    oracle: null,
+    referenceValue: new BN(0),
+    authorizedCaller: null,
+    termsHash: Array(32).fill(0),
+    hashLock: Array(32).fill(0),
+    tags: [],
+    settleAuthority: null,
+    requireSellerAta: false,
+    schedule: [],
+    autoClose: false,
+    maxExtensions: null,
+    referrer: null,
+    referrerBps: 0,
+    strictTx: false,
+    approvers: [],
+    approverWeights: [],
+    approvalThreshold: 0,
+    createSellerAta: false,
+    createReceipt: false,
+    requireDelivery: false,
+    lockOnDelivery: false,
+    requireSystemSeller: false,
+    pooled: false,
+    targetMint: null,
+    minOut: new BN(0),
+    verbose: false,
+    disputeBufferSecs: 0,
+    strictBuyerAta: false,
+    groupId: Array(32).fill(0),
+    groupSize: 0,
+    rugGuard: false,
+    arbiterFeeBps: 0,
+    unwrapOnRelease: false,
+    minReleaseAmount: new BN(0),
+    approvalWindowSecs: 0,
+    relayer: null,
+    relayerRebate: new BN(0),
+    minActionGapSlots: new BN(0),
+    oraclePubkey: null,
+    maxReleasesPerDay: 0,
+  };
+
   // ─── Demo 1: Initialize → Release (happy path) ───
   console.log("=== Demo 1: Initialize escrow and release to seller ===");
 
@@ -153,7 +241,7 @@ async function main() {
 
   // Initialize
   const initSig = await program.methods
-    .initialize(new BN(ESCROW_AMOUNT), deadline1)
+    .initialize(new BN(ESCROW_AMOUNT), deadline1, initOptions)
     .accounts({
       buyer: buyer.publicKey,
       seller: seller.publicKey,
@@ -161,6 +249,12 @@ async function main() {
       buyerTokenAccount: buyerTokenAccount,
       escrow: escrow1,
       vault: vault1,
+      config: configPDA,
+      treasury: null,
+      approvedBuyer: null,
+      template: null,
+      approvedSeller: null,
+      sellerTokenAccount: null,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
     })
@@ -181,13 +275,29 @@ async function main() {
 
   // Release
   const releaseSig = await program.methods
-    .release()
+    .release(new BN(1))
     .accounts({
       buyer: buyer.publicKey,
       mint: mint,
       escrow: escrow1,
       vault: vault1,
       sellerTokenAccount: sellerTokenAccount,
+      config: configPDA,
+      feeTokenAccount: null,
+      callbackProgram: null,
+      memoProgram: null,
+      settleAuthority: null,
+      oracle: null,
+      buyerTokenAccount: null,
+      referrerTokenAccount: null,
+      seller: null,
+      payer: null,
+      associatedTokenProgram: null,
+      systemProgram: null,
+      instructions: null,
+      unwrapAccount: null,
+      receipt: null,
+      relayer: null,
       tokenProgram: TOKEN_PROGRAM_ID,
     })
     .signers([buyer])
@@ -227,7 +337,7 @@ async function main() {
 
   // Initialize
   const initSig2 = await program.methods
-    .initialize(new BN(ESCROW_AMOUNT), deadline2)
+    .initialize(new BN(ESCROW_AMOUNT), deadline2, initOptions)
     .accounts({
       buyer: buyer.publicKey,
       seller: seller2.publicKey,
@@ -235,6 +345,12 @@ async function main() {
       buyerTokenAccount: buyerTokenAccount,
       escrow: escrow2,
       vault: vault2,
+      config: configPDA,
+      treasury: null,
+      approvedBuyer: null,
+      template: null,
+      approvedSeller: null,
+      sellerTokenAccount: null,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
     })
@@ -255,13 +371,19 @@ async function main() {
 
   // Cancel
   const cancelSig = await program.methods
-    .cancel()
+    .cancel(new BN(1))
     .accounts({
       buyer: buyer.publicKey,
       mint: mint,
       escrow: escrow2,
       vault: vault2,
       buyerTokenAccount: buyerTokenAccount,
+      config: configPDA,
+      feeTokenAccount: null,
+      callbackProgram: null,
+      memoProgram: null,
+      instructions: null,
+      relayer: null,
       tokenProgram: TOKEN_PROGRAM_ID,
     })
     .signers([buyer])
@@ -55,7 +55,8 @@ fi
 # 4. Deploy
 echo ""
 echo "=== Deploying to devnet ==="
-DEPLOY_OUTPUT=$(anchor deploy --provider.cluster devnet 2>&1)
+# Only the escrow program; seller-stub exists for localnet tests
+DEPLOY_OUTPUT=$(anchor deploy -p solana_escrow --provider.cluster devnet 2>&1)
 echo "$DEPLOY_OUTPUT"
 echo ""
 echo "Program deployed: $PROGRAM_ID"
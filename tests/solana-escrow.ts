@@ -1,15 +1,31 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN } from "@coral-xyz/anchor";
 import { SolanaEscrow } from "../target/types/solana_escrow";
+import { SellerStub } from "../target/types/seller_stub";
 import {
   createMint,
   createAccount,
   mintTo,
   getAccount,
+  createWrappedNativeAccount,
+  getAssociatedTokenAddressSync,
+  transfer,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  NATIVE_MINT,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
 } from "@solana/spl-token";
-import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  ComputeBudgetProgram,
+  Ed25519Program,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Transaction,
+} from "@solana/web3.js";
 import { expect } from "chai";
+import { createHash } from "crypto";
 
 describe("solana-escrow", () => {
   const provider = anchor.AnchorProvider.env();
@@ -26,6 +42,68 @@ describe("solana-escrow", () => {
 
   const DECIMALS = 6;
   const DEPOSIT_AMOUNT = 1_000_000; // 1 token (6 decimals)
+  const MAX_MILESTONES = 10;
+  const MAX_BASKET_ASSETS = 4;
+  const MAX_TAGS = 4;
+  const MAX_SCHEDULED_PAYMENTS = 8;
+  const DEFAULT_MAX_EXTENSIONS = 3;
+  const MEMO_PROGRAM_ID = new PublicKey(
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
+  );
+
+  const [configPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const feeRecipient = Keypair.generate();
+
+  const treasury = Keypair.generate();
+
+  // Config fields every test starts from; override per test as needed
+  function defaultConfig() {
+    return {
+      feeBps: 0,
+      feeRecipient: feeRecipient.publicKey,
+      feeRounding: { down: {} },
+      lamportFee: new BN(0),
+      treasury: treasury.publicKey,
+      requireMemo: false,
+      permissioned: false,
+      finalizer: PublicKey.default,
+      minDeadlineSecs: 0,
+      feeSplit: [],
+      feeWaiverThreshold: new BN(0),
+      swapProgram: PublicKey.default,
+      cancelFeeBps: 0,
+      permissionedSellers: false,
+      rejectOverlappingDestinations: false,
+    };
+  }
+
+  async function setConfig(overrides: any = {}) {
+    const params = { ...defaultConfig(), ...overrides };
+    const existing = await connection.getAccountInfo(configPDA);
+    if (existing) {
+      await program.methods
+        .updateConfig(params)
+        .accounts({ admin: provider.wallet.publicKey, config: configPDA })
+        .rpc();
+    } else {
+      await program.methods
+        .initializeConfig(params)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPDA,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+  }
+
+  before(async () => {
+    // The config PDA is global; start every run with no fees
+    await setConfig();
+  });
 
   beforeEach(async () => {
     // Create fresh keypairs for each test
@@ -86,13 +164,13 @@ describe("solana-escrow", () => {
     );
   });
 
-  function getEscrowPDA(): [PublicKey, number] {
+  function getEscrowPDA(escrowMint: PublicKey = mint): [PublicKey, number] {
     return PublicKey.findProgramAddressSync(
       [
         Buffer.from("escrow"),
         buyer.publicKey.toBuffer(),
         seller.publicKey.toBuffer(),
-        mint.toBuffer(),
+        escrowMint.toBuffer(),
       ],
       program.programId
     );
@@ -105,10 +183,204 @@ describe("solana-escrow", () => {
     );
   }
 
+  function getMintPolicyPDA(mintKey: PublicKey = mint): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("mint_policy"), mintKey.toBuffer()],
+      program.programId
+    );
+  }
+
   function futureDeadline(secondsFromNow: number): BN {
     return new BN(Math.floor(Date.now() / 1000) + secondsFromNow);
   }
 
+  function defaultOptions(overrides: any = {}) {
+    return {
+      kind: { standard: {} },
+      arbiter: null,
+      arbiters: [],
+      milestones: [],
+      requireSellerConfirm: false,
+      callbackProgram: null,
+      callbackStrict: false,
+      noticeSecs: 0,
+      expireBuyerBps: null,
+      sellerProgram: null,
+      maxReleases: 0,
+      oracle: null,
+      referenceValue: new BN(0),
+      authorizedCaller: null,
+      termsHash: Array(32).fill(0),
+      hashLock: Array(32).fill(0),
+      tags: [],
+      settleAuthority: null,
+      requireSellerAta: false,
+      schedule: [],
+      autoClose: false,
+      maxExtensions: null,
+      referrer: null,
+      referrerBps: 0,
+      strictTx: false,
+      approvers: [],
+      approverWeights: [],
+      approvalThreshold: 0,
+      createSellerAta: false,
+      createReceipt: false,
+      requireDelivery: false,
+      lockOnDelivery: false,
+      requireSystemSeller: false,
+      pooled: false,
+      targetMint: null,
+      minOut: new BN(0),
+      verbose: false,
+      disputeBufferSecs: 0,
+      strictBuyerAta: false,
+      groupId: Array(32).fill(0),
+      groupSize: 0,
+      rugGuard: false,
+      arbiterFeeBps: 0,
+      unwrapOnRelease: false,
+      minReleaseAmount: new BN(0),
+      approvalWindowSecs: 0,
+      relayer: null,
+      relayerRebate: new BN(0),
+      minActionGapSlots: new BN(0),
+      oraclePubkey: null,
+      maxReleasesPerDay: 0,
+      ...overrides,
+    };
+  }
+
+  async function airdrop(to: PublicKey, sol = 1) {
+    const sig = await connection.requestAirdrop(
+      to,
+      sol * anchor.web3.LAMPORTS_PER_SOL
+    );
+    await connection.confirmTransaction(sig);
+  }
+
+  // Creates an escrow between the current buyer and seller
+  async function initEscrow(
+    options: any = defaultOptions(),
+    amount: number = DEPOSIT_AMOUNT,
+    deadline: BN = futureDeadline(3600)
+  ): Promise<{ escrowPDA: PublicKey; vaultPDA: PublicKey }> {
+    const [escrowPDA] = getEscrowPDA();
+    const [vaultPDA] = getVaultPDA(escrowPDA);
+
+    await program.methods
+      .initialize(new BN(amount), deadline, options)
+      .accounts({
+        buyer: buyer.publicKey,
+        seller: seller.publicKey,
+        mint: mint,
+        buyerTokenAccount: buyerTokenAccount,
+        escrow: escrowPDA,
+        vault: vaultPDA,
+        config: configPDA,
+        treasury: null,
+        approvedBuyer: null,
+        template: null,
+        approvedSeller: null,
+        sellerTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    return { escrowPDA, vaultPDA };
+  }
+
+  function releaseIx(
+    escrowPDA: PublicKey,
+    vaultPDA: PublicKey,
+    overrides: any = {}
+  ) {
+    return program.methods
+      .release(new BN(1))
+      .accounts({
+        buyer: buyer.publicKey,
+        mint: mint,
+        escrow: escrowPDA,
+        vault: vaultPDA,
+        sellerTokenAccount: sellerTokenAccount,
+        config: configPDA,
+        feeTokenAccount: null,
+        callbackProgram: null,
+        memoProgram: null,
+        settleAuthority: null,
+        oracle: null,
+        buyerTokenAccount: null,
+        referrerTokenAccount: null,
+        seller: null,
+        payer: null,
+        associatedTokenProgram: null,
+        systemProgram: null,
+        instructions: null,
+        unwrapAccount: null,
+        receipt: null,
+        relayer: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...overrides,
+      })
+      .signers([buyer]);
+  }
+
+  function cancelIx(
+    escrowPDA: PublicKey,
+    vaultPDA: PublicKey,
+    overrides: any = {}
+  ) {
+    return program.methods
+      .cancel(new BN(1))
+      .accounts({
+        buyer: buyer.publicKey,
+        mint: mint,
+        escrow: escrowPDA,
+        vault: vaultPDA,
+        buyerTokenAccount: buyerTokenAccount,
+        config: configPDA,
+        feeTokenAccount: null,
+        callbackProgram: null,
+        memoProgram: null,
+        instructions: null,
+        relayer: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...overrides,
+      })
+      .signers([buyer]);
+  }
+
+  // Waits until the cluster clock is past `deadline`
+  async function waitForDeadline(deadline: BN) {
+    for (;;) {
+      const slot = await connection.getSlot();
+      const now = await connection.getBlockTime(slot);
+      if (now !== null && now > deadline.toNumber()) return;
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+  }
+
+  async function expectError(promise: Promise<any>, code: string) {
+    try {
+      await promise;
+      expect.fail("Should have thrown");
+    } catch (err: any) {
+      expect(err.toString()).to.include(code);
+    }
+  }
+
+  async function settledEvent(sig: string) {
+    const tx = await connection.getTransaction(sig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events = [...parser.parseLogs(tx.meta.logMessages)];
+    return events.find((e) => e.name === "escrowSettled").data as any;
+  }
+
   describe("initialize", () => {
     it("creates escrow and deposits tokens into vault", async () => {
       const [escrowPDA] = getEscrowPDA();
@@ -116,7 +388,7 @@ describe("solana-escrow", () => {
       const deadline = futureDeadline(3600); // 1 hour from now
 
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -124,6 +396,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -138,6 +416,7 @@ describe("solana-escrow", () => {
       expect(escrow.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
       expect(escrow.deadline.toNumber()).to.equal(deadline.toNumber());
       expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.decimals).to.equal(DECIMALS);
 
       // Verify vault balance
       const vaultAccount = await getAccount(connection, vaultPDA);
@@ -150,6 +429,18 @@ describe("solana-escrow", () => {
       );
     });
 
+    it("leaves the escrow and vault rent-exempt", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      for (const address of [escrowPDA, vaultPDA]) {
+        const info = await connection.getAccountInfo(address);
+        const minimum = await connection.getMinimumBalanceForRentExemption(
+          info.data.length
+        );
+        expect(info.lamports).to.be.at.least(minimum);
+      }
+    });
+
     it("rejects zero amount", async () => {
       const [escrowPDA] = getEscrowPDA();
       const [vaultPDA] = getVaultPDA(escrowPDA);
@@ -157,7 +448,7 @@ describe("solana-escrow", () => {
 
       try {
         await program.methods
-          .initialize(new BN(0), deadline)
+          .initialize(new BN(0), deadline, defaultOptions())
           .accounts({
             buyer: buyer.publicKey,
             seller: seller.publicKey,
@@ -165,6 +456,12 @@ describe("solana-escrow", () => {
             buyerTokenAccount: buyerTokenAccount,
             escrow: escrowPDA,
             vault: vaultPDA,
+            config: configPDA,
+            treasury: null,
+            approvedBuyer: null,
+            template: null,
+            approvedSeller: null,
+            sellerTokenAccount: null,
             tokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
           })
@@ -183,7 +480,7 @@ describe("solana-escrow", () => {
 
       try {
         await program.methods
-          .initialize(new BN(DEPOSIT_AMOUNT), pastDeadline)
+          .initialize(new BN(DEPOSIT_AMOUNT), pastDeadline, defaultOptions())
           .accounts({
             buyer: buyer.publicKey,
             seller: seller.publicKey,
@@ -191,6 +488,12 @@ describe("solana-escrow", () => {
             buyerTokenAccount: buyerTokenAccount,
             escrow: escrowPDA,
             vault: vaultPDA,
+            config: configPDA,
+            treasury: null,
+            approvedBuyer: null,
+            template: null,
+            approvedSeller: null,
+            sellerTokenAccount: null,
             tokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
           })
@@ -211,7 +514,7 @@ describe("solana-escrow", () => {
 
       // Initialize escrow first
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -219,6 +522,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -227,13 +536,29 @@ describe("solana-escrow", () => {
 
       // Release escrow
       await program.methods
-        .release()
+        .release(new BN(1))
         .accounts({
           buyer: buyer.publicKey,
           mint: mint,
           escrow: escrowPDA,
           vault: vaultPDA,
           sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([buyer])
@@ -252,6 +577,61 @@ describe("solana-escrow", () => {
       expect(Number(vaultAccount.amount)).to.equal(0);
     });
 
+    it("rejects the vault as the seller's token account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, { sellerTokenAccount: vaultPDA }).rpc(),
+        "InvalidDestination"
+      );
+    });
+
+    it("rejects a seller token account for another mint", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const otherMint = await createMint(
+        connection,
+        buyer,
+        buyer.publicKey,
+        null,
+        DECIMALS,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const otherAccount = await createAccount(
+        connection,
+        buyer,
+        otherMint,
+        seller.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, { sellerTokenAccount: otherAccount }).rpc(),
+        "MintMismatch"
+      );
+    });
+
+    it("rejects a retried release with the same nonce", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.lastActionNonce.toNumber()).to.equal(1);
+
+      // A distinct transaction carrying the same nonce
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA)
+          .preInstructions([
+            ComputeBudgetProgram.setComputeUnitLimit({ units: 400_000 }),
+          ])
+          .rpc(),
+        "StaleNonce"
+      );
+    });
+
     it("rejects release from non-buyer", async () => {
       const [escrowPDA] = getEscrowPDA();
       const [vaultPDA] = getVaultPDA(escrowPDA);
@@ -259,7 +639,7 @@ describe("solana-escrow", () => {
 
       // Initialize escrow
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -267,6 +647,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -283,13 +669,29 @@ describe("solana-escrow", () => {
       // Attempt release from seller (should fail — seeds won't match)
       try {
         await program.methods
-          .release()
+          .release(new BN(1))
           .accounts({
             buyer: seller.publicKey, // wrong signer
             mint: mint,
             escrow: escrowPDA,
             vault: vaultPDA,
             sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            settleAuthority: null,
+            oracle: null,
+            buyerTokenAccount: null,
+            referrerTokenAccount: null,
+            seller: null,
+            payer: null,
+            associatedTokenProgram: null,
+            systemProgram: null,
+            instructions: null,
+            unwrapAccount: null,
+            receipt: null,
+            relayer: null,
             tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([seller])
@@ -308,7 +710,7 @@ describe("solana-escrow", () => {
 
       // Initialize and release
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -316,6 +718,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -323,13 +731,29 @@ describe("solana-escrow", () => {
         .rpc();
 
       await program.methods
-        .release()
+        .release(new BN(1))
         .accounts({
           buyer: buyer.publicKey,
           mint: mint,
           escrow: escrowPDA,
           vault: vaultPDA,
           sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([buyer])
@@ -338,22 +762,50 @@ describe("solana-escrow", () => {
       // Try to release again
       try {
         await program.methods
-          .release()
+          .release(new BN(1))
           .accounts({
             buyer: buyer.publicKey,
             mint: mint,
             escrow: escrowPDA,
             vault: vaultPDA,
             sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            settleAuthority: null,
+            oracle: null,
+            buyerTokenAccount: null,
+            referrerTokenAccount: null,
+            seller: null,
+            payer: null,
+            associatedTokenProgram: null,
+            systemProgram: null,
+            instructions: null,
+            unwrapAccount: null,
+            receipt: null,
+            relayer: null,
             tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([buyer])
           .rpc();
         expect.fail("Should have thrown");
       } catch (err: any) {
-        expect(err.toString()).to.include("NotLocked");
+        expect(err.toString()).to.include("AlreadyReleased");
       }
     });
+
+    it("keeps the vault PDA as its own authority through release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      const vault = await getAccount(connection, vaultPDA);
+      expect(vault.owner.toBase58()).to.equal(vaultPDA.toBase58());
+
+      // The authority check passes and the release goes through
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
   });
 
   describe("cancel", () => {
@@ -371,7 +823,7 @@ describe("solana-escrow", () => {
 
       // Initialize escrow
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -379,6 +831,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -387,13 +845,19 @@ describe("solana-escrow", () => {
 
       // Cancel escrow
       await program.methods
-        .cancel()
+        .cancel(new BN(1))
         .accounts({
           buyer: buyer.publicKey,
           mint: mint,
           escrow: escrowPDA,
           vault: vaultPDA,
           buyerTokenAccount: buyerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          instructions: null,
+          relayer: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([buyer])
@@ -422,7 +886,7 @@ describe("solana-escrow", () => {
 
       // Initialize and release
       await program.methods
-        .initialize(new BN(DEPOSIT_AMOUNT), deadline)
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
@@ -430,6 +894,12 @@ describe("solana-escrow", () => {
           buyerTokenAccount: buyerTokenAccount,
           escrow: escrowPDA,
           vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
@@ -437,13 +907,29 @@ describe("solana-escrow", () => {
         .rpc();
 
       await program.methods
-        .release()
+        .release(new BN(1))
         .accounts({
           buyer: buyer.publicKey,
           mint: mint,
           escrow: escrowPDA,
           vault: vaultPDA,
           sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([buyer])
@@ -452,21 +938,6636 @@ describe("solana-escrow", () => {
       // Try to cancel after release
       try {
         await program.methods
-          .cancel()
+          .cancel(new BN(1))
+          .accounts({
+            buyer: buyer.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            instructions: null,
+            relayer: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("AlreadyReleased");
+      }
+    });
+
+    it("fails to release a cancelled escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "AlreadyCancelled"
+      );
+    });
+
+    it("returns rent and tokens in one tx with auto_close", async () => {
+      const tokensBefore = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ autoClose: true })
+      );
+      const rent =
+        (await connection.getBalance(escrowPDA)) +
+        (await connection.getBalance(vaultPDA));
+      const lamportsBefore = await connection.getBalance(buyer.publicKey);
+
+      // The provider wallet pays the tx fee, so the buyer gains exactly rent
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+      expect(await connection.getAccountInfo(vaultPDA)).to.be.null;
+      expect(await connection.getBalance(buyer.publicKey)).to.equal(
+        lamportsBefore + rent
+      );
+      const tokensAfter = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(tokensAfter).to.equal(tokensBefore);
+    });
+
+    it("keeps the accounts open without auto_close", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.not.be.null;
+      expect(await connection.getAccountInfo(vaultPDA)).to.not.be.null;
+    });
+  });
+
+  describe("decimals", () => {
+    it("records mint decimals and settles with them", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.decimals).to.equal(DECIMALS);
+
+      // Decimals still match the mint, so transfer_checked succeeds
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+  });
+
+  describe("escrow kinds", () => {
+    it("records the kind chosen at initialize", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions({
+            kind: { milestone: {} },
+            milestones: [new BN(DEPOSIT_AMOUNT)],
+          })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.escrowType).to.deep.equal({ milestone: {} });
+    });
+
+    it("rejects release on a milestone escrow", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions({
+            kind: { milestone: {} },
+            milestones: [new BN(DEPOSIT_AMOUNT)],
+          })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      try {
+        await program.methods
+          .release(new BN(1))
           .accounts({
             buyer: buyer.publicKey,
             mint: mint,
             escrow: escrowPDA,
             vault: vaultPDA,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            settleAuthority: null,
+            oracle: null,
+            buyerTokenAccount: null,
+            referrerTokenAccount: null,
+            seller: null,
+            payer: null,
+            associatedTokenProgram: null,
+            systemProgram: null,
+            instructions: null,
+            unwrapAccount: null,
+            receipt: null,
+            relayer: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("WrongEscrowKind");
+      }
+    });
+
+    it("rejects an NFT escrow for a fungible deposit", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      try {
+        await program.methods
+          .initialize(
+            new BN(DEPOSIT_AMOUNT),
+            futureDeadline(3600),
+            defaultOptions({ kind: { nft: {} } })
+          )
+          .accounts({
+            buyer: buyer.publicKey,
+            seller: seller.publicKey,
+            mint: mint,
             buyerTokenAccount: buyerTokenAccount,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            config: configPDA,
+            treasury: null,
+            approvedBuyer: null,
+            template: null,
+            approvedSeller: null,
+            sellerTokenAccount: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("WrongEscrowKind");
+      }
+    });
+
+    it("rejects cancel_native on a standard wSOL escrow", async () => {
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 2;
+      const [escrowPDA] = getEscrowPDA(NATIVE_MINT);
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      const buyerWsol = await createWrappedNativeAccount(
+        connection,
+        buyer,
+        buyer.publicKey,
+        amount,
+        Keypair.generate(),
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await program.methods
+        .initialize(new BN(amount), futureDeadline(3600), defaultOptions())
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: NATIVE_MINT,
+          buyerTokenAccount: buyerWsol,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      try {
+        await program.methods
+          .cancelNative(new BN(1))
+          .accounts({
+            buyer: buyer.publicKey,
+            mint: NATIVE_MINT,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerWsol,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            instructions: null,
+            relayer: null,
             tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([buyer])
           .rpc();
         expect.fail("Should have thrown");
       } catch (err: any) {
-        expect(err.toString()).to.include("NotLocked");
+        expect(err.toString()).to.include("WrongEscrowKind");
+      }
+    });
+
+    async function initUnwrapping(amount: number) {
+      const [escrowPDA] = getEscrowPDA(NATIVE_MINT);
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      const buyerWsol = await createWrappedNativeAccount(
+        connection,
+        buyer,
+        buyer.publicKey,
+        amount,
+        Keypair.generate(),
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await program.methods
+        .initialize(
+          new BN(amount),
+          futureDeadline(3600),
+          defaultOptions({ unwrapOnRelease: true })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: NATIVE_MINT,
+          buyerTokenAccount: buyerWsol,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const [unwrapPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("unwrap"), escrowPDA.toBuffer()],
+        program.programId
+      );
+      return { escrowPDA, vaultPDA, unwrapPDA };
+    }
+
+    // The seller needs no wSOL account; its wallet stands in
+    function unwrappingReleaseIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      unwrapPDA: PublicKey
+    ) {
+      return releaseIx(escrowPDA, vaultPDA, {
+        mint: NATIVE_MINT,
+        sellerTokenAccount: seller.publicKey,
+        seller: seller.publicKey,
+        payer: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
+        unwrapAccount: unwrapPDA,
+      });
+    }
+
+    it("unwraps a wSOL release into the seller's native balance", async () => {
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 2;
+      const { escrowPDA, vaultPDA, unwrapPDA } = await initUnwrapping(amount);
+      const before = await connection.getBalance(seller.publicKey);
+
+      await unwrappingReleaseIx(escrowPDA, vaultPDA, unwrapPDA).rpc();
+
+      const after = await connection.getBalance(seller.publicKey);
+      expect(after - before).to.equal(amount);
+      expect(await connection.getAccountInfo(unwrapPDA)).to.be.null;
+    });
+
+    it("unwraps even when the temporary account's address was pre-funded", async () => {
+      seller = Keypair.generate();
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 2;
+      const { escrowPDA, vaultPDA, unwrapPDA } = await initUnwrapping(amount);
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: unwrapPDA,
+            lamports: 1,
+          })
+        )
+      );
+
+      await unwrappingReleaseIx(escrowPDA, vaultPDA, unwrapPDA).rpc();
+
+      expect(await connection.getBalance(seller.publicKey)).to.equal(amount);
+      expect(await connection.getAccountInfo(unwrapPDA)).to.be.null;
+    });
+
+    it("rejects unwrap_on_release on a non-native mint", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ unwrapOnRelease: true })),
+        "NotNativeMint"
+      );
+    });
+  });
+
+  describe("milestones", () => {
+    function milestoneOptions(parts: number[]) {
+      return defaultOptions({
+        kind: { milestone: {} },
+        milestones: parts.map((p) => new BN(p)),
+      });
+    }
+
+    function releaseMilestoneIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      index: number
+    ) {
+      return program.methods
+        .releaseMilestone(index)
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    it("accepts exactly MAX_MILESTONES milestones", async () => {
+      const part = DEPOSIT_AMOUNT / MAX_MILESTONES;
+      const { escrowPDA } = await initEscrow(
+        milestoneOptions(new Array(MAX_MILESTONES).fill(part))
+      );
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.milestones.length).to.equal(MAX_MILESTONES);
+    });
+
+    it("rejects more than MAX_MILESTONES milestones", async () => {
+      const count = MAX_MILESTONES + 1;
+      const parts = new Array(count).fill(1);
+      await expectError(
+        initEscrow(milestoneOptions(parts), count),
+        "TooManyMilestones"
+      );
+    });
+
+    it("rejects milestones that don't sum to the amount", async () => {
+      await expectError(
+        initEscrow(milestoneOptions([400_000, 400_000])),
+        "MilestoneSumMismatch"
+      );
+    });
+
+    it("releases milestones one at a time", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        milestoneOptions([300_000, 700_000])
+      );
+
+      await releaseMilestoneIx(escrowPDA, vaultPDA, 0).rpc();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.amount.toNumber()).to.equal(700_000);
+
+      await expectError(
+        releaseMilestoneIx(escrowPDA, vaultPDA, 0).rpc(),
+        "MilestoneAlreadyReleased"
+      );
+
+      await releaseMilestoneIx(escrowPDA, vaultPDA, 1).rpc();
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("emits EscrowSettled on the final milestone", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        milestoneOptions([300_000, 700_000])
+      );
+
+      await releaseMilestoneIx(escrowPDA, vaultPDA, 0).rpc();
+      const sig = await releaseMilestoneIx(escrowPDA, vaultPDA, 1).rpc({
+        commitment: "confirmed",
+      });
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ released: {} });
+      expect(event.amount.toNumber()).to.equal(700_000);
+    });
+
+    it("rejects release_milestone on a standard escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        releaseMilestoneIx(escrowPDA, vaultPDA, 0).rpc(),
+        "WrongEscrowKind"
+      );
+    });
+  });
+
+  describe("seller confirmation", () => {
+    const pendingOptions = () => defaultOptions({ requireSellerConfirm: true });
+
+    function confirmSellerIx(escrowPDA: PublicKey, signer: Keypair) {
+      return program.methods
+        .confirmSeller()
+        .accounts({
+          seller: signer.publicKey,
+          escrow: escrowPDA,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .signers([signer]);
+    }
+
+    beforeEach(async () => {
+      await airdrop(seller.publicKey);
+    });
+
+    it("locks the escrow once the seller confirms", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(pendingOptions());
+
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ pendingSellerConfirm: {} });
+      expect(escrow.pendingSeller.toString()).to.equal(
+        seller.publicKey.toString()
+      );
+
+      // No release until the seller has confirmed
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "SellerNotConfirmed"
+      );
+
+      await confirmSellerIx(escrowPDA, seller).rpc();
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("lets the buyer cancel before the seller confirms", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(pendingOptions());
+
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+      const buyerAccount = await getAccount(connection, buyerTokenAccount);
+      expect(Number(buyerAccount.amount)).to.equal(DEPOSIT_AMOUNT * 10);
+    });
+
+    it("rejects confirmation from anyone but the seller", async () => {
+      const { escrowPDA } = await initEscrow(pendingOptions());
+      const stranger = Keypair.generate();
+      await airdrop(stranger.publicKey);
+
+      try {
+        await confirmSellerIx(escrowPDA, stranger).rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        // PDA seeds or has_one constraint will fail
+        expect(err).to.exist;
+      }
+    });
+  });
+
+  describe("cancel_native", () => {
+    it("refunds a wSOL escrow as native lamports", async () => {
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 2;
+      const [escrowPDA] = getEscrowPDA(NATIVE_MINT);
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      const deadline = futureDeadline(3600);
+
+      // Wrap SOL into a token account owned by the buyer
+      const buyerWsol = await createWrappedNativeAccount(
+        connection,
+        buyer,
+        buyer.publicKey,
+        amount,
+        Keypair.generate(),
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await program.methods
+        .initialize(
+          new BN(amount),
+          deadline,
+          defaultOptions({ kind: { native: {} } })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: NATIVE_MINT,
+          buyerTokenAccount: buyerWsol,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const balanceBefore = await connection.getBalance(buyer.publicKey);
+
+      await program.methods
+        .cancelNative(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: NATIVE_MINT,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerWsol,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          instructions: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      // Buyer receives the refund (plus the wSOL account rent) minus the tx fee
+      const balanceAfter = await connection.getBalance(buyer.publicKey);
+      expect(balanceAfter - balanceBefore).to.be.greaterThan(amount - 10_000);
+
+      // The temporary wSOL account is gone
+      expect(await connection.getAccountInfo(buyerWsol)).to.be.null;
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+
+    // A Native escrow of `amount` lamports, funded from a fresh wSOL account
+    async function initNative(amount: number) {
+      const [escrowPDA] = getEscrowPDA(NATIVE_MINT);
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      const buyerWsol = await createWrappedNativeAccount(
+        connection,
+        buyer,
+        buyer.publicKey,
+        amount,
+        Keypair.generate(),
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      await program.methods
+        .initialize(
+          new BN(amount),
+          futureDeadline(3600),
+          defaultOptions({ kind: { native: {} } })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: NATIVE_MINT,
+          buyerTokenAccount: buyerWsol,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      return { escrowPDA, vaultPDA, buyerWsol };
+    }
+
+    function cancelNativeIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      buyerWsol: PublicKey,
+      overrides: any = {}
+    ) {
+      return program.methods
+        .cancelNative(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: NATIVE_MINT,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerWsol,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          instructions: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...overrides,
+        })
+        .signers([buyer]);
+    }
+
+    it("charges the cancel fee like cancel", async () => {
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 10;
+      await setConfig({ cancelFeeBps: 100 });
+      try {
+        const { escrowPDA, vaultPDA, buyerWsol } = await initNative(amount);
+        const feeWsol = await createWrappedNativeAccount(
+          connection,
+          buyer,
+          feeRecipient.publicKey,
+          0,
+          Keypair.generate(),
+          undefined,
+          TOKEN_PROGRAM_ID
+        );
+
+        await expectError(
+          cancelNativeIx(escrowPDA, vaultPDA, buyerWsol).rpc(),
+          "MissingFeeAccount"
+        );
+        await cancelNativeIx(escrowPDA, vaultPDA, buyerWsol, {
+          feeTokenAccount: feeWsol,
+        }).rpc();
+
+        const fee = await getAccount(connection, feeWsol);
+        expect(Number(fee.amount)).to.equal(amount / 100);
+        const escrow = await program.account.escrow.fetch(escrowPDA);
+        expect(escrow.state).to.deep.equal({ cancelled: {} });
+      } finally {
+        await setConfig();
+      }
+    });
+
+    it("rejects a token program other than the escrow's", async () => {
+      const { escrowPDA, vaultPDA, buyerWsol } = await initNative(
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+      await expectError(
+        cancelNativeIx(escrowPDA, vaultPDA, buyerWsol, {
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        }).rpc(),
+        "TokenProgramMismatch"
+      );
+    });
+
+    it("rejects a non-native mint", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      const deadline = futureDeadline(3600);
+
+      await program.methods
+        .initialize(new BN(DEPOSIT_AMOUNT), deadline, defaultOptions())
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      try {
+        await program.methods
+          .cancelNative(new BN(1))
+          .accounts({
+            buyer: buyer.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            instructions: null,
+            relayer: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("NotNativeMint");
+      }
+    });
+  });
+
+  describe("fees", () => {
+    let feeTokenAccount: PublicKey;
+
+    beforeEach(async () => {
+      feeTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        feeRecipient.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+    });
+
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    async function releaseWithFee(amount: number) {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(new BN(amount), futureDeadline(3600), defaultOptions())
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      await program.methods
+        .release(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: feeTokenAccount,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const fee = Number(
+        (await getAccount(connection, feeTokenAccount)).amount
+      );
+      const sellerAmount = Number(
+        (await getAccount(connection, sellerTokenAccount)).amount
+      );
+      return { fee, sellerAmount };
+    }
+
+    // 1_000_030 * 250 bps = 25_000.75 and 1_000_010 * 250 bps = 25_000.25
+    const cases = [
+      { rounding: { down: {} }, amount: 1_000_030, fee: 25_000 },
+      { rounding: { up: {} }, amount: 1_000_010, fee: 25_001 },
+      { rounding: { nearest: {} }, amount: 1_000_030, fee: 25_001 },
+      { rounding: { nearest: {} }, amount: 1_000_010, fee: 25_000 },
+    ];
+
+    for (const c of cases) {
+      const mode = Object.keys(c.rounding)[0];
+      it(`rounds ${mode}: ${c.amount} at 250 bps -> fee ${c.fee}`, async () => {
+        await setConfig({ feeBps: 250, feeRounding: c.rounding });
+
+        const { fee, sellerAmount } = await releaseWithFee(c.amount);
+        expect(fee).to.equal(c.fee);
+        expect(sellerAmount).to.equal(c.amount - c.fee);
+      });
+    }
+
+    it("leaves the vault at exactly zero after a fee settlement", async () => {
+      await setConfig({ feeBps: 333, feeRounding: { up: {} } });
+      const amount = 1_000_001;
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        amount
+      );
+
+      // Stray tokens sent straight to the vault are swept with the payout
+      await mintTo(
+        connection,
+        buyer,
+        mint,
+        vaultPDA,
+        buyer,
+        7,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await program.methods
+        .release(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: feeTokenAccount,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const vaultAccount = await getAccount(connection, vaultPDA);
+      expect(Number(vaultAccount.amount)).to.equal(0);
+
+      const fee = Number(
+        (await getAccount(connection, feeTokenAccount)).amount
+      );
+      const sellerAmount = Number(
+        (await getAccount(connection, sellerTokenAccount)).amount
+      );
+      expect(fee + sellerAmount).to.equal(amount + 7);
+    });
+
+    it("requires the fee account when a fee is charged", async () => {
+      await setConfig({ feeBps: 250 });
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions()
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      try {
+        await program.methods
+          .release(new BN(1))
+          .accounts({
+            buyer: buyer.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            settleAuthority: null,
+            oracle: null,
+            buyerTokenAccount: null,
+            referrerTokenAccount: null,
+            seller: null,
+            payer: null,
+            associatedTokenProgram: null,
+            systemProgram: null,
+            instructions: null,
+            unwrapAccount: null,
+            receipt: null,
+            relayer: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("MissingFeeAccount");
+      }
+    });
+
+    it("rejects a fee above 10,000 bps", async () => {
+      try {
+        await setConfig({ feeBps: 10_001 });
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err.toString()).to.include("InvalidFee");
+      }
+    });
+  });
+
+  describe("disputes", () => {
+    let arbiter: Keypair;
+
+    beforeEach(async () => {
+      arbiter = Keypair.generate();
+      await airdrop(seller.publicKey);
+    });
+
+    function openDisputeIx(escrowPDA: PublicKey, party: Keypair) {
+      return program.methods
+        .openDispute()
+        .accounts({ party: party.publicKey, escrow: escrowPDA })
+        .signers([party]);
+    }
+
+    it("blocks release and cancel while a dispute is open", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey })
+      );
+
+      await openDisputeIx(escrowPDA, seller).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.disputeOpen).to.equal(true);
+
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "DisputeActive");
+      await expectError(cancelIx(escrowPDA, vaultPDA).rpc(), "DisputeActive");
+    });
+
+    it("lets the arbiter resolve in favor of the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey })
+      );
+      await openDisputeIx(escrowPDA, buyer).rpc();
+
+      const sig = await program.methods
+        .resolveDispute(false)
+        .accounts({
+          arbiter: arbiter.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          arbiterTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([arbiter])
+        .rpc({ commitment: "confirmed" });
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+      expect(escrow.disputeOpen).to.equal(false);
+
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ cancelled: {} });
+      expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+
+      const buyerAccount = await getAccount(connection, buyerTokenAccount);
+      expect(Number(buyerAccount.amount)).to.equal(DEPOSIT_AMOUNT * 10);
+    });
+
+    it("requires the memo and callback on a ruling", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          arbiter: arbiter.publicKey,
+          callbackProgram: Keypair.generate().publicKey,
+          callbackStrict: true,
+        })
+      );
+      await openDisputeIx(escrowPDA, buyer).rpc();
+
+      const resolveIx = (overrides: any) =>
+        program.methods
+          .resolveDispute(false)
+          .accounts({
+            arbiter: arbiter.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerTokenAccount,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            arbiterTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            ...overrides,
+          })
+          .signers([arbiter]);
+
+      await setConfig({ requireMemo: true });
+      try {
+        await expectError(resolveIx({}).rpc(), "MissingMemoProgram");
+      } finally {
+        await setConfig();
+      }
+      await expectError(resolveIx({}).rpc(), "CallbackFailed");
+    });
+
+    it("charges fees only on what the seller keeps after a ruling for the buyer", async () => {
+      const feeTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        feeRecipient.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      await setConfig({ feeBps: 250 });
+      try {
+        const { escrowPDA, vaultPDA } = await initEscrow(
+          defaultOptions({ arbiter: arbiter.publicKey })
+        );
+        await program.methods
+          .releasePartial(new BN(400_000))
+          .accounts({
+            buyer: buyer.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount,
+            callbackProgram: null,
+            memoProgram: null,
+            settleAuthority: null,
+            oracle: null,
+            buyerTokenAccount: null,
+            referrerTokenAccount: null,
+            seller: null,
+            payer: null,
+            associatedTokenProgram: null,
+            systemProgram: null,
+            instructions: null,
+            unwrapAccount: null,
+            receipt: null,
+            relayer: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer])
+          .rpc();
+        await openDisputeIx(escrowPDA, buyer).rpc();
+
+        await program.methods
+          .resolveDispute(false)
+          .accounts({
+            arbiter: arbiter.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerTokenAccount,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount,
+            arbiterTokenAccount: null,
+            callbackProgram: null,
+            memoProgram: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([arbiter])
+          .rpc();
+
+        // 2.5% of the 400_000 released, none of the 600_000 refunded
+        const escrow = await program.account.escrow.fetch(escrowPDA);
+        expect(escrow.feeCollected.toNumber()).to.equal(10_000);
+        const fees = await getAccount(connection, feeTokenAccount);
+        expect(Number(fees.amount)).to.equal(10_000);
+        const sellerAccount = await getAccount(connection, sellerTokenAccount);
+        expect(Number(sellerAccount.amount)).to.equal(390_000);
+        const buyerAccount = await getAccount(connection, buyerTokenAccount);
+        expect(Number(buyerAccount.amount)).to.equal(
+          DEPOSIT_AMOUNT * 10 - 400_000
+        );
+      } finally {
+        await setConfig();
+      }
+    });
+
+    it("re-enables release after the arbiter closes the dispute", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey })
+      );
+      await openDisputeIx(escrowPDA, seller).rpc();
+
+      await program.methods
+        .closeDispute()
+        .accounts({ arbiter: arbiter.publicKey, escrow: escrowPDA })
+        .signers([arbiter])
+        .rpc();
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects disputes on escrows without an arbiter", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(openDisputeIx(escrowPDA, seller).rpc(), "NoArbiter");
+    });
+
+    it("extends a deadline too close for the arbiter to rule", async () => {
+      const deadline = futureDeadline(60);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey, disputeBufferSecs: 3600 }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+
+      const sig = await openDisputeIx(escrowPDA, buyer).rpc({
+        commitment: "confirmed",
+      });
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deadline.toNumber()).to.be.greaterThan(
+        deadline.toNumber() + 3000
+      );
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const event = [...parser.parseLogs(tx.meta.logMessages)].find(
+        (e) => e.name === "deadlineExtended"
+      );
+      expect(event.data.deadline.toNumber()).to.equal(
+        escrow.deadline.toNumber()
+      );
+    });
+
+    it("leaves a distant deadline alone", async () => {
+      const deadline = futureDeadline(7200);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey, disputeBufferSecs: 3600 }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await openDisputeIx(escrowPDA, buyer).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deadline.toNumber()).to.equal(deadline.toNumber());
+    });
+
+    it("pays the arbiter fee before the chosen party", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey, arbiterFeeBps: 500 })
+      );
+      await openDisputeIx(escrowPDA, seller).rpc();
+      const arbiterTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        arbiter.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      const resolveIx = (arbiterAccount: PublicKey | null) =>
+        program.methods
+          .resolveDispute(true)
+          .accounts({
+            arbiter: arbiter.publicKey,
+            mint: mint,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            buyerTokenAccount: buyerTokenAccount,
+            sellerTokenAccount: sellerTokenAccount,
+            config: configPDA,
+            feeTokenAccount: null,
+            arbiterTokenAccount: arbiterAccount,
+            callbackProgram: null,
+            memoProgram: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([arbiter]);
+
+      await expectError(resolveIx(null).rpc(), "MissingArbiterAccount");
+      const sig = await resolveIx(arbiterTokenAccount).rpc({
+        commitment: "confirmed",
+      });
+
+      const fee = (DEPOSIT_AMOUNT * 500) / 10_000;
+      const arbiterAccount = await getAccount(connection, arbiterTokenAccount);
+      expect(Number(arbiterAccount.amount)).to.equal(fee);
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT - fee);
+
+      // The arbiter's fee counts as paid out
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ released: {} });
+      expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects an arbiter fee without an arbiter", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ arbiterFeeBps: 500 })),
+        "InvalidFee"
+      );
+    });
+  });
+
+  describe("lamport fee", () => {
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    async function initWithTreasury(treasuryAccount: PublicKey | null) {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions()
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          config: configPDA,
+          treasury: treasuryAccount,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+    }
+
+    it("sends the SOL fee to the treasury", async () => {
+      const lamportFee = 5_000_000;
+      await setConfig({ lamportFee: new BN(lamportFee) });
+      const before = await connection.getBalance(treasury.publicKey);
+
+      await initWithTreasury(treasury.publicKey);
+
+      const after = await connection.getBalance(treasury.publicKey);
+      expect(after - before).to.equal(lamportFee);
+    });
+
+    it("requires the treasury when a SOL fee is set", async () => {
+      await setConfig({ lamportFee: new BN(5_000_000) });
+      await expectError(initWithTreasury(null), "MissingTreasury");
+    });
+
+    it("skips the SOL fee when it is zero", async () => {
+      const before = await connection.getBalance(treasury.publicKey);
+
+      await initWithTreasury(null);
+
+      const after = await connection.getBalance(treasury.publicKey);
+      expect(after).to.equal(before);
+    });
+  });
+
+  describe("expire and reopen", () => {
+    function expireIx(escrowPDA: PublicKey) {
+      return program.methods
+        .expire()
+        .accounts({ caller: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer]);
+    }
+
+    function reopenIx(
+      escrowPDA: PublicKey,
+      newDeadline: BN,
+      sellerSigner: Keypair
+    ) {
+      return program.methods
+        .reopen(newDeadline)
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: sellerSigner.publicKey,
+          escrow: escrowPDA,
+        })
+        .signers([buyer, sellerSigner]);
+    }
+
+    beforeEach(async () => {
+      await airdrop(seller.publicKey);
+    });
+
+    it("rejects expiring before the deadline", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(expireIx(escrowPDA).rpc(), "NotExpired");
+    });
+
+    it("reopens an expired escrow with both signatures", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+      await expireIx(escrowPDA).rpc();
+
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ expired: {} });
+
+      const newDeadline = futureDeadline(3600);
+      await reopenIx(escrowPDA, newDeadline, seller).rpc();
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.deadline.toNumber()).to.equal(newDeadline.toNumber());
+
+      // Back to normal: the buyer can release again
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("rejects a reopen without the seller's signature", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+      await expireIx(escrowPDA).rpc();
+
+      // A stranger signing in the seller's place fails the PDA / has_one check
+      const stranger = Keypair.generate();
+      try {
+        await reopenIx(escrowPDA, futureDeadline(3600), stranger).rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err).to.exist;
+      }
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ expired: {} });
+    });
+
+    it("rejects reopening an escrow that has not expired", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(
+        reopenIx(escrowPDA, futureDeadline(3600), seller).rpc(),
+        "NotExpired"
+      );
+    });
+
+    it("defaults to DEFAULT_MAX_EXTENSIONS reopens", async () => {
+      const { escrowPDA } = await initEscrow();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.maxExtensions).to.equal(DEFAULT_MAX_EXTENSIONS);
+      expect(escrow.extensionCount).to.equal(0);
+    });
+
+    it("rejects reopens beyond max_extensions", async () => {
+      let deadline = futureDeadline(2);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ maxExtensions: 1 }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+      await expireIx(escrowPDA).rpc();
+
+      deadline = futureDeadline(2);
+      await reopenIx(escrowPDA, deadline, seller).rpc();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.extensionCount).to.equal(1);
+
+      await waitForDeadline(deadline);
+      await expireIx(escrowPDA).rpc();
+      await expectError(
+        reopenIx(escrowPDA, futureDeadline(3600), seller).rpc(),
+        "TooManyExtensions"
+      );
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ expired: {} });
+    });
+  });
+
+  describe("settlement callback", () => {
+    const callbackProgram = Keypair.generate().publicKey;
+
+    it("records the callback program", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ callbackProgram, callbackStrict: true })
+      );
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.callbackProgram.toString()).to.equal(
+        callbackProgram.toString()
+      );
+      expect(escrow.callbackStrict).to.equal(true);
+    });
+
+    it("fails a strict release when the callback is missing", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ callbackProgram, callbackStrict: true })
+      );
+
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "CallbackFailed");
+    });
+
+    it("settles a lenient escrow without the callback", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ callbackProgram, callbackStrict: false })
+      );
+
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+  });
+
+  describe("reduce_amount", () => {
+    function reduceIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      newAmount: number
+    ) {
+      return program.methods
+        .reduceAmount(new BN(newAmount))
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer, seller]);
+    }
+
+    it("refunds the difference and keeps the escrow locked", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 100);
+      const [mintPolicyPDA] = getMintPolicyPDA();
+      const tvlBefore = (
+        await program.account.mintPolicy.fetch(mintPolicyPDA)
+      ).tvl.toNumber();
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+
+      await reduceIx(escrowPDA, vaultPDA, 60).rpc();
+
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(40);
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.amount.toNumber()).to.equal(60);
+      expect(escrow.deposit.toNumber()).to.equal(60);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      const policy = await program.account.mintPolicy.fetch(mintPolicyPDA);
+      expect(policy.tvl.toNumber()).to.equal(tvlBefore - 40);
+
+      const vaultAccount = await getAccount(connection, vaultPDA);
+      expect(Number(vaultAccount.amount)).to.equal(60);
+    });
+
+    it("rejects increasing the amount", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 100);
+      await expectError(
+        reduceIx(escrowPDA, vaultPDA, 150).rpc(),
+        "CannotIncrease"
+      );
+    });
+  });
+
+  describe("memo", () => {
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    async function releaseLogs(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      const sig = await releaseIx(escrowPDA, vaultPDA, {
+        memoProgram: MEMO_PROGRAM_ID,
+      }).rpc({ commitment: "confirmed" });
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.logMessages.join("\n");
+    }
+
+    it("attaches a memo naming the escrow when required", async () => {
+      await setConfig({ requireMemo: true });
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions());
+
+      const logs = await releaseLogs(escrowPDA, vaultPDA);
+      expect(logs).to.include(`escrow:${escrowPDA.toString()}:released`);
+    });
+
+    it("rejects a release without the memo program when required", async () => {
+      await setConfig({ requireMemo: true });
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions());
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "MissingMemoProgram"
+      );
+    });
+
+    it("skips the memo when not required", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions());
+
+      const logs = await releaseLogs(escrowPDA, vaultPDA);
+      expect(logs).to.not.include("escrow:");
+    });
+  });
+
+  describe("test clock", () => {
+    const [testClockPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("test_clock")],
+      program.programId
+    );
+    const clockAccount = {
+      pubkey: testClockPDA,
+      isSigner: false,
+      isWritable: false,
+    };
+
+    before(function () {
+      // Only present when built with `--features test-clock`
+      const enabled = program.idl.instructions.some(
+        (ix) => ix.name === "set_test_clock"
+      );
+      if (!enabled) this.skip();
+    });
+
+    async function setTestClock(unixTimestamp: BN) {
+      await (program.methods as any)
+        .setTestClock(unixTimestamp)
+        .accounts({
+          payer: provider.wallet.publicKey,
+          testClock: testClockPDA,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    function expireIx(escrowPDA: PublicKey) {
+      return program.methods
+        .expire()
+        .accounts({ caller: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer]);
+    }
+
+    it("expires early when the override is past the deadline", async () => {
+      const deadline = futureDeadline(3600);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await setTestClock(deadline.addn(1));
+
+      await expireIx(escrowPDA).remainingAccounts([clockAccount]).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ expired: {} });
+    });
+
+    it("uses the real clock without the override account", async () => {
+      const deadline = futureDeadline(3600);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await setTestClock(deadline.addn(1));
+
+      await expectError(expireIx(escrowPDA).rpc(), "NotExpired");
+    });
+  });
+
+  describe("settlement events", () => {
+    it("reports raw and display amounts on release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        1_500_000
+      );
+      const sig = await releaseIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+
+      const event = await settledEvent(sig);
+      expect(event.escrow.toString()).to.equal(escrowPDA.toString());
+      expect(event.outcome).to.deep.equal({ released: {} });
+      expect(event.amount.toNumber()).to.equal(1_500_000);
+      expect(event.decimals).to.equal(DECIMALS);
+      expect(event.uiAmount).to.equal("1.5");
+    });
+
+    it("reports the refunded amount on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const sig = await cancelIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ cancelled: {} });
+      expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      expect(event.uiAmount).to.equal("1");
+    });
+
+    it("numbers events per escrow without gaps", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.eventSeq.toNumber()).to.equal(1);
+
+      const sig = await releaseIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+      const event = await settledEvent(sig);
+      expect(event.seq.toNumber()).to.equal(1);
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.eventSeq.toNumber()).to.equal(2);
+    });
+  });
+
+  describe("cancel with notice period", () => {
+    const NOTICE_SECS = 2;
+
+    function requestCancelIx(escrowPDA: PublicKey) {
+      return program.methods
+        .requestCancel()
+        .accounts({ buyer: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer]);
+    }
+
+    function finalizeCancelIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .finalizeCancel()
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          instructions: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    it("rejects an immediate cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ noticeSecs: NOTICE_SECS })
+      );
+      await expectError(
+        cancelIx(escrowPDA, vaultPDA).rpc(),
+        "NoticePeriodActive"
+      );
+    });
+
+    it("rejects finalizing before the notice period ends", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ noticeSecs: 3600 })
+      );
+      await requestCancelIx(escrowPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelRequested: {} });
+
+      await expectError(
+        finalizeCancelIx(escrowPDA, vaultPDA).rpc(),
+        "NoticePeriodActive"
+      );
+    });
+
+    it("refunds the buyer after the notice period", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ noticeSecs: NOTICE_SECS })
+      );
+      await requestCancelIx(escrowPDA).rpc();
+      const requested = await program.account.escrow.fetch(escrowPDA);
+      await waitForDeadline(requested.cancelRequestedAt.addn(NOTICE_SECS));
+
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      await finalizeCancelIx(escrowPDA, vaultPDA).rpc();
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(DEPOSIT_AMOUNT);
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+
+    function objectIx(escrowPDA: PublicKey) {
+      return program.methods
+        .object()
+        .accounts({ seller: seller.publicKey, escrow: escrowPDA })
+        .signers([seller]);
+    }
+
+    it("lets the seller object and hands the escrow to the arbiter", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          noticeSecs: 3600,
+          arbiter: Keypair.generate().publicKey,
+        })
+      );
+      await requestCancelIx(escrowPDA).rpc();
+      await objectIx(escrowPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.objected).to.be.true;
+      expect(escrow.disputeOpen).to.be.true;
+
+      await expectError(
+        finalizeCancelIx(escrowPDA, vaultPDA).rpc(),
+        "ObjectionRaised"
+      );
+      await expectError(requestCancelIx(escrowPDA).rpc(), "ObjectionRaised");
+    });
+
+    it("rejects an objection after the notice period", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({
+          noticeSecs: NOTICE_SECS,
+          arbiter: Keypair.generate().publicKey,
+        })
+      );
+      await requestCancelIx(escrowPDA).rpc();
+      const requested = await program.account.escrow.fetch(escrowPDA);
+      await waitForDeadline(requested.cancelRequestedAt.addn(NOTICE_SECS));
+
+      await expectError(objectIx(escrowPDA).rpc(), "ObjectionWindowClosed");
+    });
+  });
+
+  describe("arbiter panel", () => {
+    let panel: Keypair[];
+
+    beforeEach(async () => {
+      panel = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    });
+
+    async function openPanelDispute(members: Keypair[]) {
+      const escrowAccounts = await initEscrow(
+        defaultOptions({ arbiters: members.map((a) => a.publicKey) })
+      );
+      await program.methods
+        .openDispute()
+        .accounts({ party: buyer.publicKey, escrow: escrowAccounts.escrowPDA })
+        .signers([buyer])
+        .rpc();
+      return escrowAccounts;
+    }
+
+    function voteIx(escrowPDA: PublicKey, arbiter: Keypair, toSeller: boolean) {
+      return program.methods
+        .voteDispute(toSeller)
+        .accounts({ arbiter: arbiter.publicKey, escrow: escrowPDA })
+        .signers([arbiter]);
+    }
+
+    function resolveIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      arbiter: Keypair,
+      toSeller: boolean
+    ) {
+      return program.methods
+        .resolveDispute(toSeller)
+        .accounts({
+          arbiter: arbiter.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          arbiterTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([arbiter]);
+    }
+
+    it("settles once a majority agrees", async () => {
+      const { escrowPDA, vaultPDA } = await openPanelDispute(panel);
+
+      await voteIx(escrowPDA, panel[0], true).rpc();
+      await expectError(
+        resolveIx(escrowPDA, vaultPDA, panel[0], true).rpc(),
+        "NoMajority"
+      );
+
+      await voteIx(escrowPDA, panel[1], true).rpc();
+      await resolveIx(escrowPDA, vaultPDA, panel[2], true).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("moves no funds on a split vote", async () => {
+      const pair = panel.slice(0, 2);
+      const { escrowPDA, vaultPDA } = await openPanelDispute(pair);
+
+      await voteIx(escrowPDA, pair[0], true).rpc();
+      await voteIx(escrowPDA, pair[1], false).rpc();
+
+      await expectError(
+        resolveIx(escrowPDA, vaultPDA, pair[0], true).rpc(),
+        "NoMajority"
+      );
+      await expectError(
+        resolveIx(escrowPDA, vaultPDA, pair[1], false).rpc(),
+        "NoMajority"
+      );
+
+      const vaultAccount = await getAccount(connection, vaultPDA);
+      expect(Number(vaultAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects a second vote from the same arbiter", async () => {
+      const { escrowPDA } = await openPanelDispute(panel);
+      await voteIx(escrowPDA, panel[0], true).rpc();
+      await expectError(
+        voteIx(escrowPDA, panel[0], false).rpc(),
+        "AlreadyVoted"
+      );
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.votesToSeller).to.equal(1);
+    });
+
+    it("rejects votes from outside the panel", async () => {
+      const { escrowPDA } = await openPanelDispute(panel);
+      await expectError(
+        voteIx(escrowPDA, Keypair.generate(), true).rpc(),
+        "NotAnArbiter"
+      );
+    });
+  });
+
+  describe("settle_on_expire", () => {
+    function settleIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      feeTokenAccount: PublicKey | null = null
+    ) {
+      return program.methods
+        .settleOnExpire()
+        .accounts({
+          caller: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    const cases = [
+      { bps: 0, toBuyer: 0, state: { settled: {} } },
+      { bps: 5_000, toBuyer: DEPOSIT_AMOUNT / 2, state: { settled: {} } },
+      { bps: 10_000, toBuyer: DEPOSIT_AMOUNT, state: { refunded: {} } },
+    ];
+
+    for (const c of cases) {
+      it(`splits ${c.bps / 100}% to the buyer after the deadline`, async () => {
+        const deadline = futureDeadline(2);
+        const { escrowPDA, vaultPDA } = await initEscrow(
+          defaultOptions({ expireBuyerBps: c.bps }),
+          DEPOSIT_AMOUNT,
+          deadline
+        );
+        const buyerBefore = Number(
+          (await getAccount(connection, buyerTokenAccount)).amount
+        );
+        await waitForDeadline(deadline);
+
+        await settleIx(escrowPDA, vaultPDA).rpc();
+
+        const buyerAfter = Number(
+          (await getAccount(connection, buyerTokenAccount)).amount
+        );
+        const sellerAccount = await getAccount(connection, sellerTokenAccount);
+        expect(buyerAfter - buyerBefore).to.equal(c.toBuyer);
+        expect(Number(sellerAccount.amount)).to.equal(
+          DEPOSIT_AMOUNT - c.toBuyer
+        );
+
+        const escrow = await program.account.escrow.fetch(escrowPDA);
+        expect(escrow.state).to.deep.equal(c.state);
+      });
+    }
+
+    it("reports the seller's share and records its fee", async () => {
+      await setConfig({ feeBps: 100 });
+      try {
+        const feeTokenAccount = await createAccount(
+          connection,
+          buyer,
+          mint,
+          feeRecipient.publicKey,
+          Keypair.generate(),
+          undefined,
+          TOKEN_PROGRAM_ID
+        );
+        const deadline = futureDeadline(2);
+        const { escrowPDA, vaultPDA } = await initEscrow(
+          defaultOptions({ expireBuyerBps: 5_000 }),
+          DEPOSIT_AMOUNT,
+          deadline
+        );
+        await waitForDeadline(deadline);
+
+        const sig = await settleIx(escrowPDA, vaultPDA, feeTokenAccount).rpc({
+          commitment: "confirmed",
+        });
+
+        const tx = await connection.getTransaction(sig, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        });
+        const parser = new anchor.EventParser(program.programId, program.coder);
+        const event = [...parser.parseLogs(tx.meta.logMessages)].find(
+          (e) => e.name === "escrowSettled"
+        ).data as any;
+        expect(event.outcome).to.deep.equal({ released: {} });
+        expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT / 2);
+
+        // 1% of the seller's half
+        const escrow = await program.account.escrow.fetch(escrowPDA);
+        expect(escrow.feeCollected.toNumber()).to.equal(DEPOSIT_AMOUNT / 200);
+      } finally {
+        await setConfig();
+      }
+    });
+
+    it("rejects settling before the deadline", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ expireBuyerBps: 5_000 })
+      );
+      await expectError(settleIx(escrowPDA, vaultPDA).rpc(), "NotExpired");
+    });
+
+    it("rejects a split above 100%", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ expireBuyerBps: 10_001 })),
+        "InvalidSplit"
+      );
+    });
+  });
+
+  describe("verify_bumps", () => {
+    function verifyIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .verifyBumps()
+        .accounts({ escrow: escrowPDA, vault: vaultPDA });
+    }
+
+    it("leaves canonical bumps untouched", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const [, bump] = getEscrowPDA();
+      const [, vaultBump] = getVaultPDA(escrowPDA);
+
+      await verifyIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.bump).to.equal(bump);
+      expect(escrow.vaultBump).to.equal(vaultBump);
+    });
+
+    it("rejects a vault that is not the escrow's PDA", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(
+        verifyIx(escrowPDA, Keypair.generate().publicKey).rpc(),
+        "BumpMismatch"
+      );
+    });
+
+    it("repairs a stale stored bump", async function () {
+      // Only present when built with `--features test-bumps`
+      const enabled = program.idl.instructions.some(
+        (ix) => ix.name === "set_test_bumps"
+      );
+      if (!enabled) this.skip();
+
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const [, bump] = getEscrowPDA();
+      const [, vaultBump] = getVaultPDA(escrowPDA);
+      await (program.methods as any)
+        .setTestBumps((bump + 1) % 256, (vaultBump + 1) % 256)
+        .accounts({ escrow: escrowPDA })
+        .rpc();
+
+      await verifyIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.bump).to.equal(bump);
+      expect(escrow.vaultBump).to.equal(vaultBump);
+    });
+  });
+
+  describe("program-controlled seller", () => {
+    const stub = anchor.workspace.sellerStub as Program<SellerStub>;
+    const [sellerPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("seller")],
+      stub.programId
+    );
+
+    // Escrow whose seller is the stub program's PDA
+    async function initPdaSellerEscrow(sellerProgram: PublicKey) {
+      const [escrowPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          buyer.publicKey.toBuffer(),
+          sellerPDA.toBuffer(),
+          mint.toBuffer(),
+        ],
+        program.programId
+      );
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions({ requireSellerConfirm: true, sellerProgram })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: sellerPDA,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      return escrowPDA;
+    }
+
+    function stubConfirmIx(escrowPDA: PublicKey) {
+      return stub.methods.confirm().accounts({
+        seller: sellerPDA,
+        escrow: escrowPDA,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        escrowProgram: program.programId,
+      });
+    }
+
+    it("accepts a confirmation via CPI from the seller program", async () => {
+      const escrowPDA = await initPdaSellerEscrow(stub.programId);
+
+      await stubConfirmIx(escrowPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects a CPI from a program other than the stored one", async () => {
+      const escrowPDA = await initPdaSellerEscrow(Keypair.generate().publicKey);
+      await expectError(
+        stubConfirmIx(escrowPDA).rpc(),
+        "UnauthorizedProgram"
+      );
+    });
+
+    it("rejects a direct signature when a seller program is set", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      await initEscrow(
+        defaultOptions({
+          requireSellerConfirm: true,
+          sellerProgram: stub.programId,
+        })
+      );
+      await airdrop(seller.publicKey);
+
+      await expectError(
+        program.methods
+          .confirmSeller()
+          .accounts({
+            seller: seller.publicKey,
+            escrow: escrowPDA,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          })
+          .signers([seller])
+          .rpc(),
+        "UnauthorizedProgram"
+      );
+    });
+  });
+
+  describe("release_partial", () => {
+    function releasePartialIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      amount: number,
+      overrides: any = {}
+    ) {
+      return program.methods
+        .releasePartial(new BN(amount))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...overrides,
+        })
+        .signers([buyer]);
+    }
+
+    it("pays out in parts and releases when empty", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.amount.toNumber()).to.equal(200);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+
+      await releasePartialIx(escrowPDA, vaultPDA, 200).rpc();
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      expect(escrow.releaseCount).to.equal(2);
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(300);
+    });
+
+    it("emits EscrowSettled on the final partial release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+      const sig = await releasePartialIx(escrowPDA, vaultPDA, 200).rpc({
+        commitment: "confirmed",
+      });
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ released: {} });
+      expect(event.amount.toNumber()).to.equal(200);
+    });
+
+    it("writes the memo on the final partial release only", async () => {
+      await setConfig({ requireMemo: true });
+      try {
+        const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+
+        await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+        await expectError(
+          releasePartialIx(escrowPDA, vaultPDA, 200).rpc(),
+          "MissingMemoProgram"
+        );
+
+        const sig = await releasePartialIx(escrowPDA, vaultPDA, 200, {
+          memoProgram: MEMO_PROGRAM_ID,
+        }).rpc({ commitment: "confirmed" });
+        const tx = await connection.getTransaction(sig, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        });
+        expect(tx.meta.logMessages.join("\n")).to.include(
+          `escrow:${escrowPDA.toString()}:released`
+        );
+      } finally {
+        await setConfig();
+      }
+    });
+
+    it("notifies the callback on the final partial release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          callbackProgram: Keypair.generate().publicKey,
+          callbackStrict: true,
+        }),
+        300
+      );
+
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+      await expectError(
+        releasePartialIx(escrowPDA, vaultPDA, 200).rpc(),
+        "CallbackFailed"
+      );
+    });
+
+    it("rejects dust partial releases but allows the final sweep", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ minReleaseAmount: new BN(100) }),
+        300
+      );
+
+      await expectError(
+        releasePartialIx(escrowPDA, vaultPDA, 50).rpc(),
+        "ReleaseBelowMinimum"
+      );
+      await releasePartialIx(escrowPDA, vaultPDA, 250).rpc();
+      // Only 50 left: below the minimum, but it clears the balance
+      await releasePartialIx(escrowPDA, vaultPDA, 50).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects a full release once partials have drained the escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+      await releasePartialIx(escrowPDA, vaultPDA, 300).rpc();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "AlreadyReleased"
+      );
+    });
+
+    it("closes out a locked escrow with nothing left without a transfer", async function () {
+      // Only present when built with `--features test-amount`
+      const enabled = program.idl.instructions.some(
+        (ix) => ix.name === "set_test_amount"
+      );
+      if (!enabled) this.skip();
+
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+      await (program.methods as any)
+        .setTestAmount(new BN(0))
+        .accounts({ escrow: escrowPDA })
+        .rpc();
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(0);
+    });
+
+    it("reports the vault balance after a partial release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+
+      const balance = await program.methods
+        .getVaultBalance()
+        .accounts({ escrow: escrowPDA, vault: vaultPDA })
+        .view();
+      const vault = await getAccount(connection, vaultPDA);
+
+      expect(balance.toNumber()).to.equal(Number(vault.amount));
+      expect(balance.toNumber()).to.equal(200);
+    });
+
+    it("snapshots the vault balance across a partial release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+      const snapshot = () =>
+        program.methods
+          .snapshot()
+          .accounts({ escrow: escrowPDA, vault: vaultPDA })
+          .rpc();
+
+      await snapshot();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.lastSnapshotAmount.toNumber()).to.equal(300);
+      const firstAt = escrow.lastSnapshotAt.toNumber();
+      expect(firstAt).to.be.greaterThan(0);
+
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+      await snapshot();
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.lastSnapshotAmount.toNumber()).to.equal(200);
+      expect(escrow.lastSnapshotAt.toNumber()).to.be.at.least(firstAt);
+    });
+
+    it("rejects releases past max_releases", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ maxReleases: 2 }),
+        300
+      );
+
+      await releasePartialIx(escrowPDA, vaultPDA, 1).rpc();
+      await releasePartialIx(escrowPDA, vaultPDA, 1).rpc();
+      await expectError(
+        releasePartialIx(escrowPDA, vaultPDA, 1).rpc(),
+        "TooManyReleases"
+      );
+
+      // The full release is not a partial release and still works
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(300);
+    });
+
+    it("caps partial releases per day", async function () {
+      // Only present when built with `--features test-clock`
+      const enabled = program.idl.instructions.some(
+        (ix) => ix.name === "set_test_clock"
+      );
+      if (!enabled) this.skip();
+
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ maxReleasesPerDay: 2 }),
+        300
+      );
+      const [testClockPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("test_clock")],
+        program.programId
+      );
+      const setClock = (unixTimestamp: number) =>
+        (program.methods as any)
+          .setTestClock(new BN(unixTimestamp))
+          .accounts({
+            payer: provider.wallet.publicKey,
+            testClock: testClockPDA,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+      const release = () =>
+        releasePartialIx(escrowPDA, vaultPDA, 1).remainingAccounts([
+          { pubkey: testClockPDA, isSigner: false, isWritable: false },
+        ]);
+
+      const start = Math.floor(Date.now() / 1000);
+      await setClock(start);
+
+      await release().rpc();
+      await release().rpc();
+      await expectError(release().rpc(), "DailyReleaseLimit");
+
+      // A new window opens a day after the first release
+      await setClock(start + 86_400);
+      await release().rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.releaseCount).to.equal(3);
+      expect(escrow.releasesToday).to.equal(1);
+      expect(escrow.releasesWindowStart.toNumber()).to.equal(start + 86_400);
+    });
+
+    it("reports the creation parameters via get_escrow after extensions and reductions", async () => {
+      const deadline = futureDeadline(3600);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        300,
+        deadline
+      );
+
+      await program.methods
+        .extendBy(new BN(86_400))
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          config: configPDA,
+        })
+        .signers([buyer])
+        .rpc();
+      await releasePartialIx(escrowPDA, vaultPDA, 100).rpc();
+
+      // There is no separate status view: get_escrow is the one that
+      // returns the escrow's fields, the originals included
+      const escrow = await program.methods
+        .getEscrow()
+        .accounts({ escrow: escrowPDA })
+        .view();
+      expect(escrow.amount.toNumber()).to.equal(200);
+      expect(escrow.deadline.toNumber()).to.equal(deadline.toNumber() + 86_400);
+      expect(escrow.originalAmount.toNumber()).to.equal(300);
+      expect(escrow.originalDeadline.toNumber()).to.equal(deadline.toNumber());
+      expect(escrow.originalSeller.toString()).to.equal(
+        seller.publicKey.toString()
+      );
+    });
+
+    it("rejects releasing more than remains", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 300);
+      await expectError(
+        releasePartialIx(escrowPDA, vaultPDA, 301).rpc(),
+        "AmountExceedsEscrow"
+      );
+    });
+
+    it("refunds only the unreleased remainder on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), 100);
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+
+      await releasePartialIx(escrowPDA, vaultPDA, 40).rpc();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(60);
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(40);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+  });
+
+  describe("get_escrow", () => {
+    it("returns the same escrow as the account fetch", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ noticeSecs: 60, maxReleases: 3 })
+      );
+
+      const viewed = await program.methods
+        .getEscrow()
+        .accounts({ escrow: escrowPDA })
+        .view();
+      const fetched = await program.account.escrow.fetch(escrowPDA);
+
+      expect(JSON.stringify(viewed)).to.equal(JSON.stringify(fetched));
+      expect(viewed.buyer.toString()).to.equal(buyer.publicKey.toString());
+      expect(viewed.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+  });
+
+  describe("derive_vault", () => {
+    it("matches the vault PDA derived from the escrow", async () => {
+      const escrowKey = Keypair.generate().publicKey;
+      const [expected, expectedBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), escrowKey.toBuffer()],
+        program.programId
+      );
+
+      const derived = await program.methods
+        .deriveVault(escrowKey)
+        .accounts({ systemProgram: SystemProgram.programId })
+        .view();
+
+      expect(derived.address.toString()).to.equal(expected.toString());
+      expect(derived.bump).to.equal(expectedBump);
+    });
+  });
+
+  describe("get_status_label", () => {
+    function label(escrowPDA: PublicKey) {
+      return program.methods
+        .getStatusLabel()
+        .accounts({ escrow: escrowPDA })
+        .view();
+    }
+
+    it("shows days and hours left on a locked escrow", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        futureDeadline(3 * 86_400 + 4 * 3_600 + 1_800)
+      );
+      expect(await label(escrowPDA)).to.equal("Locked, 3d 4h remaining");
+    });
+
+    it("shows minutes and seconds when under an hour is left", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        futureDeadline(5 * 60 + 30)
+      );
+      expect(await label(escrowPDA)).to.match(/^Locked, 5m \d+s remaining$/);
+    });
+
+    it("flags a passed deadline", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+      expect(await label(escrowPDA)).to.equal("Locked, deadline passed");
+    });
+
+    it("names terminal states", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      expect(await label(escrowPDA)).to.equal("Released");
+    });
+  });
+
+  describe("approved buyers", () => {
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    function approvedBuyerPDA(buyerKey: PublicKey): PublicKey {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("approved_buyer"), buyerKey.toBuffer()],
+        program.programId
+      );
+      return pda;
+    }
+
+    async function setApproval(active: boolean) {
+      await program.methods
+        .setApprovedBuyer(buyer.publicKey, active)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPDA,
+          approvedBuyer: approvedBuyerPDA(buyer.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    async function initWithApproval(approvedBuyer: PublicKey | null) {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions()
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      return escrowPDA;
+    }
+
+    it("lets approved buyers initialize when permissioned", async () => {
+      await setConfig({ permissioned: true });
+      await setApproval(true);
+
+      const escrowPDA = await initWithApproval(
+        approvedBuyerPDA(buyer.publicKey)
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects unapproved buyers when permissioned", async () => {
+      await setConfig({ permissioned: true });
+      await expectError(initWithApproval(null), "BuyerNotApproved");
+    });
+
+    it("rejects revoked buyers when permissioned", async () => {
+      await setConfig({ permissioned: true });
+      await setApproval(true);
+      await setApproval(false);
+
+      await expectError(
+        initWithApproval(approvedBuyerPDA(buyer.publicKey)),
+        "BuyerNotApproved"
+      );
+    });
+
+    it("skips the check when not permissioned", async () => {
+      const escrowPDA = await initWithApproval(null);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("ignores a revoked entry when not permissioned", async () => {
+      await setApproval(false);
+      const escrowPDA = await initWithApproval(
+        approvedBuyerPDA(buyer.publicKey)
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+  });
+
+  describe("approved sellers", () => {
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    function approvedSellerPDA(sellerKey: PublicKey): PublicKey {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("approved_seller"), sellerKey.toBuffer()],
+        program.programId
+      );
+      return pda;
+    }
+
+    async function setApproval(active: boolean) {
+      await program.methods
+        .setApprovedSeller(seller.publicKey, active)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPDA,
+          approvedSeller: approvedSellerPDA(seller.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    async function initWithApproval(approvedSeller: PublicKey | null) {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions()
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      return escrowPDA;
+    }
+
+    it("lets approved sellers be named when permissioned", async () => {
+      await setConfig({ permissionedSellers: true });
+      await setApproval(true);
+
+      const escrowPDA = await initWithApproval(
+        approvedSellerPDA(seller.publicKey)
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects unapproved sellers when permissioned", async () => {
+      await setConfig({ permissionedSellers: true });
+      await expectError(initWithApproval(null), "SellerNotApproved");
+    });
+
+    it("rejects revoked sellers when permissioned", async () => {
+      await setConfig({ permissionedSellers: true });
+      await setApproval(true);
+      await setApproval(false);
+
+      await expectError(
+        initWithApproval(approvedSellerPDA(seller.publicKey)),
+        "SellerNotApproved"
+      );
+    });
+
+    it("allows any seller when not permissioned", async () => {
+      const escrowPDA = await initWithApproval(null);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("ignores a revoked entry when not permissioned", async () => {
+      await setApproval(false);
+      const escrowPDA = await initWithApproval(
+        approvedSellerPDA(seller.publicKey)
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+  });
+
+  describe("force_expire_and_refund", () => {
+    const keeper = Keypair.generate();
+
+    function forceRefundIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .forceExpireAndRefund()
+        .accounts({
+          caller: keeper.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          config: configPDA,
+          callbackProgram: null,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([keeper]);
+    }
+
+    it("refunds the buyer in one step after the deadline", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      await waitForDeadline(deadline);
+
+      await forceRefundIx(escrowPDA, vaultPDA).rpc();
+
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(DEPOSIT_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ refunded: {} });
+    });
+
+    it("reports a refunded outcome, distinct from cancel", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+
+      const sig = await forceRefundIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const event = [...parser.parseLogs(tx.meta.logMessages)].find(
+        (e) => e.name === "escrowSettled"
+      ).data as any;
+      expect(event.outcome).to.deep.equal({ refunded: {} });
+      expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("treats a refunded escrow as terminal", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+      await forceRefundIx(escrowPDA, vaultPDA).rpc();
+
+      await expectError(
+        forceRefundIx(escrowPDA, vaultPDA).rpc(),
+        "AlreadyRefunded"
+      );
+      await expectError(cancelIx(escrowPDA, vaultPDA).rpc(), "AlreadyRefunded");
+    });
+
+    it("rejects a refund before the deadline", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        forceRefundIx(escrowPDA, vaultPDA).rpc(),
+        "NotExpired"
+      );
+    });
+
+    it("rejects escrows whose expiry policy pays the seller", async () => {
+      const deadline = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ expireBuyerBps: 5_000 }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+
+      await expectError(
+        forceRefundIx(escrowPDA, vaultPDA).rpc(),
+        "RefundNotAllowed"
+      );
+    });
+  });
+
+  describe("oracle pricing", () => {
+    // Fixture loaded by Anchor.toml: 2.00 per token, published 1_760_000_000
+    const oracle = new PublicKey(
+      "2bTKY6oqR3LJKUm6NK9xAJknfj2XaxCroBA6urWhVLGt"
+    );
+    const PUBLISH_TIME = 1_760_000_000;
+    const ONE_DOLLAR = new BN(1_000_000);
+
+    const [testClockPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("test_clock")],
+      program.programId
+    );
+    const clockAccount = {
+      pubkey: testClockPDA,
+      isSigner: false,
+      isWritable: false,
+    };
+    const testClock = program.idl.instructions.some(
+      (ix) => ix.name === "set_test_clock"
+    );
+
+    function oracleOptions(referenceValue: BN) {
+      return defaultOptions({ oracle, referenceValue });
+    }
+
+    // Pin the clock next to the fixture's publish time
+    async function freshClock() {
+      await (program.methods as any)
+        .setTestClock(new BN(PUBLISH_TIME + 10))
+        .accounts({
+          payer: provider.wallet.publicKey,
+          testClock: testClockPDA,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    function oracleReleaseIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return releaseIx(escrowPDA, vaultPDA, {
+        oracle,
+        buyerTokenAccount,
+      }).remainingAccounts([clockAccount]);
+    }
+
+    it("stores the oracle and reference value", async () => {
+      const { escrowPDA } = await initEscrow(oracleOptions(ONE_DOLLAR));
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.oracle.toString()).to.equal(oracle.toString());
+      expect(escrow.referenceValue.toNumber()).to.equal(1_000_000);
+    });
+
+    it("rejects an oracle without a reference value", async () => {
+      await expectError(initEscrow(oracleOptions(new BN(0))), "ZeroAmount");
+    });
+
+    it("rejects a release without the oracle account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        oracleOptions(ONE_DOLLAR)
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "OraclePriceError"
+      );
+    });
+
+    it("rejects a stale price", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        oracleOptions(ONE_DOLLAR)
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, { oracle, buyerTokenAccount }).rpc(),
+        "StaleOracle"
+      );
+    });
+
+    it("pays the quoted amount and refunds the rest", async function () {
+      if (!testClock) this.skip();
+      await freshClock();
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        oracleOptions(ONE_DOLLAR)
+      );
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+
+      await oracleReleaseIx(escrowPDA, vaultPDA).rpc();
+
+      // $1 at $2 per token = 0.5 tokens
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(500_000);
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(DEPOSIT_AMOUNT - 500_000);
+    });
+
+    it("caps the payout at the deposit", async function () {
+      if (!testClock) this.skip();
+      await freshClock();
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        oracleOptions(new BN(5_000_000))
+      );
+
+      await oracleReleaseIx(escrowPDA, vaultPDA).rpc();
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+  });
+
+  describe("authorized caller", () => {
+    const stub = anchor.workspace.sellerStub as Program<SellerStub>;
+
+    function stubReleaseIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return stub.methods
+        .release(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          mintPolicy: getMintPolicyPDA()[0],
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          escrowProgram: program.programId,
+        })
+        .signers([buyer]);
+    }
+
+    it("allows a release via CPI from the authorized caller", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ authorizedCaller: stub.programId })
+      );
+
+      await stubReleaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects a release via CPI from another program", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ authorizedCaller: Keypair.generate().publicKey })
+      );
+
+      await expectError(
+        stubReleaseIx(escrowPDA, vaultPDA).rpc(),
+        "UnauthorizedCaller"
+      );
+    });
+
+    it("still allows a direct release by the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ authorizedCaller: Keypair.generate().publicKey })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+  });
+
+  describe("terms hash", () => {
+    const terms = "Deliver 10 widgets by Friday; full refund if late.";
+    const termsHash = Array.from(createHash("sha256").update(terms).digest());
+
+    it("stores the hash and emits it on initialize", async () => {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      const sig = await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions({ termsHash })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.termsHash).to.deep.equal(termsHash);
+
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const event = [...parser.parseLogs(tx.meta.logMessages)].find(
+        (e) => e.name === "escrowInitialized"
+      ).data as any;
+      expect(event.escrow.toString()).to.equal(escrowPDA.toString());
+      expect(event.termsHash).to.deep.equal(termsHash);
+      expect(event.seq.toNumber()).to.equal(0);
+    });
+
+    it("defaults to all zeros", async () => {
+      const { escrowPDA } = await initEscrow();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.termsHash).to.deep.equal(Array(32).fill(0));
+    });
+  });
+
+  describe("basket escrows", () => {
+    const ASSET_AMOUNT = 2_000_000;
+
+    type Asset = {
+      mint: PublicKey;
+      buyerAccount: PublicKey;
+      sellerAccount: PublicKey;
+    };
+
+    // Basket vaults are numbered from 1; the primary vault is index 0
+    function assetVaultPDA(escrowPDA: PublicKey, vaultIndex: number) {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), escrowPDA.toBuffer(), Buffer.from([vaultIndex])],
+        program.programId
+      );
+      return pda;
+    }
+
+    // A second mint with a funded buyer account and an empty seller account
+    async function createAsset(): Promise<Asset> {
+      const assetMint = await createMint(
+        connection,
+        buyer,
+        buyer.publicKey,
+        null,
+        DECIMALS,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const buyerAccount = await createAccount(
+        connection,
+        buyer,
+        assetMint,
+        buyer.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const sellerAccount = await createAccount(
+        connection,
+        buyer,
+        assetMint,
+        seller.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      await mintTo(
+        connection,
+        buyer,
+        assetMint,
+        buyerAccount,
+        buyer,
+        ASSET_AMOUNT,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      return { mint: assetMint, buyerAccount, sellerAccount };
+    }
+
+    function addAssetIx(escrowPDA: PublicKey, asset: Asset, vaultIndex = 1) {
+      return program.methods
+        .addAsset(new BN(ASSET_AMOUNT), vaultIndex)
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          primaryMint: mint,
+          mint: asset.mint,
+          buyerTokenAccount: asset.buyerAccount,
+          assetVault: assetVaultPDA(escrowPDA, vaultIndex),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer]);
+    }
+
+    function basketAccounts(
+      escrowPDA: PublicKey,
+      asset: Asset,
+      to: PublicKey,
+      vaultIndex = 1
+    ) {
+      return [
+        { pubkey: asset.mint, isSigner: false, isWritable: false },
+        {
+          pubkey: assetVaultPDA(escrowPDA, vaultIndex),
+          isSigner: false,
+          isWritable: true,
+        },
+        { pubkey: to, isSigner: false, isWritable: true },
+      ];
+    }
+
+    async function balance(account: PublicKey) {
+      return Number((await getAccount(connection, account)).amount);
+    }
+
+    it("records the asset and funds its vault", async () => {
+      const { escrowPDA } = await initEscrow();
+      const asset = await createAsset();
+
+      await addAssetIx(escrowPDA, asset).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.assets.length).to.equal(1);
+      expect(escrow.assets[0].mint.toString()).to.equal(
+        asset.mint.toString()
+      );
+      expect(escrow.assets[0].amount.toNumber()).to.equal(ASSET_AMOUNT);
+      expect(await balance(assetVaultPDA(escrowPDA, 1))).to.equal(
+        ASSET_AMOUNT
+      );
+    });
+
+    it("releases every asset to the seller together", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const asset = await createAsset();
+      await addAssetIx(escrowPDA, asset).rpc();
+
+      await releaseIx(escrowPDA, vaultPDA)
+        .remainingAccounts(
+          basketAccounts(escrowPDA, asset, asset.sellerAccount)
+        )
+        .rpc();
+
+      expect(await balance(sellerTokenAccount)).to.equal(DEPOSIT_AMOUNT);
+      expect(await balance(asset.sellerAccount)).to.equal(ASSET_AMOUNT);
+      expect(await balance(assetVaultPDA(escrowPDA, 1))).to.equal(0);
+    });
+
+    it("refunds every asset on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const asset = await createAsset();
+      await addAssetIx(escrowPDA, asset).rpc();
+
+      await cancelIx(escrowPDA, vaultPDA)
+        .remainingAccounts(basketAccounts(escrowPDA, asset, asset.buyerAccount))
+        .rpc();
+
+      expect(await balance(asset.buyerAccount)).to.equal(ASSET_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+
+    it("rejects a release without the basket accounts", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await addAssetIx(escrowPDA, await createAsset()).rpc();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "InvalidBasketAccounts"
+      );
+    });
+
+    it("rejects paying an asset to someone other than the seller", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const asset = await createAsset();
+      await addAssetIx(escrowPDA, asset).rpc();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA)
+          .remainingAccounts(
+            basketAccounts(escrowPDA, asset, asset.buyerAccount)
+          )
+          .rpc(),
+        "InvalidBasketAccounts"
+      );
+    });
+
+    it("derives a separate vault per index", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const assets = [await createAsset(), await createAsset()];
+      for (const [i, asset] of assets.entries()) {
+        await addAssetIx(escrowPDA, asset, i + 1).rpc();
+      }
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      const vaults = [
+        vaultPDA,
+        assetVaultPDA(escrowPDA, 1),
+        assetVaultPDA(escrowPDA, 2),
+      ];
+      expect(new Set(vaults.map((v) => v.toString())).size).to.equal(3);
+      for (const [i, asset] of assets.entries()) {
+        expect(escrow.assets[i].vaultIndex).to.equal(i + 1);
+        const vault = await getAccount(connection, vaults[i + 1]);
+        expect(vault.mint.toString()).to.equal(asset.mint.toString());
+        expect(Number(vault.amount)).to.equal(ASSET_AMOUNT);
+      }
+
+      await releaseIx(escrowPDA, vaultPDA)
+        .remainingAccounts([
+          ...basketAccounts(escrowPDA, assets[0], assets[0].sellerAccount, 1),
+          ...basketAccounts(escrowPDA, assets[1], assets[1].sellerAccount, 2),
+        ])
+        .rpc();
+      expect(await balance(assets[1].sellerAccount)).to.equal(ASSET_AMOUNT);
+    });
+
+    it("rejects a vault index other than the next free one", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(
+        addAssetIx(escrowPDA, await createAsset(), 2).rpc(),
+        "InvalidVaultIndex"
+      );
+    });
+
+    it("rejects more than MAX_BASKET_ASSETS assets", async () => {
+      const { escrowPDA } = await initEscrow();
+      for (let i = 1; i <= MAX_BASKET_ASSETS; i++) {
+        await addAssetIx(escrowPDA, await createAsset(), i).rpc();
+      }
+
+      await expectError(
+        addAssetIx(
+          escrowPDA,
+          await createAsset(),
+          MAX_BASKET_ASSETS + 1
+        ).rpc(),
+        "TooManyAssets"
+      );
+    });
+  });
+
+  describe("settle authority", () => {
+    const settler = Keypair.generate();
+
+    // Release signed only by `signer`; the buyer does not sign
+    function settlerReleaseIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      signer: Keypair,
+      destination: PublicKey = sellerTokenAccount
+    ) {
+      return program.methods
+        .release(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          settleAuthority: signer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: destination,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([signer]);
+    }
+
+    it("defaults to the buyer", async () => {
+      const { escrowPDA } = await initEscrow();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.settleAuthority.toString()).to.equal(
+        buyer.publicKey.toString()
+      );
+    });
+
+    it("lets the settle authority release without the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ settleAuthority: settler.publicKey })
+      );
+
+      await settlerReleaseIx(escrowPDA, vaultPDA, settler).rpc();
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("still lets the buyer release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ settleAuthority: settler.publicKey })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects paying the settle authority's own account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ settleAuthority: settler.publicKey })
+      );
+      const settlerAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        settler.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+
+      await expectError(
+        settlerReleaseIx(escrowPDA, vaultPDA, settler, settlerAccount).rpc(),
+        "ConstraintTokenOwner"
+      );
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects an unrelated signer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ settleAuthority: settler.publicKey })
+      );
+
+      await expectError(
+        settlerReleaseIx(escrowPDA, vaultPDA, Keypair.generate()).rpc(),
+        "UnauthorizedSettler"
+      );
+    });
+  });
+
+  describe("require seller ATA", () => {
+    async function initRequiringAta(sellerAta: PublicKey | null) {
+      const [escrowPDA] = getEscrowPDA();
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+      await program.methods
+        .initialize(
+          new BN(DEPOSIT_AMOUNT),
+          futureDeadline(3600),
+          defaultOptions({ requireSellerAta: true })
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: sellerAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      return escrowPDA;
+    }
+
+    it("accepts a seller with an existing ATA", async () => {
+      // `sellerTokenAccount` from beforeEach is the seller's ATA
+      const escrowPDA = await initRequiringAta(
+        getAssociatedTokenAddressSync(mint, seller.publicKey)
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects a seller without an ATA", async () => {
+      seller = Keypair.generate();
+      await expectError(
+        initRequiringAta(getAssociatedTokenAddressSync(mint, seller.publicKey)),
+        "SellerAccountMissing"
+      );
+    });
+
+    it("rejects a missing seller account", async () => {
+      await expectError(initRequiringAta(null), "SellerAccountMissing");
+    });
+  });
+
+  describe("rescue_excess", () => {
+    const EXTRA = 250_000;
+
+    function rescueIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .rescueExcess()
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    it("returns tokens sent straight to the vault", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await transfer(
+        connection,
+        buyer,
+        buyerTokenAccount,
+        vaultPDA,
+        buyer,
+        EXTRA,
+        [],
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const before = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+
+      await rescueIx(escrowPDA, vaultPDA).rpc();
+
+      const after = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      expect(after - before).to.equal(EXTRA);
+      const vault = await getAccount(connection, vaultPDA);
+      expect(Number(vault.amount)).to.equal(DEPOSIT_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects a rescue with nothing extra in the vault", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(rescueIx(escrowPDA, vaultPDA).rpc(), "NoExcess");
+    });
+  });
+
+  describe("scheduled installments", () => {
+    function scheduleOptions(parts: { unlockAt: BN; amount: number }[]) {
+      return defaultOptions({
+        kind: { scheduled: {} },
+        schedule: parts.map((p) => ({
+          unlockAt: p.unlockAt,
+          amount: new BN(p.amount),
+        })),
+      });
+    }
+
+    function releaseScheduledIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .releaseScheduled()
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    async function sellerBalance() {
+      return Number((await getAccount(connection, sellerTokenAccount)).amount);
+    }
+
+    it("rejects a schedule that doesn't sum to the amount", async () => {
+      await expectError(
+        initEscrow(
+          scheduleOptions([
+            { unlockAt: futureDeadline(60), amount: 400_000 },
+            { unlockAt: futureDeadline(120), amount: 400_000 },
+          ])
+        ),
+        "ScheduleSumMismatch"
+      );
+    });
+
+    it("rejects more than MAX_SCHEDULED_PAYMENTS installments", async () => {
+      const count = MAX_SCHEDULED_PAYMENTS + 1;
+      const parts = new Array(count)
+        .fill(0)
+        .map((_, i) => ({ unlockAt: futureDeadline(60 + i), amount: 1 }));
+      await expectError(
+        initEscrow(scheduleOptions(parts), count),
+        "TooManyInstallments"
+      );
+    });
+
+    it("rejects installments out of order or after the deadline", async () => {
+      await expectError(
+        initEscrow(
+          scheduleOptions([
+            { unlockAt: futureDeadline(120), amount: 500_000 },
+            { unlockAt: futureDeadline(60), amount: 500_000 },
+          ])
+        ),
+        "InvalidSchedule"
+      );
+      await expectError(
+        initEscrow(
+          scheduleOptions([
+            { unlockAt: futureDeadline(7200), amount: DEPOSIT_AMOUNT },
+          ])
+        ),
+        "InvalidSchedule"
+      );
+    });
+
+    it("pays installments as they mature", async () => {
+      const first = futureDeadline(2);
+      const second = futureDeadline(4);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        scheduleOptions([
+          { unlockAt: first, amount: 300_000 },
+          { unlockAt: second, amount: 700_000 },
+        ])
+      );
+
+      await expectError(
+        releaseScheduledIx(escrowPDA, vaultPDA).rpc(),
+        "NothingMatured"
+      );
+
+      await waitForDeadline(first);
+      await releaseScheduledIx(escrowPDA, vaultPDA).rpc();
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.amount.toNumber()).to.equal(700_000);
+      expect(escrow.schedule.map((p) => p.paid)).to.deep.equal([true, false]);
+      expect(await sellerBalance()).to.equal(300_000);
+
+      await waitForDeadline(second);
+      await releaseScheduledIx(escrowPDA, vaultPDA).rpc();
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      expect(await sellerBalance()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("pays every matured installment in one call", async () => {
+      const first = futureDeadline(2);
+      const second = futureDeadline(3);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        scheduleOptions([
+          { unlockAt: first, amount: 250_000 },
+          { unlockAt: second, amount: 250_000 },
+          { unlockAt: futureDeadline(600), amount: 500_000 },
+        ])
+      );
+
+      await waitForDeadline(second);
+      await releaseScheduledIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+      expect(escrow.amount.toNumber()).to.equal(500_000);
+      expect(await sellerBalance()).to.equal(500_000);
+    });
+
+    it("emits EscrowSettled on the final installment", async () => {
+      const unlockAt = futureDeadline(2);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        scheduleOptions([{ unlockAt, amount: DEPOSIT_AMOUNT }])
+      );
+
+      await waitForDeadline(unlockAt);
+      const sig = await releaseScheduledIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+      const event = await settledEvent(sig);
+      expect(event.outcome).to.deep.equal({ released: {} });
+      expect(event.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects release_scheduled on a standard escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        releaseScheduledIx(escrowPDA, vaultPDA).rpc(),
+        "WrongEscrowKind"
+      );
+    });
+  });
+
+  describe("referrer share", () => {
+    const referrer = Keypair.generate();
+    let referrerTokenAccount: PublicKey;
+    let feeTokenAccount: PublicKey;
+
+    beforeEach(async () => {
+      referrerTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        referrer.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      feeTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        feeRecipient.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+    });
+
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    async function balance(account: PublicKey) {
+      return Number((await getAccount(connection, account)).amount);
+    }
+
+    it("pays the referrer before the fee and the seller", async () => {
+      await setConfig({ feeBps: 250 });
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ referrer: referrer.publicKey, referrerBps: 500 })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA, {
+        feeTokenAccount,
+        referrerTokenAccount,
+      }).rpc();
+
+      // 5% of 1_000_000 to the referrer, then 2.5% of the remaining 950_000
+      expect(await balance(referrerTokenAccount)).to.equal(50_000);
+      expect(await balance(feeTokenAccount)).to.equal(23_750);
+      expect(await balance(sellerTokenAccount)).to.equal(926_250);
+      expect(await balance(vaultPDA)).to.equal(0);
+    });
+
+    it("pays nothing to a referrer with a zero share", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ referrer: referrer.publicKey, referrerBps: 0 })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      expect(await balance(referrerTokenAccount)).to.equal(0);
+      expect(await balance(sellerTokenAccount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("requires the referrer's token account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ referrer: referrer.publicKey, referrerBps: 500 })
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "MissingReferrerAccount"
+      );
+    });
+
+    it("rejects a share that exceeds 100% with the protocol fee", async () => {
+      await setConfig({ feeBps: 250 });
+      await expectError(
+        initEscrow(
+          defaultOptions({ referrer: referrer.publicKey, referrerBps: 9_800 })
+        ),
+        "InvalidFee"
+      );
+    });
+
+    it("rejects a share without a referrer", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ referrerBps: 500 })),
+        "InvalidFee"
+      );
+    });
+  });
+
+  describe("strict transactions", () => {
+    it("releases when bundled only with compute budget instructions", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ strictTx: true })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA, {
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      })
+        .preInstructions([
+          ComputeBudgetProgram.setComputeUnitLimit({ units: 400_000 }),
+        ])
+        .rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects a release bundled with another program's instruction", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ strictTx: true })
+      );
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, {
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+          .postInstructions([
+            SystemProgram.transfer({
+              fromPubkey: buyer.publicKey,
+              toPubkey: provider.wallet.publicKey,
+              lamports: 1_000,
+            }),
+          ])
+          .rpc(),
+        "UnexpectedInstruction"
+      );
+    });
+
+    it("requires the instructions sysvar", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ strictTx: true })
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "UnexpectedInstruction"
+      );
+    });
+
+    it("ignores bundled instructions when not strict", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      await releaseIx(escrowPDA, vaultPDA)
+        .postInstructions([
+          SystemProgram.transfer({
+            fromPubkey: buyer.publicKey,
+            toPubkey: provider.wallet.publicKey,
+            lamports: 1_000,
+          }),
+        ])
+        .rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+  });
+
+  describe("no-deadline escrows", () => {
+    const NO_DEADLINE = new BN(0);
+
+    function expireIx(escrowPDA: PublicKey) {
+      return program.methods
+        .expire()
+        .accounts({ caller: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer]);
+    }
+
+    it("creates an open-ended escrow with a zero deadline", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        NO_DEADLINE
+      );
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.noDeadline).to.equal(true);
+      expect(escrow.deadline.toNumber()).to.equal(0);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("never expires", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        NO_DEADLINE
+      );
+      await expectError(expireIx(escrowPDA).rpc(), "NotExpired");
+    });
+
+    it("can still be cancelled by the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        NO_DEADLINE
+      );
+
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ cancelled: {} });
+    });
+
+    it("can still be released by the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        NO_DEADLINE
+      );
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("still rejects a negative deadline", async () => {
+      await expectError(
+        initEscrow(defaultOptions(), DEPOSIT_AMOUNT, new BN(-1)),
+        "DeadlineInPast"
+      );
+    });
+  });
+
+  describe("weighted approvals", () => {
+    // The lead's approval counts double
+    const lead = Keypair.generate();
+    const members = [Keypair.generate(), Keypair.generate()];
+    const approvalOptions = defaultOptions({
+      approvers: [lead.publicKey, ...members.map((m) => m.publicKey)],
+      approverWeights: [2, 1, 1],
+      approvalThreshold: 3,
+    });
+
+    function confirmReleaseIx(escrowPDA: PublicKey, approver: Keypair) {
+      return program.methods
+        .confirmRelease()
+        .accounts({ approver: approver.publicKey, escrow: escrowPDA })
+        .signers([approver]);
+    }
+
+    it("releases once the lead and one member approve", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(approvalOptions);
+
+      await confirmReleaseIx(escrowPDA, lead).rpc();
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "ThresholdNotMet"
+      );
+
+      await confirmReleaseIx(escrowPDA, members[0]).rpc();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("does not release on the members' approvals alone", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(approvalOptions);
+
+      await confirmReleaseIx(escrowPDA, members[0]).rpc();
+      await confirmReleaseIx(escrowPDA, members[1]).rpc();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "ThresholdNotMet"
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("rejects approvals from outside the approver set", async () => {
+      const { escrowPDA } = await initEscrow(approvalOptions);
+      await expectError(
+        confirmReleaseIx(escrowPDA, Keypair.generate()).rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("rejects a threshold the approvers cannot reach", async () => {
+      await expectError(
+        initEscrow({ ...approvalOptions, approvalThreshold: 5 }),
+        "InvalidApprovers"
+      );
+    });
+
+    it("releases on a quorum reached within the approval window", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow({
+        ...approvalOptions,
+        approvalWindowSecs: 3600,
+      });
+
+      await confirmReleaseIx(escrowPDA, lead).rpc();
+      await confirmReleaseIx(escrowPDA, members[0]).rpc();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("resets stale confirmations on the next confirm", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow({
+        ...approvalOptions,
+        approvalWindowSecs: 2,
+      });
+
+      await confirmReleaseIx(escrowPDA, members[0]).rpc();
+      await waitForDeadline(futureDeadline(3));
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "ApprovalWindowExpired"
+      );
+
+      // The lead's approval starts a new round instead of completing the old one
+      await confirmReleaseIx(escrowPDA, lead).rpc();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.approvals).to.equal(0b001);
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "ThresholdNotMet"
+      );
+    });
+  });
+
+  describe("close_escrow", () => {
+    const finalizer = Keypair.generate();
+
+    function closeEscrowIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey | null,
+      caller: Keypair
+    ) {
+      return program.methods
+        .closeEscrow()
+        .accounts({
+          caller: caller.publicKey,
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([caller]);
+    }
+
+    beforeEach(async () => {
+      await setConfig({ finalizer: finalizer.publicKey });
+    });
+
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    it("lets the finalizer close a released escrow, rent to the buyer", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const before = await connection.getBalance(buyer.publicKey);
+
+      await closeEscrowIx(escrowPDA, vaultPDA, finalizer).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+      expect(await connection.getAccountInfo(vaultPDA)).to.be.null;
+      expect(await connection.getBalance(buyer.publicKey)).to.be.greaterThan(
+        before
+      );
+      expect(await connection.getBalance(finalizer.publicKey)).to.equal(0);
+    });
+
+    it("lets the buyer close a cancelled escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+
+      await closeEscrowIx(escrowPDA, vaultPDA, buyer).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+    });
+
+    it("rejects anyone else", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      await expectError(
+        closeEscrowIx(escrowPDA, vaultPDA, Keypair.generate()).rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("rejects escrows that are still locked", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        closeEscrowIx(escrowPDA, vaultPDA, finalizer).rpc(),
+        "NotTerminal"
+      );
+    });
+
+    function closeVaultOnlyIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .closeVaultOnly()
+        .accounts({
+          caller: buyer.publicKey,
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer]);
+    }
+
+    it("closes just the vault and keeps the escrow readable", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      const before = await connection.getBalance(buyer.publicKey);
+
+      await closeVaultOnlyIx(escrowPDA, vaultPDA).rpc();
+
+      expect(await connection.getAccountInfo(vaultPDA)).to.be.null;
+      expect(await connection.getBalance(buyer.publicKey)).to.be.greaterThan(
+        before
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      expect(escrow.vaultClosed).to.equal(true);
+    });
+
+    it("closes the record once the vault was closed on its own", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      await closeVaultOnlyIx(escrowPDA, vaultPDA).rpc();
+      const before = await connection.getBalance(buyer.publicKey);
+
+      await closeEscrowIx(escrowPDA, null, finalizer).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+      expect(await connection.getBalance(buyer.publicKey)).to.be.greaterThan(
+        before
+      );
+    });
+
+    it("requires the vault until it is closed", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      await expectError(
+        closeEscrowIx(escrowPDA, null, finalizer).rpc(),
+        "MissingVault"
+      );
+    });
+
+    it("won't close the vault of a locked escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        closeVaultOnlyIx(escrowPDA, vaultPDA).rpc(),
+        "NotTerminal"
+      );
+    });
+  });
+
+  describe("freeze_escrow", () => {
+    function freezeIx(escrowPDA: PublicKey, sellerSigner: Keypair) {
+      return program.methods
+        .freezeEscrow()
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: sellerSigner.publicKey,
+          escrow: escrowPDA,
+        })
+        .signers([buyer, sellerSigner]);
+    }
+
+    function unfreezeIx(escrowPDA: PublicKey) {
+      return program.methods
+        .unfreezeEscrow()
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          escrow: escrowPDA,
+        })
+        .signers([buyer, seller]);
+    }
+
+    it("requires both the buyer and the seller", async () => {
+      const { escrowPDA } = await initEscrow();
+
+      // A stranger signing in the seller's place fails the PDA / has_one check
+      try {
+        await freezeIx(escrowPDA, Keypair.generate()).rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err).to.exist;
+      }
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.frozen).to.equal(false);
+    });
+
+    it("blocks release, cancel, and expiry while frozen", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await freezeIx(escrowPDA, seller).rpc();
+
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "EscrowFrozen");
+      await expectError(cancelIx(escrowPDA, vaultPDA).rpc(), "EscrowFrozen");
+      await expectError(
+        program.methods
+          .expire()
+          .accounts({ caller: buyer.publicKey, escrow: escrowPDA })
+          .signers([buyer])
+          .rpc(),
+        "EscrowFrozen"
+      );
+
+      const vaultAccount = await getAccount(connection, vaultPDA);
+      expect(Number(vaultAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("releases normally once unfrozen", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await freezeIx(escrowPDA, seller).rpc();
+      await unfreezeIx(escrowPDA).rpc();
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects unfreezing an escrow that is not frozen", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(unfreezeIx(escrowPDA).rpc(), "NotFrozen");
+    });
+  });
+
+  describe("create seller ATA", () => {
+    function sellerAta() {
+      return getAssociatedTokenAddressSync(
+        mint,
+        seller.publicKey,
+        false,
+        TOKEN_PROGRAM_ID
+      );
+    }
+
+    function createAtaAccounts() {
+      return {
+        sellerTokenAccount: sellerAta(),
+        seller: seller.publicKey,
+        payer: buyer.publicKey,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      };
+    }
+
+    it("creates the seller's ATA on release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ createSellerAta: true })
+      );
+      expect(await connection.getAccountInfo(sellerAta())).to.be.null;
+
+      await releaseIx(escrowPDA, vaultPDA, createAtaAccounts()).rpc();
+
+      const ata = await getAccount(connection, sellerAta());
+      expect(ata.owner.toBase58()).to.equal(seller.publicKey.toBase58());
+      expect(Number(ata.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("requires the payer and programs to create the ATA", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ createSellerAta: true })
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, {
+          ...createAtaAccounts(),
+          payer: null,
+        }).rpc(),
+        "AtaCreationFailed"
+      );
+    });
+
+    it("does not create the ATA unless the escrow allows it", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      try {
+        await releaseIx(escrowPDA, vaultPDA, createAtaAccounts()).rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err).to.exist;
+      }
+      expect(await connection.getAccountInfo(sellerAta())).to.be.null;
+    });
+  });
+
+  describe("tvl cap", () => {
+    async function setMintPolicy(maxTvl: number) {
+      await program.methods
+        .setMintPolicy(new BN(maxTvl))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          config: configPDA,
+          mint: mint,
+          mintPolicy: getMintPolicyPDA()[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    async function tvl(): Promise<number> {
+      const policy = await program.account.mintPolicy.fetch(
+        getMintPolicyPDA()[0]
+      );
+      return policy.tvl.toNumber();
+    }
+
+    it("is unlimited by default", async () => {
+      await initEscrow();
+      const policy = await program.account.mintPolicy.fetch(
+        getMintPolicyPDA()[0]
+      );
+      expect(policy.maxTvl.toNumber()).to.equal(0);
+      expect(policy.tvl.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects escrows above the mint's cap", async () => {
+      await setMintPolicy(DEPOSIT_AMOUNT * 1.5);
+      await initEscrow();
+
+      seller = Keypair.generate();
+      await expectError(initEscrow(), "TvlCapExceeded");
+
+      await initEscrow(defaultOptions(), DEPOSIT_AMOUNT / 2);
+      expect(await tvl()).to.equal(DEPOSIT_AMOUNT * 1.5);
+    });
+
+    it("frees capacity when an escrow settles", async () => {
+      await setMintPolicy(DEPOSIT_AMOUNT);
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      expect(await tvl()).to.equal(0);
+
+      seller = Keypair.generate();
+      const next = await initEscrow();
+      await cancelIx(next.escrowPDA, next.vaultPDA).rpc();
+      expect(await tvl()).to.equal(0);
+    });
+  });
+
+  describe("receipts", () => {
+    function getReceiptPDA(escrowKey: PublicKey): PublicKey {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("receipt"), escrowKey.toBuffer()],
+        program.programId
+      );
+      return pda;
+    }
+
+    function receiptAccounts(escrowPDA: PublicKey) {
+      return {
+        receipt: getReceiptPDA(escrowPDA),
+        payer: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
+      };
+    }
+
+    it("writes a receipt on release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ createReceipt: true })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA, receiptAccounts(escrowPDA)).rpc();
+
+      const receipt = await program.account.receipt.fetch(
+        getReceiptPDA(escrowPDA)
+      );
+      expect(receipt.escrow.toBase58()).to.equal(escrowPDA.toBase58());
+      expect(receipt.buyer.toBase58()).to.equal(buyer.publicKey.toBase58());
+      expect(receipt.seller.toBase58()).to.equal(seller.publicKey.toBase58());
+      expect(receipt.mint.toBase58()).to.equal(mint.toBase58());
+      expect(receipt.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      expect(receipt.releasedAt.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("requires the receipt account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ createReceipt: true })
+      );
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).rpc(),
+        "MissingReceiptAccount"
+      );
+    });
+
+    it("writes the receipt when its address was pre-funded", async () => {
+      const settler = Keypair.generate();
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ createReceipt: true, settleAuthority: settler.publicKey })
+      );
+      const receiptPDA = getReceiptPDA(escrowPDA);
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: receiptPDA,
+            lamports: 1,
+          })
+        )
+      );
+
+      // Released by the settle authority; the buyer doesn't sign
+      await program.methods
+        .release(new BN(1))
+        .accounts({
+          buyer: buyer.publicKey,
+          settleAuthority: settler.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: provider.wallet.publicKey,
+          associatedTokenProgram: null,
+          systemProgram: SystemProgram.programId,
+          instructions: null,
+          unwrapAccount: null,
+          receipt: receiptPDA,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([settler])
+        .rpc();
+
+      const receipt = await program.account.receipt.fetch(receiptPDA);
+      expect(receipt.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("writes no receipt unless the escrow asks for one", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA, receiptAccounts(escrowPDA)).rpc();
+      expect(await connection.getAccountInfo(getReceiptPDA(escrowPDA))).to.be
+        .null;
+    });
+  });
+
+  describe("delivery proofs", () => {
+    const DELIVERY_HASH = Array.from({ length: 32 }, (_, i) => i + 1);
+
+    function submitDeliveryIx(escrowPDA: PublicKey, hash: number[]) {
+      return program.methods
+        .submitDelivery(hash)
+        .accounts({ seller: seller.publicKey, escrow: escrowPDA })
+        .signers([seller]);
+    }
+
+    it("blocks release until the seller submits a delivery", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ requireDelivery: true })
+      );
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "NoDelivery");
+
+      await submitDeliveryIx(escrowPDA, DELIVERY_HASH).rpc();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deliveryHash).to.deep.equal(DELIVERY_HASH);
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("rejects an empty delivery hash", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ requireDelivery: true })
+      );
+      await expectError(
+        submitDeliveryIx(escrowPDA, new Array(32).fill(0)).rpc(),
+        "NoDelivery"
+      );
+    });
+
+    it("blocks cancel once delivered with lock_on_delivery", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ lockOnDelivery: true })
+      );
+      await submitDeliveryIx(escrowPDA, DELIVERY_HASH).rpc();
+
+      await expectError(
+        cancelIx(escrowPDA, vaultPDA).rpc(),
+        "DeliverySubmitted"
+      );
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("still allows cancel after delivery without lock_on_delivery", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await submitDeliveryIx(escrowPDA, DELIVERY_HASH).rpc();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("only lets the seller submit", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ requireDelivery: true })
+      );
+      try {
+        await program.methods
+          .submitDelivery(DELIVERY_HASH)
+          .accounts({ seller: buyer.publicKey, escrow: escrowPDA })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have thrown");
+      } catch (err: any) {
+        expect(err).to.exist;
+      }
+    });
+  });
+
+  describe("close_many", () => {
+    const finalizer = Keypair.generate();
+
+    function closeManyIx(
+      escrows: { escrowPDA: PublicKey; vaultPDA: PublicKey }[]
+    ) {
+      return program.methods
+        .closeMany()
+        .accounts({
+          caller: finalizer.publicKey,
+          config: configPDA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          escrows.flatMap(({ escrowPDA, vaultPDA }) => [
+            { pubkey: escrowPDA, isWritable: true, isSigner: false },
+            { pubkey: vaultPDA, isWritable: true, isSigner: false },
+            { pubkey: buyer.publicKey, isWritable: true, isSigner: false },
+          ])
+        )
+        .signers([finalizer]);
+    }
+
+    beforeEach(async () => {
+      await setConfig({ finalizer: finalizer.publicKey });
+    });
+
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    it("closes settled escrows and skips the rest", async () => {
+      const settled: { escrowPDA: PublicKey; vaultPDA: PublicKey }[] = [];
+      for (let i = 0; i < 3; i++) {
+        seller = Keypair.generate();
+        const escrow = await initEscrow();
+        await cancelIx(escrow.escrowPDA, escrow.vaultPDA).rpc();
+        settled.push(escrow);
+      }
+      seller = Keypair.generate();
+      const locked = await initEscrow();
+
+      await closeManyIx([...settled, locked]).rpc();
+
+      for (const { escrowPDA, vaultPDA } of settled) {
+        expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+        expect(await connection.getAccountInfo(vaultPDA)).to.be.null;
+      }
+      const escrow = await program.account.escrow.fetch(locked.escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("closes the record of an escrow whose vault was closed", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+      await program.methods
+        .closeVaultOnly()
+        .accounts({
+          caller: buyer.publicKey,
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      await closeManyIx([{ escrowPDA, vaultPDA }]).rpc();
+
+      expect(await connection.getAccountInfo(escrowPDA)).to.be.null;
+    });
+
+    it("rejects a buyer that doesn't match the escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+      await expectError(
+        program.methods
+          .closeMany()
+          .accounts({
+            caller: finalizer.publicKey,
+            config: configPDA,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts([
+            { pubkey: escrowPDA, isWritable: true, isSigner: false },
+            { pubkey: vaultPDA, isWritable: true, isSigner: false },
+            { pubkey: finalizer.publicKey, isWritable: true, isSigner: false },
+          ])
+          .signers([finalizer])
+          .rpc(),
+        "InvalidBatch"
+      );
+    });
+  });
+
+  describe("minimum duration", () => {
+    const MIN_DEADLINE_SECS = 3600;
+
+    before(async () => {
+      await setConfig({ minDeadlineSecs: MIN_DEADLINE_SECS });
+    });
+
+    after(async () => {
+      await setConfig();
+    });
+
+    it("rejects deadlines sooner than the minimum", async () => {
+      await expectError(
+        initEscrow(
+          defaultOptions(),
+          DEPOSIT_AMOUNT,
+          futureDeadline(MIN_DEADLINE_SECS - 60)
+        ),
+        "DeadlineTooSoon"
+      );
+    });
+
+    it("accepts deadlines at the minimum", async () => {
+      // Slack for drift between the local and the cluster clock
+      await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        futureDeadline(MIN_DEADLINE_SECS + 60)
+      );
+    });
+
+    it("rejects a minimum above the maximum duration", async () => {
+      await expectError(
+        setConfig({ minDeadlineSecs: 91 * 24 * 60 * 60 }),
+        "DeadlineTooFar"
+      );
+    });
+  });
+
+  describe("set_max_deadline", () => {
+    const DAY = 24 * 60 * 60;
+
+    function setMaxDeadlineIx(secs: number, admin: PublicKey = provider.wallet.publicKey) {
+      return program.methods
+        .setMaxDeadline(secs)
+        .accounts({ admin, config: configPDA });
+    }
+
+    afterEach(async () => {
+      await setMaxDeadlineIx(90 * DAY).rpc();
+    });
+
+    it("applies a lower maximum to new escrows", async () => {
+      await setMaxDeadlineIx(DAY).rpc();
+      await expectError(
+        initEscrow(defaultOptions(), DEPOSIT_AMOUNT, futureDeadline(2 * DAY)),
+        "DeadlineTooFar"
+      );
+      await initEscrow(defaultOptions(), DEPOSIT_AMOUNT, futureDeadline(DAY / 2));
+    });
+
+    it("applies a higher maximum to new escrows", async () => {
+      await expectError(
+        initEscrow(defaultOptions(), DEPOSIT_AMOUNT, futureDeadline(120 * DAY)),
+        "DeadlineTooFar"
+      );
+      await setMaxDeadlineIx(180 * DAY).rpc();
+      await initEscrow(defaultOptions(), DEPOSIT_AMOUNT, futureDeadline(120 * DAY));
+
+      const config = await program.account.config.fetch(configPDA);
+      expect(config.maxDeadlineSecs).to.equal(180 * DAY);
+    });
+
+    it("rejects a maximum beyond the limit", async () => {
+      await expectError(
+        setMaxDeadlineIx(11 * 365 * DAY).rpc(),
+        "DeadlineTooFar"
+      );
+    });
+
+    it("rejects non-admin callers", async () => {
+      await expectError(
+        setMaxDeadlineIx(DAY, buyer.publicKey).signers([buyer]).rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("initialize_with_duration", () => {
+    const DURATION_SECS = 3600;
+
+    function initWithDurationIx(durationSecs: BN) {
+      const [escrowPDA] = getEscrowPDA();
+      return program.methods
+        .initializeWithDuration(
+          new BN(DEPOSIT_AMOUNT),
+          durationSecs,
+          defaultOptions()
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: getVaultPDA(escrowPDA)[0],
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template: null,
+          approvedSeller: null,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer]);
+    }
+
+    it("stores the deadline as the on-chain time plus the duration", async () => {
+      const tx = await initWithDurationIx(new BN(DURATION_SECS)).rpc({
+        commitment: "confirmed",
+      });
+      const { blockTime } = await connection.getTransaction(tx, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+
+      const escrow = await program.account.escrow.fetch(getEscrowPDA()[0]);
+      // The block time and the clock the program read may differ slightly
+      expect(escrow.deadline.toNumber()).to.be.closeTo(
+        blockTime + DURATION_SECS,
+        5
+      );
+    });
+
+    it("rejects a duration beyond the maximum", async () => {
+      await expectError(
+        initWithDurationIx(new BN(91 * 24 * 60 * 60)).rpc(),
+        "DurationTooLong"
+      );
+    });
+  });
+
+  describe("pooled deposits", () => {
+    async function newContributor(balance: number) {
+      const keypair = Keypair.generate();
+      const tokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        keypair.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      await mintTo(
+        connection,
+        buyer,
+        mint,
+        tokenAccount,
+        buyer,
+        balance,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      return { keypair, tokenAccount };
+    }
+
+    function depositIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      contributor: { keypair: Keypair; tokenAccount: PublicKey },
+      amount: number
+    ) {
+      return program.methods
+        .deposit(new BN(amount))
+        .accounts({
+          contributor: contributor.keypair.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          config: configPDA,
+          contributorTokenAccount: contributor.tokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([contributor.keypair]);
+    }
+
+    it("refunds each contributor pro rata on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ pooled: true })
+      );
+      const alice = await newContributor(DEPOSIT_AMOUNT);
+      const bob = await newContributor(DEPOSIT_AMOUNT * 2);
+      await depositIx(escrowPDA, vaultPDA, alice, DEPOSIT_AMOUNT).rpc();
+      await depositIx(escrowPDA, vaultPDA, bob, DEPOSIT_AMOUNT * 2).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.amount.toNumber()).to.equal(DEPOSIT_AMOUNT * 4);
+      expect(escrow.contributors.length).to.equal(2);
+
+      const buyerBefore = Number(
+        (await getAccount(connection, buyerTokenAccount)).amount
+      );
+      await cancelIx(escrowPDA, vaultPDA)
+        .remainingAccounts([
+          { pubkey: alice.tokenAccount, isWritable: true, isSigner: false },
+          { pubkey: bob.tokenAccount, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+      const balance = async (account: PublicKey) =>
+        Number((await getAccount(connection, account)).amount);
+      expect(await balance(alice.tokenAccount)).to.equal(DEPOSIT_AMOUNT);
+      expect(await balance(bob.tokenAccount)).to.equal(DEPOSIT_AMOUNT * 2);
+      expect(await balance(buyerTokenAccount)).to.equal(
+        buyerBefore + DEPOSIT_AMOUNT
+      );
+    });
+
+    async function pooledEscrow(options: any = {}, deadline?: BN) {
+      const escrowAccounts = await initEscrow(
+        defaultOptions({ pooled: true, ...options }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      const alice = await newContributor(DEPOSIT_AMOUNT);
+      await depositIx(
+        escrowAccounts.escrowPDA,
+        escrowAccounts.vaultPDA,
+        alice,
+        DEPOSIT_AMOUNT
+      ).rpc();
+      return { ...escrowAccounts, alice };
+    }
+
+    const balance = async (account: PublicKey) =>
+      Number((await getAccount(connection, account)).amount);
+
+    it("refunds contributors their share on settle_on_expire", async () => {
+      const deadline = futureDeadline(6);
+      const { escrowPDA, vaultPDA, alice } = await pooledEscrow(
+        { expireBuyerBps: 5_000 },
+        deadline
+      );
+      const buyerBefore = await balance(buyerTokenAccount);
+      await waitForDeadline(deadline);
+
+      await program.methods
+        .settleOnExpire()
+        .accounts({
+          caller: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: alice.tokenAccount, isWritable: true, isSigner: false },
+        ])
+        .signers([buyer])
+        .rpc();
+
+      // Half the pool goes back, split evenly between buyer and Alice
+      expect(await balance(alice.tokenAccount)).to.equal(DEPOSIT_AMOUNT / 2);
+      expect(await balance(buyerTokenAccount)).to.equal(
+        buyerBefore + DEPOSIT_AMOUNT / 2
+      );
+      expect(await balance(sellerTokenAccount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("refunds contributors their share on a ruling for the buyer", async () => {
+      const arbiter = Keypair.generate();
+      const { escrowPDA, vaultPDA, alice } = await pooledEscrow({
+        arbiter: arbiter.publicKey,
+      });
+      await program.methods
+        .openDispute()
+        .accounts({ party: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer])
+        .rpc();
+      const buyerBefore = await balance(buyerTokenAccount);
+
+      await program.methods
+        .resolveDispute(false)
+        .accounts({
+          arbiter: arbiter.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          arbiterTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: alice.tokenAccount, isWritable: true, isSigner: false },
+        ])
+        .signers([arbiter])
+        .rpc();
+
+      expect(await balance(alice.tokenAccount)).to.equal(DEPOSIT_AMOUNT);
+      expect(await balance(buyerTokenAccount)).to.equal(
+        buyerBefore + DEPOSIT_AMOUNT
+      );
+    });
+
+    it("requires the contributors' token accounts on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ pooled: true })
+      );
+      const alice = await newContributor(DEPOSIT_AMOUNT);
+      await depositIx(escrowPDA, vaultPDA, alice, DEPOSIT_AMOUNT).rpc();
+
+      await expectError(
+        cancelIx(escrowPDA, vaultPDA).rpc(),
+        "InvalidContributorAccounts"
+      );
+    });
+
+    it("only takes the buyer's top-ups unless pooled", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const stranger = await newContributor(DEPOSIT_AMOUNT);
+      await expectError(
+        depositIx(escrowPDA, vaultPDA, stranger, DEPOSIT_AMOUNT).rpc(),
+        "UnauthorizedBuyer"
+      );
+
+      await depositIx(
+        escrowPDA,
+        vaultPDA,
+        { keypair: buyer, tokenAccount: buyerTokenAccount },
+        DEPOSIT_AMOUNT
+      ).rpc();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.amount.toNumber()).to.equal(DEPOSIT_AMOUNT * 2);
+      expect(escrow.contributors).to.be.empty;
+    });
+
+    it("rejects more than MAX_CONTRIBUTORS", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ pooled: true })
+      );
+      for (let i = 0; i < 4; i++) {
+        const contributor = await newContributor(1);
+        await depositIx(escrowPDA, vaultPDA, contributor, 1).rpc();
+      }
+      const extra = await newContributor(1);
+      await expectError(
+        depositIx(escrowPDA, vaultPDA, extra, 1).rpc(),
+        "TooManyContributors"
+      );
+    });
+  });
+
+  describe("system-owned seller", () => {
+    it("rejects a seller that isn't a wallet", async () => {
+      // A token account, owned by the Token program
+      const notAWallet = sellerTokenAccount;
+      const [escrowPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          buyer.publicKey.toBuffer(),
+          notAWallet.toBuffer(),
+          mint.toBuffer(),
+        ],
+        program.programId
+      );
+      const [vaultPDA] = getVaultPDA(escrowPDA);
+
+      await expectError(
+        program.methods
+          .initialize(
+            new BN(DEPOSIT_AMOUNT),
+            futureDeadline(3600),
+            defaultOptions({ requireSystemSeller: true })
+          )
+          .accounts({
+            buyer: buyer.publicKey,
+            seller: notAWallet,
+            mint: mint,
+            buyerTokenAccount: buyerTokenAccount,
+            escrow: escrowPDA,
+            vault: vaultPDA,
+            config: configPDA,
+            treasury: null,
+            approvedBuyer: null,
+            template: null,
+            approvedSeller: null,
+            sellerTokenAccount: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc(),
+        "InvalidSellerAccount"
+      );
+    });
+
+    it("accepts a wallet seller", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ requireSystemSeller: true })
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.seller.toBase58()).to.equal(seller.publicKey.toBase58());
+    });
+  });
+
+  describe("fee split", () => {
+    const treasuryWallet = Keypair.generate();
+    const stakingPool = Keypair.generate();
+    const FEE_SPLIT = [
+      { account: treasuryWallet.publicKey, bps: 60 },
+      { account: stakingPool.publicKey, bps: 40 },
+    ];
+
+    before(async () => {
+      await setConfig({ feeBps: 100, feeSplit: FEE_SPLIT });
+    });
+
+    after(async () => {
+      await setConfig();
+    });
+
+    async function tokenAccountFor(owner: PublicKey) {
+      return createAccount(
+        connection,
+        buyer,
+        mint,
+        owner,
+        Keypair.generate(),
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+    }
+
+    it("splits the fee between the recipients", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const treasuryAccount = await tokenAccountFor(treasuryWallet.publicKey);
+      const stakingAccount = await tokenAccountFor(stakingPool.publicKey);
+
+      await releaseIx(escrowPDA, vaultPDA)
+        .remainingAccounts([
+          { pubkey: treasuryAccount, isWritable: true, isSigner: false },
+          { pubkey: stakingAccount, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+      // 1% of the deposit, split 60/40
+      const fee = DEPOSIT_AMOUNT / 100;
+      const balance = async (account: PublicKey) =>
+        Number((await getAccount(connection, account)).amount);
+      expect(await balance(treasuryAccount)).to.equal((fee * 60) / 100);
+      expect(await balance(stakingAccount)).to.equal((fee * 40) / 100);
+      expect(await balance(sellerTokenAccount)).to.equal(DEPOSIT_AMOUNT - fee);
+    });
+
+    it("requires a token account per recipient", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const treasuryAccount = await tokenAccountFor(treasuryWallet.publicKey);
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA)
+          .remainingAccounts([
+            { pubkey: treasuryAccount, isWritable: true, isSigner: false },
+          ])
+          .rpc(),
+        "FeeSplitMismatch"
+      );
+    });
+
+    it("rejects shares that don't sum to fee_bps", async () => {
+      await expectError(
+        setConfig({ feeBps: 100, feeSplit: [{ ...FEE_SPLIT[0], bps: 50 }] }),
+        "FeeSplitMismatch"
+      );
+    });
+  });
+
+  describe("fee waiver", () => {
+    let feeTokenAccount: PublicKey;
+
+    before(async () => {
+      await setConfig({
+        feeBps: 250,
+        feeWaiverThreshold: new BN(DEPOSIT_AMOUNT),
+      });
+    });
+
+    after(async () => {
+      await setConfig();
+    });
+
+    beforeEach(async () => {
+      feeTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        feeRecipient.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+    });
+
+    async function balance(account: PublicKey) {
+      return Number((await getAccount(connection, account)).amount);
+    }
+
+    it("waives the fee below the threshold", async () => {
+      const amount = DEPOSIT_AMOUNT - 1;
+      const { escrowPDA, vaultPDA } = await initEscrow(defaultOptions(), amount);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.feeWaived).to.be.true;
+
+      await releaseIx(escrowPDA, vaultPDA, { feeTokenAccount }).rpc();
+
+      expect(await balance(feeTokenAccount)).to.equal(0);
+      expect(await balance(sellerTokenAccount)).to.equal(amount);
+    });
+
+    it("charges the fee at or above the threshold", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.feeWaived).to.be.false;
+
+      await releaseIx(escrowPDA, vaultPDA, { feeTokenAccount }).rpc();
+
+      // 2.5% of 1_000_000
+      expect(await balance(feeTokenAccount)).to.equal(25_000);
+      expect(await balance(sellerTokenAccount)).to.equal(975_000);
+    });
+  });
+
+  describe("swap on release", () => {
+    const stub = anchor.workspace.sellerStub as Program<SellerStub>;
+    const [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool")],
+      stub.programId
+    );
+    let outMint: PublicKey;
+    let poolIn: PublicKey;
+    let poolOut: PublicKey;
+    let sellerOut: PublicKey;
+
+    before(async () => {
+      await setConfig({ swapProgram: stub.programId });
+    });
+
+    after(async () => {
+      await setConfig();
+    });
+
+    beforeEach(async () => {
+      outMint = await createMint(connection, buyer, buyer.publicKey, null, 6);
+      poolIn = await createAccount(
+        connection,
+        buyer,
+        mint,
+        poolPDA,
+        Keypair.generate()
+      );
+      poolOut = await createAccount(
+        connection,
+        buyer,
+        outMint,
+        poolPDA,
+        Keypair.generate()
+      );
+      await mintTo(connection, buyer, outMint, poolOut, buyer, DEPOSIT_AMOUNT);
+      sellerOut = await createAccount(
+        connection,
+        buyer,
+        outMint,
+        seller.publicKey
+      );
+    });
+
+    function swapAccounts(vaultPDA: PublicKey) {
+      return [
+        { pubkey: stub.programId, isSigner: false, isWritable: false },
+        { pubkey: sellerOut, isSigner: false, isWritable: true },
+        // Forwarded to the swap program's `swap`
+        { pubkey: vaultPDA, isSigner: false, isWritable: true },
+        { pubkey: mint, isSigner: false, isWritable: false },
+        { pubkey: poolIn, isSigner: false, isWritable: true },
+        { pubkey: outMint, isSigner: false, isWritable: false },
+        { pubkey: poolOut, isSigner: false, isWritable: true },
+        { pubkey: sellerOut, isSigner: false, isWritable: true },
+        { pubkey: poolPDA, isSigner: false, isWritable: false },
+        { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      ];
+    }
+
+    it("pays the seller in the target mint", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          kind: { swap: {} },
+          targetMint: outMint,
+          minOut: new BN(DEPOSIT_AMOUNT),
+        })
+      );
+
+      await releaseIx(escrowPDA, vaultPDA)
+        .remainingAccounts(swapAccounts(vaultPDA))
+        .rpc();
+
+      const received = await getAccount(connection, sellerOut);
+      expect(Number(received.amount)).to.equal(DEPOSIT_AMOUNT);
+      const vault = await getAccount(connection, vaultPDA);
+      expect(Number(vault.amount)).to.equal(0);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects a swap below min_out", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          kind: { swap: {} },
+          targetMint: outMint,
+          minOut: new BN(DEPOSIT_AMOUNT + 1),
+        })
+      );
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA)
+          .remainingAccounts(swapAccounts(vaultPDA))
+          .rpc(),
+        "SlippageExceeded"
+      );
+    });
+
+    it("rejects a swap program that isn't whitelisted", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          kind: { swap: {} },
+          targetMint: outMint,
+          minOut: new BN(1),
+        })
+      );
+      const accounts = swapAccounts(vaultPDA);
+      accounts[0].pubkey = TOKEN_PROGRAM_ID;
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA).remainingAccounts(accounts).rpc(),
+        "InvalidSwapAccounts"
+      );
+    });
+
+    it("rejects a target mint on referred escrows", async () => {
+      await expectError(
+        initEscrow(
+          defaultOptions({
+            kind: { swap: {} },
+            referrer: Keypair.generate().publicKey,
+            targetMint: outMint,
+            minOut: new BN(1),
+          })
+        ),
+        "WrongEscrowKind"
+      );
+    });
+
+    it("requires the Swap kind with a target mint", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ targetMint: outMint, minOut: new BN(1) })),
+        "WrongEscrowKind"
+      );
+    });
+
+    it("rejects the Swap kind without a target mint", async () => {
+      await expectError(
+        initEscrow(defaultOptions({ kind: { swap: {} } })),
+        "WrongEscrowKind"
+      );
+    });
+  });
+
+  describe("verbose logging", () => {
+    async function releaseTx(options: any) {
+      const { escrowPDA, vaultPDA } = await initEscrow(options);
+      const sig = await releaseIx(escrowPDA, vaultPDA).rpc({
+        commitment: "confirmed",
+      });
+      const tx = await connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return {
+        units: tx.meta.computeUnitsConsumed,
+        logs: tx.meta.logMessages.join("\n"),
+      };
+    }
+
+    it("only formats settlement logs for verbose escrows", async () => {
+      const quiet = await releaseTx(defaultOptions());
+
+      seller = Keypair.generate();
+      sellerTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        seller.publicKey
+      );
+      const verbose = await releaseTx(defaultOptions({ verbose: true }));
+
+      expect(quiet.logs).not.to.include("Escrow released");
+      expect(verbose.logs).to.include("Escrow released");
+      // Both emit EscrowSettled; skipping the msg! saves compute
+      expect(quiet.units).to.be.lessThan(verbose.units);
+    });
+  });
+
+  describe("seller collateral", () => {
+    const COLLATERAL = 200_000;
+    let arbiter: Keypair;
+
+    beforeEach(async () => {
+      arbiter = Keypair.generate();
+      await airdrop(seller.publicKey);
+      await mintTo(
+        connection,
+        buyer,
+        mint,
+        sellerTokenAccount,
+        buyer,
+        COLLATERAL
+      );
+    });
+
+    function getCollateralPDA(escrowPDA: PublicKey): PublicKey {
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("collateral"), escrowPDA.toBuffer()],
+        program.programId
+      )[0];
+    }
+
+    async function postCollateral(escrowPDA: PublicKey) {
+      await program.methods
+        .postCollateral(new BN(COLLATERAL))
+        .accounts({
+          seller: seller.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          collateralVault: getCollateralPDA(escrowPDA),
+          sellerTokenAccount: sellerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc();
+    }
+
+    function settleCollateralIx(
+      escrowPDA: PublicKey,
+      recipientTokenAccount: PublicKey
+    ) {
+      return program.methods.settleCollateral().accounts({
+        mint: mint,
+        escrow: escrowPDA,
+        collateralVault: getCollateralPDA(escrowPDA),
+        recipientTokenAccount,
+        seller: seller.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      });
+    }
+
+    async function balance(account: PublicKey) {
+      return Number((await getAccount(connection, account)).amount);
+    }
+
+    it("returns the collateral to the seller after a release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await postCollateral(escrowPDA);
+      expect(await balance(sellerTokenAccount)).to.equal(0);
+
+      await expectError(
+        settleCollateralIx(escrowPDA, sellerTokenAccount).rpc(),
+        "NotTerminal"
+      );
+
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+      await settleCollateralIx(escrowPDA, sellerTokenAccount).rpc();
+
+      expect(await balance(sellerTokenAccount)).to.equal(
+        DEPOSIT_AMOUNT + COLLATERAL
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.collateralAmount.toNumber()).to.equal(0);
+    });
+
+    it("forfeits the collateral to the buyer on a lost dispute", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ arbiter: arbiter.publicKey })
+      );
+      await postCollateral(escrowPDA);
+
+      await program.methods
+        .openDispute()
+        .accounts({ party: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer])
+        .rpc();
+      await program.methods
+        .resolveDispute(false)
+        .accounts({
+          arbiter: arbiter.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          buyerTokenAccount: buyerTokenAccount,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          arbiterTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([arbiter])
+        .rpc();
+
+      await expectError(
+        settleCollateralIx(escrowPDA, sellerTokenAccount).rpc(),
+        "WrongCollateralRecipient"
+      );
+      await settleCollateralIx(escrowPDA, buyerTokenAccount).rpc();
+
+      expect(await balance(buyerTokenAccount)).to.equal(
+        DEPOSIT_AMOUNT * 10 + COLLATERAL
+      );
+    });
+
+    it("rejects settling an escrow without collateral", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+
+      await expectError(
+        settleCollateralIx(escrowPDA, sellerTokenAccount).rpc(),
+        "NoCollateral"
+      );
+    });
+  });
+
+  describe("strict buyer ATA", () => {
+    let nonAta: PublicKey;
+
+    beforeEach(async () => {
+      nonAta = await createAccount(
+        connection,
+        buyer,
+        mint,
+        buyer.publicKey,
+        Keypair.generate()
+      );
+      await mintTo(connection, buyer, mint, nonAta, buyer, DEPOSIT_AMOUNT);
+    });
+
+    it("rejects a non-ATA buyer token account at initialize", async () => {
+      buyerTokenAccount = nonAta;
+      await expectError(
+        initEscrow(defaultOptions({ strictBuyerAta: true })),
+        "NotCanonicalAta"
+      );
+    });
+
+    it("rejects a refund to a non-ATA buyer token account", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ strictBuyerAta: true })
+      );
+
+      const ata = buyerTokenAccount;
+      buyerTokenAccount = nonAta;
+      await expectError(cancelIx(escrowPDA, vaultPDA).rpc(), "NotCanonicalAta");
+
+      buyerTokenAccount = ata;
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("accepts any buyer token account when not strict", async () => {
+      buyerTokenAccount = nonAta;
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+    });
+  });
+
+  describe("cancel fee", () => {
+    let feeTokenAccount: PublicKey;
+
+    beforeEach(async () => {
+      feeTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        feeRecipient.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+    });
+
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    it("keeps the cancel fee and refunds the rest", async () => {
+      await setConfig({ cancelFeeBps: 100 });
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      const before = await getAccount(connection, buyerTokenAccount);
+
+      await cancelIx(escrowPDA, vaultPDA, { feeTokenAccount }).rpc();
+
+      const fee = DEPOSIT_AMOUNT / 100;
+      const after = await getAccount(connection, buyerTokenAccount);
+      expect(Number(after.amount - before.amount)).to.equal(DEPOSIT_AMOUNT - fee);
+      const recipient = await getAccount(connection, feeTokenAccount);
+      expect(Number(recipient.amount)).to.equal(fee);
+    });
+
+    it("requires the fee account when a cancel fee is due", async () => {
+      await setConfig({ cancelFeeBps: 100 });
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(cancelIx(escrowPDA, vaultPDA).rpc(), "MissingFeeAccount");
+    });
+
+    it("rejects a cancel fee above 10000 bps", async () => {
+      await expectError(setConfig({ cancelFeeBps: 10_001 }), "InvalidFee");
+    });
+  });
+
+  describe("escrow groups", () => {
+    const GROUP_ID = Array(32).fill(7);
+
+    type Leg = {
+      escrowPDA: PublicKey;
+      vaultPDA: PublicKey;
+      sellerTokenAccount: PublicKey;
+    };
+
+    // Each leg pays a different seller, so the escrow PDAs differ
+    async function initLeg(overrides: any = {}): Promise<Leg> {
+      seller = Keypair.generate();
+      sellerTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        seller.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const escrow = await initEscrow(
+        defaultOptions({ groupId: GROUP_ID, groupSize: 2, ...overrides })
+      );
+      return { ...escrow, sellerTokenAccount };
+    }
+
+    // `feeSplit` lists each leg's fee split token accounts, in leg order
+    function releaseGroupIx(legs: Leg[], feeSplit: PublicKey[][] = []) {
+      const [mintPolicyPDA] = getMintPolicyPDA();
+      return program.methods
+        .releaseGroup(GROUP_ID)
+        .accounts({
+          config: configPDA,
+          memoProgram: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          legs.flatMap((leg, i) => [
+            { pubkey: leg.escrowPDA, isWritable: true, isSigner: false },
+            { pubkey: leg.vaultPDA, isWritable: true, isSigner: false },
+            { pubkey: mint, isWritable: false, isSigner: false },
+            { pubkey: mintPolicyPDA, isWritable: true, isSigner: false },
+            { pubkey: leg.sellerTokenAccount, isWritable: true, isSigner: false },
+            // No fee due: the program ID stands in for the fee account
+            { pubkey: program.programId, isWritable: false, isSigner: false },
+            { pubkey: buyer.publicKey, isWritable: false, isSigner: true },
+            ...(feeSplit[i] ?? []).map((pubkey) => ({
+              pubkey,
+              isWritable: true,
+              isSigner: false,
+            })),
+          ])
+        )
+        .signers([buyer]);
+    }
+
+    it("releases every escrow of a two-escrow group together", async () => {
+      const legs = [await initLeg(), await initLeg()];
+
+      await releaseGroupIx(legs).rpc();
+
+      for (const leg of legs) {
+        const escrow = await program.account.escrow.fetch(leg.escrowPDA);
+        expect(escrow.state).to.deep.equal({ released: {} });
+        const account = await getAccount(connection, leg.sellerTokenAccount);
+        expect(Number(account.amount)).to.equal(DEPOSIT_AMOUNT);
+      }
+    });
+
+    it("rejects a group with an escrow missing", async () => {
+      const [first] = [await initLeg(), await initLeg()];
+      await expectError(releaseGroupIx([first]).rpc(), "GroupMismatch");
+    });
+
+    it("pays no one unless every escrow is releasable", async () => {
+      const legs = [await initLeg(), await initLeg({ requireDelivery: true })];
+
+      await expectError(releaseGroupIx(legs).rpc(), "GroupNotReady");
+
+      const escrow = await program.account.escrow.fetch(legs[0].escrowPDA);
+      expect(escrow.state).to.deep.equal({ locked: {} });
+    });
+
+    it("blocks releasing a grouped escrow on its own", async () => {
+      await initLeg();
+      const { escrowPDA, vaultPDA } = await initLeg();
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "GroupNotReady");
+    });
+
+    it("rejects a group size out of range", async () => {
+      seller = Keypair.generate();
+      await expectError(
+        initEscrow(defaultOptions({ groupId: GROUP_ID, groupSize: 1 })),
+        "GroupMismatch"
+      );
+    });
+
+    it("rejects a callback on a grouped escrow", async () => {
+      seller = Keypair.generate();
+      await expectError(
+        initEscrow(
+          defaultOptions({
+            groupId: GROUP_ID,
+            groupSize: 2,
+            callbackProgram: TOKEN_PROGRAM_ID,
+          })
+        ),
+        "WrongEscrowKind"
+      );
+    });
+
+    describe("with a fee split", () => {
+      const treasuryWallet = Keypair.generate();
+      const stakingPool = Keypair.generate();
+
+      before(async () => {
+        await setConfig({
+          feeBps: 100,
+          feeSplit: [
+            { account: treasuryWallet.publicKey, bps: 60 },
+            { account: stakingPool.publicKey, bps: 40 },
+          ],
+        });
+      });
+
+      after(async () => {
+        await setConfig();
+      });
+
+      const tokenAccountFor = (owner: PublicKey) =>
+        createAccount(
+          connection,
+          buyer,
+          mint,
+          owner,
+          Keypair.generate(),
+          undefined,
+          TOKEN_PROGRAM_ID
+        );
+
+      it("splits each leg's fee between the recipients", async () => {
+        const legs = [await initLeg(), await initLeg()];
+        const feeSplit = [];
+        for (const _ of legs) {
+          feeSplit.push([
+            await tokenAccountFor(treasuryWallet.publicKey),
+            await tokenAccountFor(stakingPool.publicKey),
+          ]);
+        }
+
+        await releaseGroupIx(legs, feeSplit).rpc();
+
+        // 1% of each deposit, split 60/40
+        const fee = DEPOSIT_AMOUNT / 100;
+        const balance = async (account: PublicKey) =>
+          Number((await getAccount(connection, account)).amount);
+        for (const [i, leg] of legs.entries()) {
+          expect(await balance(leg.sellerTokenAccount)).to.equal(
+            DEPOSIT_AMOUNT - fee
+          );
+          expect(await balance(feeSplit[i][0])).to.equal((fee * 60) / 100);
+          expect(await balance(feeSplit[i][1])).to.equal((fee * 40) / 100);
+        }
+      });
+
+      it("rejects legs without their fee split accounts", async () => {
+        const legs = [await initLeg(), await initLeg()];
+        await expectError(releaseGroupIx(legs).rpc(), "GroupMismatch");
+      });
+    });
+  });
+
+  describe("rug guard", () => {
+    it("blocks release after new supply is minted", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ rugGuard: true })
+      );
+      await mintTo(
+        connection,
+        buyer,
+        mint,
+        buyerTokenAccount,
+        buyer,
+        DEPOSIT_AMOUNT * 10
+      );
+
+      await expectError(releaseIx(escrowPDA, vaultPDA).rpc(), "MintChanged");
+
+      // The buyer can still back out
+      await cancelIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("releases while the mint is unchanged", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ rugGuard: true })
+      );
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+    });
+
+    it("ignores new supply without the guard", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await mintTo(
+        connection,
+        buyer,
+        mint,
+        buyerTokenAccount,
+        buyer,
+        DEPOSIT_AMOUNT * 10
+      );
+      await releaseIx(escrowPDA, vaultPDA).rpc();
+    });
+  });
+
+  describe("relayer rebate", () => {
+    const REBATE = 5_000_000;
+    const relayer = Keypair.generate();
+
+    it("reimburses the relayer on release", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          relayer: relayer.publicKey,
+          relayerRebate: new BN(REBATE),
+        })
+      );
+      const before = await connection.getBalance(relayer.publicKey);
+
+      await releaseIx(escrowPDA, vaultPDA, {
+        relayer: relayer.publicKey,
+      }).rpc();
+
+      const after = await connection.getBalance(relayer.publicKey);
+      expect(after - before).to.equal(REBATE);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.relayerRebate.toNumber()).to.equal(0);
+    });
+
+    it("reimburses the relayer on cancel", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          relayer: relayer.publicKey,
+          relayerRebate: new BN(REBATE),
+        })
+      );
+      const before = await connection.getBalance(relayer.publicKey);
+
+      await cancelIx(escrowPDA, vaultPDA, {
+        relayer: relayer.publicKey,
+      }).rpc();
+
+      const after = await connection.getBalance(relayer.publicKey);
+      expect(after - before).to.equal(REBATE);
+    });
+
+    it("ignores the rebate without a relayer", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ relayerRebate: new BN(REBATE) })
+      );
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.relayerRebate.toNumber()).to.equal(0);
+    });
+  });
+
+  describe("rate limit", () => {
+    // Both deliveries land in one transaction, hence in the same slot
+    async function submitTwice(escrowPDA: PublicKey) {
+      const second = await program.methods
+        .submitDelivery(Array(32).fill(2))
+        .accounts({ seller: seller.publicKey, escrow: escrowPDA })
+        .instruction();
+      return program.methods
+        .submitDelivery(Array(32).fill(1))
+        .accounts({ seller: seller.publicKey, escrow: escrowPDA })
+        .postInstructions([second])
+        .signers([seller])
+        .rpc();
+    }
+
+    it("rejects a second action in the same slot", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ minActionGapSlots: new BN(1) })
+      );
+      await expectError(submitTwice(escrowPDA), "RateLimited");
+    });
+
+    it("allows back-to-back actions without a gap", async () => {
+      const { escrowPDA } = await initEscrow();
+      await submitTwice(escrowPDA);
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deliveryHash).to.deep.equal(Array(32).fill(2));
+    });
+  });
+
+  describe("escrow templates", () => {
+    const TEMPLATE_ID = new BN(1);
+    const arbiter = Keypair.generate();
+    const [templatePDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("template"),
+        provider.wallet.publicKey.toBuffer(),
+        TEMPLATE_ID.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+
+    before(async () => {
+      await program.methods
+        .createTemplate(TEMPLATE_ID, {
+          kind: { standard: {} },
+          arbiter: arbiter.publicKey,
+          arbiterFeeBps: 50,
+          deadlineSecs: 3600,
+          requireSellerConfirm: false,
+          noticeSecs: 600,
+          expireBuyerBps: null,
+          disputeBufferSecs: 0,
+          autoClose: true,
+          maxExtensions: null,
+          referrer: null,
+          referrerBps: 0,
+        })
+        .accounts({
+          authority: provider.wallet.publicKey,
+          template: templatePDA,
+          approvedSeller: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    });
+
+    function initFromTemplateIx(template: PublicKey | null) {
+      const [escrowPDA] = getEscrowPDA();
+      return program.methods
+        .initializeFromTemplate(new BN(DEPOSIT_AMOUNT))
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          mint: mint,
+          buyerTokenAccount: buyerTokenAccount,
+          escrow: escrowPDA,
+          vault: getVaultPDA(escrowPDA)[0],
+          config: configPDA,
+          treasury: null,
+          approvedBuyer: null,
+          template,
+          sellerTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer]);
+    }
+
+    it("creates an escrow with the template's parameters", async () => {
+      const before = Math.floor(Date.now() / 1000);
+      await initFromTemplateIx(templatePDA).rpc();
+
+      const escrow = await program.account.escrow.fetch(getEscrowPDA()[0]);
+      expect(escrow.amount.toNumber()).to.equal(DEPOSIT_AMOUNT);
+      expect(escrow.arbiter.toBase58()).to.equal(arbiter.publicKey.toBase58());
+      expect(escrow.arbiterFeeBps).to.equal(50);
+      expect(escrow.noticeSecs).to.equal(600);
+      expect(escrow.autoClose).to.be.true;
+      expect(escrow.deadline.toNumber()).to.be.closeTo(before + 3600, 60);
+    });
+
+    it("requires the template account", async () => {
+      await expectError(initFromTemplateIx(null).rpc(), "TemplateNotFound");
+    });
+  });
+
+  describe("verify_preimage", () => {
+    const secret = Buffer.from("htlc secret");
+    const hashLock = Array.from(createHash("sha256").update(secret).digest());
+
+    function verify(escrowPDA: PublicKey, preimage: Buffer): Promise<boolean> {
+      return program.methods
+        .verifyPreimage(preimage)
+        .accounts({ escrow: escrowPDA })
+        .view();
+    }
+
+    it("accepts the preimage of the hash lock", async () => {
+      const { escrowPDA } = await initEscrow(defaultOptions({ hashLock }));
+      expect(await verify(escrowPDA, secret)).to.be.true;
+    });
+
+    it("rejects any other preimage", async () => {
+      const { escrowPDA } = await initEscrow(defaultOptions({ hashLock }));
+      expect(await verify(escrowPDA, Buffer.from("wrong secret"))).to.be.false;
+    });
+
+    it("rejects every preimage without a hash lock", async () => {
+      const { escrowPDA } = await initEscrow();
+      expect(await verify(escrowPDA, secret)).to.be.false;
+    });
+  });
+
+  describe("claim", () => {
+    function claimIx(escrowPDA: PublicKey, vaultPDA: PublicKey) {
+      return program.methods
+        .claim()
+        .accounts({
+          seller: seller.publicKey,
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller]);
+    }
+
+    // Drains the buyer's wallet, as if they closed it
+    async function closeBuyer() {
+      const balance = await connection.getBalance(buyer.publicKey);
+      const tx = new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: buyer.publicKey,
+          toPubkey: provider.wallet.publicKey,
+          lamports: balance,
+        })
+      );
+      await provider.sendAndConfirm(tx, [buyer]);
+    }
+
+    it("pays the seller once the buyer is closed and the deadline passed", async () => {
+      const deadline = futureDeadline(3);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({
+          approvers: [Keypair.generate().publicKey],
+          approverWeights: [1],
+          approvalThreshold: 1,
+        }),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await closeBuyer();
+      await waitForDeadline(deadline);
+
+      await claimIx(escrowPDA, vaultPDA).rpc();
+
+      const sellerAccount = await getAccount(connection, sellerTokenAccount);
+      expect(Number(sellerAccount.amount)).to.equal(DEPOSIT_AMOUNT);
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+    });
+
+    it("rejects a claim while the buyer is active", async () => {
+      const deadline = futureDeadline(3);
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+      await waitForDeadline(deadline);
+
+      await expectError(claimIx(escrowPDA, vaultPDA).rpc(), "BuyerStillActive");
+    });
+
+    it("rejects a claim before the deadline", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await closeBuyer();
+
+      await expectError(claimIx(escrowPDA, vaultPDA).rpc(), "NotExpired");
+    });
+  });
+
+  describe("tags", () => {
+    function tag(label: string): number[] {
+      return Array.from(Buffer.from(label.padEnd(16, "\0")));
+    }
+
+    function addTagIx(escrowPDA: PublicKey, label: string) {
+      return program.methods
+        .addTag(tag(label))
+        .accounts({ buyer: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer]);
+    }
+
+    it("adds and removes tags", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ tags: [tag("auction")] })
+      );
+      await addTagIx(escrowPDA, "priority").rpc();
+      // Tags are a set
+      await addTagIx(escrowPDA, "priority").rpc();
+
+      let escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.tags).to.deep.equal([tag("auction"), tag("priority")]);
+
+      await program.methods
+        .removeTag(tag("auction"))
+        .accounts({ buyer: buyer.publicKey, escrow: escrowPDA })
+        .signers([buyer])
+        .rpc();
+
+      escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.tags).to.deep.equal([tag("priority")]);
+    });
+
+    it("rejects more than MAX_TAGS tags", async () => {
+      const labels = Array.from({ length: MAX_TAGS }, (_, i) => `tag${i}`);
+      await expectError(
+        initEscrow(defaultOptions({ tags: [...labels, "extra"].map(tag) })),
+        "TooManyTags"
+      );
+
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ tags: labels.map(tag) })
+      );
+      await expectError(addTagIx(escrowPDA, "extra").rpc(), "TooManyTags");
+    });
+  });
+
+  describe("token program pinning", () => {
+    it("stores the token program at initialize", async () => {
+      const { escrowPDA } = await initEscrow();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.tokenProgram.toBase58()).to.equal(TOKEN_PROGRAM_ID.toBase58());
+    });
+
+    it("rejects a release with another token program", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, {
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        }).rpc(),
+        "TokenProgramMismatch"
+      );
+    });
+
+    it("rejects a cancel with another token program", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow();
+      await expectError(
+        cancelIx(escrowPDA, vaultPDA, {
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        }).rpc(),
+        "TokenProgramMismatch"
+      );
+    });
+  });
+
+  describe("amend", () => {
+    function amendIx(escrowPDA: PublicKey, changes: any) {
+      return program.methods
+        .amend({
+          deadline: null,
+          arbiterFeeBps: null,
+          termsHash: null,
+          ...changes,
+        })
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          escrow: escrowPDA,
+          config: configPDA,
+        });
+    }
+
+    it("amends the deadline and arbiter fee with both signatures", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ arbiter: Keypair.generate().publicKey })
+      );
+      const deadline = futureDeadline(7200);
+
+      await amendIx(escrowPDA, { deadline, arbiterFeeBps: 250 })
+        .signers([buyer, seller])
+        .rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deadline.toNumber()).to.equal(deadline.toNumber());
+      expect(escrow.arbiterFeeBps).to.equal(250);
+    });
+
+    it("rejects an amendment signed by one party", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(
+        amendIx(escrowPDA, { deadline: futureDeadline(7200) })
+          .signers([buyer])
+          .rpc(),
+        "UnauthorizedAmendment"
+      );
+    });
+
+    it("validates changes like initialize", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(
+        amendIx(escrowPDA, { arbiterFeeBps: 100 })
+          .signers([buyer, seller])
+          .rpc(),
+        "InvalidFee"
+      );
+      await expectError(
+        amendIx(escrowPDA, { deadline: futureDeadline(-60) })
+          .signers([buyer, seller])
+          .rpc(),
+        "DeadlineInPast"
+      );
+    });
+  });
+
+  describe("overlapping destinations", () => {
+    afterEach(async () => {
+      await setConfig();
+    });
+
+    it("pays both amounts when the seller is also the fee recipient", async () => {
+      // The seller's token account doubles as the fee account
+      await setConfig({ feeBps: 250, feeRecipient: seller.publicKey });
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      await releaseIx(escrowPDA, vaultPDA, {
+        feeTokenAccount: sellerTokenAccount,
+      }).rpc();
+
+      const received = await getAccount(connection, sellerTokenAccount);
+      expect(Number(received.amount)).to.equal(DEPOSIT_AMOUNT);
+      const vault = await getAccount(connection, vaultPDA);
+      expect(Number(vault.amount)).to.equal(0);
+    });
+
+    it("rejects the overlap when the config requires distinct accounts", async () => {
+      await setConfig({
+        feeBps: 250,
+        feeRecipient: seller.publicKey,
+        rejectOverlappingDestinations: true,
+      });
+      const { escrowPDA, vaultPDA } = await initEscrow();
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, {
+          feeTokenAccount: sellerTokenAccount,
+        }).rpc(),
+        "OverlappingDestinations"
+      );
+    });
+  });
+
+  describe("extend_by", () => {
+    function extendIx(escrowPDA: PublicKey, additionalSecs: number) {
+      return program.methods
+        .extendBy(new BN(additionalSecs))
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: escrowPDA,
+          config: configPDA,
+        })
+        .signers([buyer]);
+    }
+
+    it("moves the deadline back by the given duration", async () => {
+      const deadline = futureDeadline(3600);
+      const { escrowPDA } = await initEscrow(
+        defaultOptions(),
+        DEPOSIT_AMOUNT,
+        deadline
+      );
+
+      await extendIx(escrowPDA, 86_400).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.deadline.toNumber()).to.equal(
+        deadline.toNumber() + 86_400
+      );
+    });
+
+    it("counts extensions against max_extensions", async () => {
+      const { escrowPDA } = await initEscrow(
+        defaultOptions({ maxExtensions: 1 })
+      );
+
+      await extendIx(escrowPDA, 60).rpc();
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.extensionCount).to.equal(1);
+
+      await expectError(extendIx(escrowPDA, 60).rpc(), "TooManyExtensions");
+    });
+
+    it("rejects a non-positive extension", async () => {
+      const { escrowPDA } = await initEscrow();
+      await expectError(extendIx(escrowPDA, 0).rpc(), "DeadlineNotExtended");
+      await expectError(
+        extendIx(escrowPDA, -60).rpc(),
+        "DeadlineNotExtended"
+      );
+    });
+
+    it("rejects extending past the maximum duration", async () => {
+      const { escrowPDA } = await initEscrow();
+      const config = await program.account.config.fetch(configPDA);
+      await expectError(
+        extendIx(escrowPDA, config.maxDeadlineSecs).rpc(),
+        "DeadlineTooFar"
+      );
+    });
+  });
+
+  describe("release_with_signature", () => {
+    const oracle = Keypair.generate();
+
+    // The escrow, the release's nonce, then whatever the oracle attests to
+    function approval(escrowPDA: PublicKey, nonce = 1) {
+      return Buffer.concat([
+        escrowPDA.toBuffer(),
+        new BN(nonce).toArrayLike(Buffer, "le", 8),
+        Buffer.from("delivered"),
+      ]);
+    }
+
+    function oracleSignature(signer: Keypair, msg: Buffer) {
+      // web3.js lays the instruction out as header, key, signature, message
+      const ix = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message: msg,
+      });
+      return { ix, signature: Array.from(ix.data.subarray(48, 112)) };
+    }
+
+    function releaseWithSignatureIx(
+      escrowPDA: PublicKey,
+      vaultPDA: PublicKey,
+      signer: Keypair,
+      message = approval(escrowPDA),
+      signatureOverride?: number[]
+    ) {
+      const { ix, signature } = oracleSignature(signer, message);
+      return program.methods
+        .releaseWithSignature(message, signatureOverride ?? signature)
+        .accounts({
+          buyer: buyer.publicKey,
+          mint: mint,
+          escrow: escrowPDA,
+          vault: vaultPDA,
+          sellerTokenAccount: sellerTokenAccount,
+          config: configPDA,
+          feeTokenAccount: null,
+          callbackProgram: null,
+          memoProgram: null,
+          settleAuthority: null,
+          oracle: null,
+          buyerTokenAccount: null,
+          referrerTokenAccount: null,
+          seller: null,
+          payer: null,
+          associatedTokenProgram: null,
+          systemProgram: null,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          unwrapAccount: null,
+          receipt: null,
+          relayer: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([ix])
+        .signers([buyer]);
+    }
+
+    it("releases with the oracle's signature", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+
+      await releaseWithSignatureIx(escrowPDA, vaultPDA, oracle).rpc();
+
+      const escrow = await program.account.escrow.fetch(escrowPDA);
+      expect(escrow.state).to.deep.equal({ released: {} });
+      const received = await getAccount(connection, sellerTokenAccount);
+      expect(Number(received.amount)).to.equal(DEPOSIT_AMOUNT);
+    });
+
+    it("rejects a signature by another key", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+
+      await expectError(
+        releaseWithSignatureIx(escrowPDA, vaultPDA, Keypair.generate()).rpc(),
+        "InvalidSignature"
+      );
+    });
+
+    it("rejects a signature other than the verified one", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+      const forged = oracleSignature(oracle, Buffer.from("not delivered"));
+
+      await expectError(
+        releaseWithSignatureIx(
+          escrowPDA,
+          vaultPDA,
+          oracle,
+          undefined,
+          forged.signature
+        ).rpc(),
+        "InvalidSignature"
+      );
+    });
+
+    it("rejects a signature meant for another escrow", async () => {
+      const first = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+      seller = Keypair.generate();
+      sellerTokenAccount = await createAccount(
+        connection,
+        buyer,
+        mint,
+        seller.publicKey,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+
+      await expectError(
+        releaseWithSignatureIx(
+          escrowPDA,
+          vaultPDA,
+          oracle,
+          approval(first.escrowPDA)
+        ).rpc(),
+        "InvalidSignature"
+      );
+    });
+
+    it("rejects a signature for a stale nonce", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+
+      await expectError(
+        releaseWithSignatureIx(
+          escrowPDA,
+          vaultPDA,
+          oracle,
+          approval(escrowPDA, 0)
+        ).rpc(),
+        "InvalidSignature"
+      );
+    });
+
+    it("refuses a plain release of a gated escrow", async () => {
+      const { escrowPDA, vaultPDA } = await initEscrow(
+        defaultOptions({ oraclePubkey: oracle.publicKey })
+      );
+
+      await expectError(
+        releaseIx(escrowPDA, vaultPDA, {
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        }).rpc(),
+        "InvalidSignature"
+      );
+    });
+  });
+
+  describe("error codes", () => {
+    // SDKs hardcode these numbers; a change here is a breaking change
+    const EXPECTED_CODES: Record<string, number> = {
+      ZeroAmount: 6000,
+      DeadlineInPast: 6001,
+      DeadlineTooFar: 6002,
+      NotLocked: 6003,
+      NotNativeMint: 6004,
+      WrongEscrowKind: 6005,
+      InvalidFee: 6006,
+      MissingFeeAccount: 6007,
+      MathOverflow: 6008,
+      Unauthorized: 6009,
+      InvalidArbiter: 6010,
+      NoArbiter: 6011,
+      DisputeActive: 6012,
+      NoDispute: 6013,
+      DecimalsMismatch: 6014,
+      TooManyMilestones: 6015,
+      MilestoneSumMismatch: 6016,
+      InvalidMilestone: 6017,
+      MilestoneAlreadyReleased: 6018,
+      SellerNotConfirmed: 6019,
+      MissingTreasury: 6020,
+      InsufficientLamports: 6021,
+      NotExpired: 6022,
+      CallbackFailed: 6023,
+      CannotIncrease: 6024,
+      MissingMemoProgram: 6025,
+      AlreadyReleased: 6026,
+      AlreadyCancelled: 6027,
+      NoticePeriodActive: 6028,
+      NotAnArbiter: 6029,
+      NoMajority: 6030,
+      InvalidSplit: 6031,
+      BumpMismatch: 6032,
+      UnauthorizedProgram: 6033,
+      AmountExceedsEscrow: 6034,
+      TooManyReleases: 6035,
+      BuyerNotApproved: 6036,
+      RefundNotAllowed: 6037,
+      StaleOracle: 6038,
+      OraclePriceError: 6039,
+      MissingBuyerTokenAccount: 6040,
+      UnauthorizedCaller: 6041,
+      TooManyAssets: 6042,
+      InvalidBasketAccounts: 6043,
+      NotRentExempt: 6044,
+      UnauthorizedSettler: 6045,
+      InvalidVaultIndex: 6046,
+      SellerAccountMissing: 6047,
+      NoExcess: 6048,
+      AlreadyRefunded: 6049,
+      ScheduleSumMismatch: 6050,
+      InvalidSchedule: 6051,
+      TooManyInstallments: 6052,
+      NothingMatured: 6053,
+      VaultNotEmpty: 6054,
+      TooManyExtensions: 6055,
+      MissingReferrerAccount: 6056,
+      UnexpectedInstruction: 6057,
+      VaultAuthorityChanged: 6058,
+      InvalidApprovers: 6059,
+      ThresholdNotMet: 6060,
+      AmountExceedsLocked: 6061,
+      NotTerminal: 6062,
+      EscrowFrozen: 6063,
+      NotFrozen: 6064,
+      AtaCreationFailed: 6065,
+      TvlCapExceeded: 6066,
+      MissingReceiptAccount: 6067,
+      NoDelivery: 6068,
+      InvalidBatch: 6069,
+      DeadlineTooSoon: 6070,
+      TooManyContributors: 6071,
+      InvalidContributorAccounts: 6072,
+      InvalidSellerAccount: 6073,
+      FeeSplitMismatch: 6074,
+      DeliverySubmitted: 6075,
+      UnauthorizedBuyer: 6076,
+      ReturnDataTooLarge: 6077,
+      ObjectionRaised: 6078,
+      ObjectionWindowClosed: 6079,
+      StaleNonce: 6080,
+      SlippageExceeded: 6081,
+      InvalidSwapAccounts: 6082,
+      InvalidDestination: 6083,
+      NoCollateral: 6084,
+      NotCanonicalAta: 6085,
+      GroupMismatch: 6086,
+      GroupNotReady: 6087,
+      MintChanged: 6088,
+      DurationTooLong: 6089,
+      MissingArbiterAccount: 6090,
+      MissingUnwrapAccount: 6091,
+      ReleaseBelowMinimum: 6092,
+      ApprovalWindowExpired: 6093,
+      MintMismatch: 6094,
+      InsufficientRebate: 6095,
+      RateLimited: 6096,
+      TemplateNotFound: 6097,
+      BuyerStillActive: 6098,
+      TooManyTags: 6099,
+      TokenProgramMismatch: 6100,
+      UnauthorizedAmendment: 6101,
+      SellerNotApproved: 6102,
+      OverlappingDestinations: 6103,
+      DeadlineNotExtended: 6104,
+      InvalidSignature: 6105,
+      DailyReleaseLimit: 6106,
+      AlreadyVoted: 6107,
+      WrongCollateralRecipient: 6108,
+      MissingVault: 6109,
+    };
+
+    it("keeps every error at its pinned code", () => {
+      const codes: Record<string, number> = {};
+      for (const error of program.idl.errors ?? []) {
+        const name = error.name.charAt(0).toUpperCase() + error.name.slice(1);
+        codes[name] = error.code;
       }
+      expect(codes).to.deep.equal(EXPECTED_CODES);
     });
   });
 });